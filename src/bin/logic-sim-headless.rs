@@ -0,0 +1,9 @@
+//! Headless runner without the board editor, it builds without the `gui`
+//! feature and takes the arguments of `logic-sim --headless`.
+
+use logic_sim::headless;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(headless::run(&args));
+}
@@ -0,0 +1,1432 @@
+//! Gates placed on a board, with positions of the gates and of the wires
+//! between them, on top of a [`LogicSimulation`]. Only available with the
+//! `gui` feature.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use macroquad::prelude::{Rect, Vec2};
+
+use crate::{
+    board_file::{self, BoardFile, FileError, GateEntry, WireEntry},
+    bus::Bus,
+    gates::Gate,
+    history::{Edit, GateSnapshot, History, WireSnapshot},
+    id::{ConnectionId, GateId, InputPin, OutputPin},
+    logic_simulation::{Diagnostic, LogicSimulation, SimulationError, SimulationMode},
+    net::Resolution,
+    routing,
+    subcircuit::{Circuit, SubcircuitMode},
+    vcd,
+    waveform::{Probe, Waveforms},
+};
+
+/// Horizontal distance between the selected gates and the ports added for
+/// wires leaving a packaged selection.
+const PORT_DISTANCE: f32 = 100.;
+
+pub type GateItem<'a> = (GateId, &'a mut Vec2, &'a str, (&'a [Bus], &'a [Bus]), bool);
+
+/// Connection with the corners of its route, its bend points and the value
+/// of its output.
+pub type WireItem<'a> = (ConnectionId, &'a [Vec2], &'a [Vec2], Bus);
+
+struct Wire {
+    output: OutputPin,
+    input: InputPin,
+    /// Positions of the pins relative to their gates
+    offsets: (Vec2, Vec2),
+    /// Bend points placed by the user, the route passes through them in
+    /// order
+    waypoints: Vec<Vec2>,
+    /// Corners of the route from the output to the input, empty until the
+    /// wire is routed
+    route: Vec<Vec2>,
+}
+
+/// Distance between neighbouring pins of a gate.
+pub const PIN_PITCH: f32 = 25.;
+
+/// Coarsest grid all pins of a gate lie on once its first pin is on the grid,
+/// finer grids divide it.
+pub const GRID_PITCH: f32 = PIN_PITCH / 2.;
+
+/// Size of the square body of a gate with given number of inputs and outputs,
+/// a multiple of the pitch of the pins.
+pub fn gate_size(inputs: usize, outputs: usize) -> f32 {
+    usize::max(inputs, outputs).max(1) as f32 * PIN_PITCH
+}
+
+/// Position of a pin relative to its gate, inputs are spread along the left
+/// side of the gate and outputs along the right side, centered and one pitch
+/// apart.
+pub fn pin_offset(size: f32, pins: usize, index: usize, output: bool) -> Vec2 {
+    let t = size / 2. + (index as f32 - (pins as f32 - 1.) / 2.) * PIN_PITCH;
+    Vec2::new(if output { size } else { 0. }, t)
+}
+
+impl Wire {
+    fn snapshot(&self, connection: ConnectionId) -> WireSnapshot {
+        WireSnapshot {
+            connection,
+            output: self.output,
+            input: self.input,
+            waypoints: self.waypoints.clone(),
+        }
+    }
+}
+
+/// Rounds a position to the nearest point of a grid with given pitch.
+pub fn snap(pos: Vec2, pitch: f32) -> Vec2 {
+    (pos / pitch).round() * pitch
+}
+
+/// Lines a dragged gate lines up with, a vertical line at `x` and a
+/// horizontal line at `y`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Guides {
+    pub x: Option<f32>,
+    pub y: Option<f32>,
+}
+
+pub struct BoardSimulation {
+    sim: LogicSimulation,
+    gates: BTreeMap<GateId, Vec2>,
+    connections: BTreeMap<ConnectionId, Wire>,
+    /// Library of subcircuits that can be added to the board
+    circuits: Vec<Circuit>,
+    subcircuits: SubcircuitMode,
+    history: History,
+    /// Areas of the gates the routes of the wires were computed for
+    routed: BTreeMap<GateId, Rect>,
+}
+
+impl Default for BoardSimulation {
+    fn default() -> Self {
+        BoardSimulation::new()
+    }
+}
+
+impl BoardSimulation {
+    pub fn new() -> BoardSimulation {
+        BoardSimulation {
+            sim: LogicSimulation::new(),
+            gates: BTreeMap::new(),
+            connections: BTreeMap::new(),
+            circuits: Vec::new(),
+            subcircuits: SubcircuitMode::default(),
+            history: History::default(),
+            routed: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a board from a loaded file.
+    pub fn from_file(file: &BoardFile) -> Result<BoardSimulation, FileError> {
+        let mut board = BoardSimulation {
+            circuits: file.circuits.clone(),
+            subcircuits: file.subcircuits,
+            ..BoardSimulation::new()
+        };
+        board.sim.set_mode(file.mode);
+        board.sim.set_default_resolution(file.default_resolution);
+
+        // gates get new ids, wires and nets refer to the ids in the file
+        let (ids, connections) = file.build(&mut board.sim, &file.circuits, file.subcircuits)?;
+        for gate in &file.gates {
+            board
+                .gates
+                .insert(ids[&gate.id], Vec2::new(gate.pos.0, gate.pos.1));
+        }
+
+        for (entry, connection) in file.wires.iter().zip(connections) {
+            let output = ids[&entry.output.0].output(entry.output.1);
+            let input = ids[&entry.input.0].input(entry.input.1);
+            let mut wire = board.wire(output, input)?;
+            wire.waypoints = entry
+                .waypoints
+                .iter()
+                .map(|(x, y)| Vec2::new(*x, *y))
+                .collect();
+            board.connections.insert(connection, wire);
+        }
+        board.route_wires();
+
+        Ok(board)
+    }
+
+    /// Unrouted wire between two pins.
+    fn wire(&self, output: OutputPin, input: InputPin) -> Result<Wire, SimulationError> {
+        let offset = |gate: GateId, pin: usize, output: bool| {
+            let (inputs, outputs) = self.sim.get_gate_state(gate)?;
+            let pins = if output { outputs.len() } else { inputs.len() };
+            let size = gate_size(inputs.len(), outputs.len());
+            Ok(pin_offset(size, pins, pin, output))
+        };
+
+        Ok(Wire {
+            output,
+            input,
+            offsets: (
+                offset(output.gate, output.index, true)?,
+                offset(input.gate, input.index, false)?,
+            ),
+            waypoints: Vec::new(),
+            route: Vec::new(),
+        })
+    }
+
+    /// Describes the board for saving it to a file, gates are numbered in
+    /// the order of their ids.
+    pub fn to_file(&self) -> BoardFile {
+        self.copy(&self.gates.keys().copied().collect())
+    }
+
+    /// Names of the subcircuits that can be added to the board.
+    pub fn circuit_names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.circuits.iter().map(|circuit| circuit.name.as_str())
+    }
+
+    pub fn add_circuit(&mut self, name: &str, pos: Vec2) -> Result<(), FileError> {
+        let gate_id =
+            board_file::add_gate(&mut self.sim, &self.circuits, self.subcircuits, name, &[])?;
+        self.gates.insert(gate_id, pos);
+        self.record_gate(gate_id, Edit::AddGate);
+        Ok(())
+    }
+
+    /// Turns the whole board into a subcircuit with given name, its `IN`
+    /// and `OUT` ports become the pins of the subcircuit. The board is
+    /// cleared, the library of subcircuits is kept. Parts of the board are
+    /// packaged by [`BoardSimulation::package_selection`].
+    pub fn package(&mut self, name: &str) -> Result<(), FileError> {
+        Circuit::check_name(name, &self.circuits)?;
+        let board = BoardFile {
+            circuits: Vec::new(),
+            ..self.to_file()
+        };
+
+        let mut circuits = std::mem::take(&mut self.circuits);
+        circuits.push(Circuit {
+            name: name.to_string(),
+            board,
+        });
+        self.rebuild(BoardFile {
+            mode: self.sim.mode(),
+            default_resolution: self.sim.default_resolution(),
+            subcircuits: self.subcircuits,
+            circuits,
+            ..BoardFile::default()
+        })
+    }
+
+    /// Turns the selected gates into a subcircuit with given name and
+    /// replaces them by an instance of it, as a single edit. Wires between
+    /// the selection and the rest of the board go through new `IN` and `OUT`
+    /// ports of the subcircuit, one for each output they come from. Returns
+    /// the id of the instance.
+    pub fn package_selection(
+        &mut self,
+        name: &str,
+        gates: &BTreeSet<GateId>,
+    ) -> Result<GateId, FileError> {
+        Circuit::check_name(name, &self.circuits)?;
+        if gates.is_empty() {
+            return Err(FileError("no gates to package".to_string()));
+        }
+        let mut rects = Vec::with_capacity(gates.len());
+        for gate_id in gates {
+            let pos = self.gate_pos(*gate_id)?;
+            rects.extend(self.gate_rect(*gate_id, pos));
+        }
+        let left = rects
+            .iter()
+            .map(|rect| rect.left())
+            .fold(f32::MAX, f32::min);
+        let right = rects
+            .iter()
+            .map(|rect| rect.right())
+            .fold(f32::MIN, f32::max);
+        let top = rects.iter().map(|rect| rect.top()).fold(f32::MAX, f32::min);
+
+        // outputs outside driving selected inputs, and selected outputs
+        // driving inputs outside
+        let mut inputs: BTreeMap<OutputPin, Vec<InputPin>> = BTreeMap::new();
+        let mut outputs: BTreeMap<OutputPin, Vec<InputPin>> = BTreeMap::new();
+        for wire in self.connections.values() {
+            match (
+                gates.contains(&wire.output.gate),
+                gates.contains(&wire.input.gate),
+            ) {
+                (false, true) => inputs.entry(wire.output).or_default().push(wire.input),
+                (true, false) => outputs.entry(wire.output).or_default().push(wire.input),
+                _ => {}
+            }
+        }
+
+        // gates of the copy are numbered in the order of their ids, ports
+        // are placed next to the first pin they connect to
+        let mut board = BoardFile {
+            circuits: Vec::new(),
+            ..self.copy(gates)
+        };
+        let file_id = |gate_id: GateId| gates.iter().position(|id| *id == gate_id).unwrap();
+        let mut ports = Vec::new();
+        for (output, targets) in &inputs {
+            let width = self.sim.get_gate_state(output.gate)?.1[output.index].width();
+            let wire = self.wire(*output, targets[0])?;
+            let y = self.gates[&targets[0].gate].y + wire.offsets.1.y - gate_size(0, 1) / 2.;
+            ports.push(GateEntry {
+                id: board.gates.len() + ports.len(),
+                kind: "IN".to_string(),
+                pos: (left - PORT_DISTANCE, y),
+                delay: 1,
+                params: vec![width as u64],
+            });
+            for input in targets {
+                board.wires.push(WireEntry {
+                    output: (ports.last().unwrap().id, 0),
+                    input: (file_id(input.gate), input.index),
+                    waypoints: Vec::new(),
+                });
+            }
+        }
+        for (output, targets) in &outputs {
+            let width = self.sim.get_gate_state(output.gate)?.1[output.index].width();
+            let wire = self.wire(*output, targets[0])?;
+            let y = self.gates[&output.gate].y + wire.offsets.0.y - gate_size(1, 0) / 2.;
+            ports.push(GateEntry {
+                id: board.gates.len() + ports.len(),
+                kind: "OUT".to_string(),
+                pos: (right + PORT_DISTANCE, y),
+                delay: 1,
+                params: vec![width as u64],
+            });
+            board.wires.push(WireEntry {
+                output: (file_id(output.gate), output.index),
+                input: (ports.last().unwrap().id, 0),
+                waypoints: Vec::new(),
+            });
+        }
+        let first_port = board.gates.len();
+        board.gates.extend(ports);
+
+        // pins of the instance are the ports of the subcircuit top to bottom
+        let (input_ports, output_ports) = board.ports();
+        let pin = |ports: &[usize], port: usize| ports.iter().position(|id| *id == port).unwrap();
+        let input_pins: Vec<usize> = (first_port..)
+            .take(inputs.len())
+            .map(|port| pin(&input_ports, port))
+            .collect();
+        let output_pins: Vec<usize> = (first_port + inputs.len()..)
+            .take(outputs.len())
+            .map(|port| pin(&output_ports, port))
+            .collect();
+        self.circuits.push(Circuit {
+            name: name.to_string(),
+            board,
+        });
+
+        let replace = |board: &mut BoardSimulation, edits: &mut Vec<Edit>| {
+            for gate_id in gates {
+                edits.push(Edit::RemoveGate(board.snapshot(*gate_id)?));
+                board.delete_gate(*gate_id)?;
+            }
+            let instance = board_file::add_gate(
+                &mut board.sim,
+                &board.circuits,
+                board.subcircuits,
+                name,
+                &[],
+            )?;
+            board.gates.insert(instance, Vec2::new(left, top));
+            edits.push(Edit::AddGate(board.snapshot(instance)?));
+
+            let wires = inputs
+                .iter()
+                .zip(&input_pins)
+                .map(|((output, _), pin)| (*output, instance.input(*pin)))
+                .chain(
+                    outputs
+                        .values()
+                        .zip(&output_pins)
+                        .flat_map(|(targets, pin)| {
+                            targets
+                                .iter()
+                                .map(move |input| (instance.output(*pin), *input))
+                        }),
+                );
+            for (output, input) in wires {
+                let connection = board.connect(output, input, Vec::new())?;
+                edits.push(Edit::AddConnection(
+                    board.wire_ref(connection)?.snapshot(connection),
+                ));
+            }
+            Ok::<_, FileError>(instance)
+        };
+        let mut edits = Vec::new();
+        let packaged = replace(self, &mut edits);
+        if packaged.is_err() {
+            self.revert(&Edit::Batch(edits))?;
+            self.circuits.pop();
+        } else {
+            self.history.push(Edit::Batch(edits));
+        }
+        packaged
+    }
+
+    /// Adds a saved board to the library as a subcircuit with given name,
+    /// together with the subcircuits it uses.
+    pub fn import(&mut self, name: &str, file: BoardFile) -> Result<(), FileError> {
+        let mut circuits = self.circuits.clone();
+        for circuit in &file.circuits {
+            if !circuits.contains(circuit) {
+                Circuit::check_name(&circuit.name, &circuits)?;
+                circuits.push(circuit.clone());
+            }
+        }
+
+        Circuit::check_name(name, &circuits)?;
+        circuits.push(Circuit {
+            name: name.to_string(),
+            board: BoardFile {
+                circuits: Vec::new(),
+                ..file
+            },
+        });
+        self.rebuild(BoardFile {
+            circuits,
+            ..self.to_file()
+        })
+    }
+
+    pub fn subcircuit_mode(&self) -> SubcircuitMode {
+        self.subcircuits
+    }
+
+    /// Changes how subcircuits are simulated, the board is built again and
+    /// starts from its initial state.
+    pub fn set_subcircuit_mode(&mut self, mode: SubcircuitMode) {
+        if self.subcircuits != mode {
+            let file = BoardFile {
+                subcircuits: mode,
+                ..self.to_file()
+            };
+            self.rebuild(file)
+                .expect("board can be built from its own file");
+        }
+    }
+
+    /// Builds the board again from a file, the edit history is cleared as
+    /// all gates get new ids.
+    fn rebuild(&mut self, file: BoardFile) -> Result<(), FileError> {
+        let mut history = std::mem::take(&mut self.history);
+        *self = BoardSimulation::from_file(&file)?;
+        history.clear();
+        self.history = history;
+        Ok(())
+    }
+
+    /// Adds a gate at given position, returns the id of the gate.
+    pub fn add_gate<const INPUTS: usize, const OUTPUTS: usize>(
+        &mut self,
+        gate: impl Gate<INPUTS, OUTPUTS> + 'static,
+        pos: Vec2,
+    ) -> GateId {
+        let gate_id = self.sim.add_gate(gate);
+        self.gates.insert(gate_id, pos);
+        self.record_gate(gate_id, Edit::AddGate);
+        gate_id
+    }
+
+    /// Records the addition of a gate, it has no connections yet.
+    fn record_gate(&mut self, gate_id: GateId, edit: fn(GateSnapshot) -> Edit) {
+        if let Ok(snapshot) = self.snapshot(gate_id) {
+            self.history.push(edit(snapshot));
+        }
+    }
+
+    /// Removes a gate with all its connections.
+    pub fn remove_gate(&mut self, gate_id: GateId) -> Result<(), SimulationError> {
+        let snapshot = self.snapshot(gate_id)?;
+        self.delete_gate(gate_id)?;
+        self.history.push(Edit::RemoveGate(snapshot));
+        Ok(())
+    }
+
+    /// Removes gates with all their connections as a single edit.
+    pub fn remove_gates(&mut self, gates: &BTreeSet<GateId>) -> Result<(), SimulationError> {
+        if let Some(unknown) = gates.iter().find(|id| !self.gates.contains_key(id)) {
+            return Err(SimulationError::UnknownGate(*unknown));
+        }
+
+        // connections between the gates are recorded with the gate removed
+        // first
+        let mut edits = Vec::new();
+        for gate_id in gates {
+            edits.push(Edit::RemoveGate(self.snapshot(*gate_id)?));
+            self.delete_gate(*gate_id)?;
+        }
+        self.history.push(Edit::Batch(edits));
+        Ok(())
+    }
+
+    /// Gates whose bodies overlap given area.
+    pub fn gates_in(&self, area: Rect) -> BTreeSet<GateId> {
+        self.gates
+            .iter()
+            .filter(|(gate_id, pos)| {
+                self.gate_rect(**gate_id, **pos)
+                    .is_some_and(|rect| rect.overlaps(&area))
+            })
+            .map(|(gate_id, _)| *gate_id)
+            .collect()
+    }
+
+    /// Describes given gates with the connections and nets between them, in
+    /// the format of saved boards, for pasting them with
+    /// [`BoardSimulation::paste`]. The library of subcircuits is included, so
+    /// the gates can be pasted on other boards.
+    pub fn copy(&self, gates: &BTreeSet<GateId>) -> BoardFile {
+        let file_ids: BTreeMap<GateId, usize> = gates
+            .iter()
+            .filter(|id| self.gates.contains_key(id))
+            .enumerate()
+            .map(|(file_id, id)| (*id, file_id))
+            .collect();
+
+        let nets = file_ids
+            .iter()
+            .flat_map(|(id, file_id)| {
+                let inputs = self
+                    .sim
+                    .get_gate_state(*id)
+                    .map_or(0, |(inputs, _)| inputs.len());
+                (0..inputs).filter_map(|input| {
+                    let resolution = self.sim.resolution_override(id.input(input)).ok()??;
+                    Some(((*file_id, input), resolution))
+                })
+            })
+            .collect();
+
+        BoardFile {
+            mode: self.sim.mode(),
+            default_resolution: self.sim.default_resolution(),
+            gates: file_ids
+                .iter()
+                .filter_map(|(id, file_id)| {
+                    let pos = self.gates[id];
+                    Some(GateEntry {
+                        id: *file_id,
+                        kind: self.sim.get_gate_name(*id).ok()?.to_string(),
+                        pos: (pos.x, pos.y),
+                        delay: self.sim.get_gate_delay(*id).ok()?,
+                        params: self.sim.get_gate_params(*id).ok()?,
+                    })
+                })
+                .collect(),
+            wires: self
+                .connections
+                .values()
+                .filter_map(|wire| {
+                    Some(WireEntry {
+                        output: (*file_ids.get(&wire.output.gate)?, wire.output.index),
+                        input: (*file_ids.get(&wire.input.gate)?, wire.input.index),
+                        waypoints: wire
+                            .waypoints
+                            .iter()
+                            .map(|point| (point.x, point.y))
+                            .collect(),
+                    })
+                })
+                .collect(),
+            nets,
+            subcircuits: self.subcircuits,
+            circuits: self.circuits.clone(),
+        }
+    }
+
+    /// Adds the gates, connections and nets of a copied board moved by given
+    /// offset, as a single edit. Subcircuits of the copied board that are
+    /// not in the library are added to it, subcircuits with the same name as
+    /// one in the library are taken from the library. Returns the ids of
+    /// the added gates.
+    pub fn paste(&mut self, file: &BoardFile, offset: Vec2) -> Result<Vec<GateId>, FileError> {
+        for circuit in &file.circuits {
+            if !self.circuit_names().any(|name| name == circuit.name) {
+                self.circuits.push(circuit.clone());
+            }
+        }
+
+        let mut edits = Vec::new();
+        let pasted = self.paste_edits(file, offset, &mut edits);
+        if pasted.is_err() {
+            self.revert(&Edit::Batch(edits))?;
+        } else {
+            self.history.push(Edit::Batch(edits));
+        }
+        pasted
+    }
+
+    fn paste_edits(
+        &mut self,
+        file: &BoardFile,
+        offset: Vec2,
+        edits: &mut Vec<Edit>,
+    ) -> Result<Vec<GateId>, FileError> {
+        let mut ids = BTreeMap::new();
+        for gate in &file.gates {
+            let gate_id = board_file::add_gate(
+                &mut self.sim,
+                &self.circuits,
+                self.subcircuits,
+                &gate.kind,
+                &gate.params,
+            )?;
+            self.gates
+                .insert(gate_id, Vec2::new(gate.pos.0, gate.pos.1) + offset);
+            self.sim.set_gate_delay(gate_id, gate.delay)?;
+            // nets are part of the recorded gate
+            for ((_, input), resolution) in file.nets.iter().filter(|(net, _)| net.0 == gate.id) {
+                self.sim
+                    .set_resolution(gate_id.input(*input), *resolution)?;
+            }
+            edits.push(Edit::AddGate(self.snapshot(gate_id)?));
+            if ids.insert(gate.id, gate_id).is_some() {
+                return Err(FileError(format!("duplicate gate {}", gate.id)));
+            }
+        }
+
+        for entry in &file.wires {
+            let output = board_file::sim_output(&self.sim, &ids, entry.output)?;
+            let input = board_file::sim_input(&self.sim, &ids, entry.input)?;
+            let waypoints = entry
+                .waypoints
+                .iter()
+                .map(|(x, y)| Vec2::new(*x, *y) + offset)
+                .collect();
+            let connection = self.connect(output, input, waypoints)?;
+            edits.push(Edit::AddConnection(
+                self.wire_ref(connection)?.snapshot(connection),
+            ));
+        }
+
+        Ok(ids.into_values().collect())
+    }
+
+    fn delete_gate(&mut self, gate_id: GateId) -> Result<(), SimulationError> {
+        self.sim.remove_gate(gate_id)?;
+        if self.gates.remove(&gate_id).is_some() {
+            self.connections
+                .retain(|_, wire| wire.output.gate != gate_id && wire.input.gate != gate_id);
+        }
+        Ok(())
+    }
+
+    /// Everything needed to add a gate again once removed.
+    fn snapshot(&self, gate_id: GateId) -> Result<GateSnapshot, SimulationError> {
+        let inputs = self.sim.get_gate_state(gate_id)?.0.len();
+        let nets = (0..inputs)
+            .filter_map(|input| {
+                let resolution = self.sim.resolution_override(gate_id.input(input)).ok()??;
+                Some((input, resolution))
+            })
+            .collect();
+        let wires = self
+            .connections
+            .iter()
+            .filter(|(_, wire)| wire.output.gate == gate_id || wire.input.gate == gate_id)
+            .map(|(connection, wire)| wire.snapshot(*connection))
+            .collect();
+
+        Ok(GateSnapshot {
+            id: gate_id,
+            kind: self.sim.get_gate_name(gate_id)?.to_string(),
+            params: self.sim.get_gate_params(gate_id)?,
+            delay: self.sim.get_gate_delay(gate_id)?,
+            pos: self.gate_pos(gate_id)?,
+            nets,
+            wires,
+        })
+    }
+
+    /// Adds a removed gate again with its connections, the gate gets a new
+    /// id.
+    fn restore_gate(&mut self, snapshot: &GateSnapshot) -> Result<(), FileError> {
+        let gate_id = board_file::add_gate(
+            &mut self.sim,
+            &self.circuits,
+            self.subcircuits,
+            &snapshot.kind,
+            &snapshot.params,
+        )?;
+        self.history.gate_added(snapshot.id, gate_id);
+        self.gates.insert(gate_id, snapshot.pos);
+        self.sim.set_gate_delay(gate_id, snapshot.delay)?;
+        for (input, resolution) in &snapshot.nets {
+            self.sim
+                .set_resolution(gate_id.input(*input), *resolution)?;
+        }
+
+        for wire in &snapshot.wires {
+            self.reconnect(wire)?;
+        }
+        Ok(())
+    }
+
+    /// Connects an output to an input, fails when the pins do not exist or
+    /// have different widths.
+    pub fn add_connection(
+        &mut self,
+        output: OutputPin,
+        input: InputPin,
+    ) -> Result<ConnectionId, SimulationError> {
+        let connection = self.connect(output, input, Vec::new())?;
+        self.history.push(Edit::AddConnection(WireSnapshot {
+            connection,
+            output,
+            input,
+            waypoints: Vec::new(),
+        }));
+        Ok(connection)
+    }
+
+    fn connect(
+        &mut self,
+        output: OutputPin,
+        input: InputPin,
+        waypoints: Vec<Vec2>,
+    ) -> Result<ConnectionId, SimulationError> {
+        let wire = Wire {
+            waypoints,
+            ..self.wire(output, input)?
+        };
+        let connection = self.sim.add_connection(output, input)?;
+        self.connections.insert(connection, wire);
+        self.route_wires();
+        Ok(connection)
+    }
+
+    pub fn remove_connection(&mut self, connection: ConnectionId) -> Result<(), SimulationError> {
+        let snapshot = self.wire_ref(connection)?.snapshot(connection);
+        self.disconnect(connection)?;
+        self.history.push(Edit::RemoveConnection(snapshot));
+        Ok(())
+    }
+
+    fn wire_ref(&self, connection: ConnectionId) -> Result<&Wire, SimulationError> {
+        self.connections
+            .get(&connection)
+            .ok_or(SimulationError::UnknownConnection(connection))
+    }
+
+    fn disconnect(&mut self, connection: ConnectionId) -> Result<(), SimulationError> {
+        self.sim.remove_connection(connection)?;
+        self.connections.remove(&connection);
+        Ok(())
+    }
+
+    /// Records that gates were dragged to their current positions from given
+    /// positions, as a single edit.
+    pub fn finish_move(&mut self, from: &[(GateId, Vec2)]) -> Result<(), SimulationError> {
+        let mut edits = Vec::new();
+        for (gate_id, from) in from {
+            let to = self.gate_pos(*gate_id)?;
+            if to != *from {
+                edits.push(Edit::MoveGate {
+                    gate: *gate_id,
+                    from: *from,
+                    to,
+                });
+            }
+        }
+
+        match edits.len() {
+            0 => {}
+            1 => self.history.push(edits.remove(0)),
+            _ => self.history.push(Edit::Batch(edits)),
+        }
+        Ok(())
+    }
+
+    /// Undoes the last edit, returns whether there was an edit to undo.
+    pub fn undo(&mut self) -> Result<bool, FileError> {
+        let Some(edit) = self.history.take_undo() else {
+            return Ok(false);
+        };
+
+        // edits that fail are kept, to try again
+        match self.revert(&edit) {
+            Ok(()) => self.history.undone(edit),
+            Err(error) => {
+                self.history.undo_failed(edit);
+                return Err(error);
+            }
+        }
+        Ok(true)
+    }
+
+    fn revert(&mut self, edit: &Edit) -> Result<(), FileError> {
+        match edit {
+            Edit::AddGate(snapshot) => self.delete_gate(self.history.gate(snapshot.id))?,
+            Edit::RemoveGate(snapshot) => self.restore_gate(snapshot)?,
+            Edit::MoveGate { gate, from, .. } => self.move_gate(*gate, *from)?,
+            Edit::AddConnection(wire) => {
+                self.disconnect(self.history.connection(wire.connection))?
+            }
+            Edit::RemoveConnection(wire) => self.reconnect(wire)?,
+            Edit::Batch(edits) => {
+                for edit in edits.iter().rev() {
+                    self.revert(edit)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Redoes the last undone edit, returns whether there was an edit to
+    /// redo.
+    pub fn redo(&mut self) -> Result<bool, FileError> {
+        let Some(edit) = self.history.take_redo() else {
+            return Ok(false);
+        };
+
+        match self.replay(&edit) {
+            Ok(()) => self.history.redone(edit),
+            Err(error) => {
+                self.history.redo_failed(edit);
+                return Err(error);
+            }
+        }
+        Ok(true)
+    }
+
+    fn replay(&mut self, edit: &Edit) -> Result<(), FileError> {
+        match edit {
+            Edit::AddGate(snapshot) => self.restore_gate(snapshot)?,
+            Edit::RemoveGate(snapshot) => self.delete_gate(self.history.gate(snapshot.id))?,
+            Edit::MoveGate { gate, to, .. } => self.move_gate(*gate, *to)?,
+            Edit::AddConnection(wire) => self.reconnect(wire)?,
+            Edit::RemoveConnection(wire) => {
+                self.disconnect(self.history.connection(wire.connection))?
+            }
+            Edit::Batch(edits) => {
+                for edit in edits {
+                    self.replay(edit)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn move_gate(&mut self, gate_id: GateId, pos: Vec2) -> Result<(), SimulationError> {
+        let gate_id = self.history.gate(gate_id);
+        let gate_pos = self
+            .gates
+            .get_mut(&gate_id)
+            .ok_or(SimulationError::UnknownGate(gate_id))?;
+        *gate_pos = pos;
+        Ok(())
+    }
+
+    /// Adds a removed connection again between the current ids of its pins.
+    fn reconnect(&mut self, wire: &WireSnapshot) -> Result<(), SimulationError> {
+        let new = self.connect(
+            self.history.output(wire.output),
+            self.history.input(wire.input),
+            wire.waypoints.clone(),
+        )?;
+        self.history.connection_added(wire.connection, new);
+        Ok(())
+    }
+
+    /// Maximum number of edits that can be undone.
+    pub fn history_depth(&self) -> usize {
+        self.history.depth()
+    }
+
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history.set_depth(depth)
+    }
+
+    pub fn simulate(&mut self) {
+        self.sim.simulate()
+    }
+
+    pub fn reset(&mut self) {
+        self.sim.reset()
+    }
+
+    pub fn mode(&self) -> SimulationMode {
+        self.sim.mode()
+    }
+
+    pub fn set_mode(&mut self, mode: SimulationMode) {
+        self.sim.set_mode(mode)
+    }
+
+    /// Iterates over gates with their positions, names, input and output
+    /// states and whether they are interactive.
+    pub fn gate_iter_mut(&mut self) -> impl Iterator<Item = GateItem<'_>> + '_ {
+        let sim = &self.sim;
+        self.gates.iter_mut().filter_map(|(id, pos)| {
+            let name = sim.get_gate_name(*id).ok()?;
+            let state = sim.get_gate_state(*id).ok()?;
+            let interactive = sim.is_gate_interactive(*id).ok()?;
+            Some((*id, pos, name, state, interactive))
+        })
+    }
+
+    pub fn press_gate(&mut self, gate_id: GateId) -> Result<(), SimulationError> {
+        self.sim.press_gate(gate_id)
+    }
+
+    pub fn release_gate(&mut self, gate_id: GateId) -> Result<(), SimulationError> {
+        self.sim.release_gate(gate_id)
+    }
+
+    /// Iterates over connections with their routes as of the last call to
+    /// [`BoardSimulation::route_wires`].
+    pub fn connection_iter(&self) -> impl Iterator<Item = WireItem<'_>> + '_ {
+        self.connections.iter().filter_map(|(connection, wire)| {
+            let output_state = self.sim.get_gate_state(wire.output.gate).ok()?.1[wire.output.index];
+            Some((
+                *connection,
+                wire.route.as_slice(),
+                wire.waypoints.as_slice(),
+                output_state,
+            ))
+        })
+    }
+
+    /// Area covered by the body of a gate.
+    fn gate_rect(&self, gate_id: GateId, pos: Vec2) -> Option<Rect> {
+        let (inputs, outputs) = self.sim.get_gate_state(gate_id).ok()?;
+        let size = gate_size(inputs.len(), outputs.len());
+        Some(Rect::new(pos.x, pos.y, size, size))
+    }
+
+    /// Routes wires that are new, had their bend points changed or whose
+    /// gates were moved, and wires in the way of gates added, moved or
+    /// removed since the last call.
+    pub fn route_wires(&mut self) {
+        let rects: BTreeMap<GateId, Rect> = self
+            .gates
+            .iter()
+            .filter_map(|(gate_id, pos)| Some((*gate_id, self.gate_rect(*gate_id, *pos)?)))
+            .collect();
+
+        let mut moved = Vec::new();
+        // areas of the changed gates before and after the change
+        let mut changed = Vec::new();
+        for (gate_id, rect) in &rects {
+            let old = self.routed.get(gate_id);
+            if old != Some(rect) {
+                moved.push(*gate_id);
+                changed.push(*rect);
+                changed.extend(old);
+            }
+        }
+        for (gate_id, rect) in &self.routed {
+            if !rects.contains_key(gate_id) {
+                changed.push(*rect);
+            }
+        }
+
+        let obstacles: Vec<Rect> = rects.values().copied().collect();
+        for wire in self.connections.values_mut() {
+            let stale = wire.route.is_empty()
+                || moved.contains(&wire.output.gate)
+                || moved.contains(&wire.input.gate)
+                || changed
+                    .iter()
+                    .any(|rect| routing::crosses(&wire.route, *rect));
+            if stale {
+                let output = self.gates[&wire.output.gate] + wire.offsets.0;
+                let input = self.gates[&wire.input.gate] + wire.offsets.1;
+                wire.route = routing::route(output, input, &wire.waypoints, &obstacles);
+            }
+        }
+        self.routed = rects;
+    }
+
+    pub fn waypoints(&self, connection: ConnectionId) -> Result<&[Vec2], SimulationError> {
+        Ok(&self.wire_ref(connection)?.waypoints)
+    }
+
+    /// Replaces the bend points of a connection, the connection is routed
+    /// again.
+    pub fn set_waypoints(
+        &mut self,
+        connection: ConnectionId,
+        waypoints: Vec<Vec2>,
+    ) -> Result<(), SimulationError> {
+        let wire = self
+            .connections
+            .get_mut(&connection)
+            .ok_or(SimulationError::UnknownConnection(connection))?;
+        wire.waypoints = waypoints;
+        wire.route.clear();
+        self.route_wires();
+        Ok(())
+    }
+
+    /// Adds a bend point to a connection between the points it makes the
+    /// smallest detour between. Returns the index of the new bend point.
+    pub fn add_waypoint(
+        &mut self,
+        connection: ConnectionId,
+        pos: Vec2,
+    ) -> Result<usize, SimulationError> {
+        let wire = self.wire_ref(connection)?;
+        let output = self.gate_pos(wire.output.gate)? + wire.offsets.0;
+        let input = self.gate_pos(wire.input.gate)? + wire.offsets.1;
+        let mut points = vec![output];
+        points.extend_from_slice(&wire.waypoints);
+        points.push(input);
+
+        let length = |a: Vec2, b: Vec2| (a - b).abs().x + (a - b).abs().y;
+        let index = points
+            .windows(2)
+            .map(|part| length(part[0], pos) + length(pos, part[1]) - length(part[0], part[1]))
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map_or(0, |(index, _)| index);
+
+        let mut waypoints = wire.waypoints.clone();
+        waypoints.insert(index, pos);
+        self.set_waypoints(connection, waypoints)?;
+        Ok(index)
+    }
+
+    /// Heights of the top edge and of the pins of a gate relative to the
+    /// gate, the first pin comes right after the top edge.
+    fn rows(&self, gate_id: GateId) -> Result<Vec<f32>, SimulationError> {
+        let (inputs, outputs) = self.sim.get_gate_state(gate_id)?;
+        let size = gate_size(inputs.len(), outputs.len());
+        let inputs = (0..inputs.len()).map(|i| pin_offset(size, inputs.len(), i, false).y);
+        let outputs = (0..outputs.len()).map(|i| pin_offset(size, outputs.len(), i, true).y);
+        Ok(std::iter::once(0.).chain(inputs).chain(outputs).collect())
+    }
+
+    /// Position for a gate dragged to given position. With a grid, the left
+    /// edge and the first pin of the gate are snapped to the grid, which puts
+    /// all of its pins on the grid when the pitch divides [`GRID_PITCH`]. The gate
+    /// is then lined up with other gates whose left edge, top edge or pins
+    /// are closer than `tolerance`, the lines it lines up with are returned
+    /// as guides. Gates moved together with the gate are not lined up with.
+    pub fn align_gate(
+        &self,
+        gate_id: GateId,
+        pos: Vec2,
+        moving: &BTreeSet<GateId>,
+        grid: Option<f32>,
+        tolerance: f32,
+    ) -> Result<(Vec2, Guides), SimulationError> {
+        let rows = self.rows(gate_id)?;
+        let mut pos = pos;
+        if let Some(pitch) = grid {
+            let first_pin = rows.get(1).copied().unwrap_or(0.);
+            let snapped = snap(pos + Vec2::new(0., first_pin), pitch);
+            pos = snapped - Vec2::new(0., first_pin);
+        }
+
+        // closest distances to other gates, with the lines to line up with
+        let mut guides = Guides::default();
+        let mut closest = (tolerance, tolerance);
+        let mut shift = Vec2::ZERO;
+        for (other, other_pos) in &self.gates {
+            if *other == gate_id || moving.contains(other) {
+                continue;
+            }
+
+            let dx = other_pos.x - pos.x;
+            if dx.abs() < closest.0 {
+                closest.0 = dx.abs();
+                guides.x = Some(other_pos.x);
+                shift.x = dx;
+            }
+
+            for other_row in self.rows(*other)? {
+                for row in &rows {
+                    let dy = other_pos.y + other_row - (pos.y + row);
+                    if dy.abs() < closest.1 {
+                        closest.1 = dy.abs();
+                        guides.y = Some(other_pos.y + other_row);
+                        shift.y = dy;
+                    }
+                }
+            }
+        }
+
+        Ok((pos + shift, guides))
+    }
+
+    pub fn gate_pos(&self, gate_id: GateId) -> Result<Vec2, SimulationError> {
+        self.gates
+            .get(&gate_id)
+            .copied()
+            .ok_or(SimulationError::UnknownGate(gate_id))
+    }
+
+    pub fn gate_delay(&self, gate_id: GateId) -> Result<u64, SimulationError> {
+        self.sim.get_gate_delay(gate_id)
+    }
+
+    pub fn set_gate_delay(&mut self, gate_id: GateId, delay: u64) -> Result<(), SimulationError> {
+        self.sim.set_gate_delay(gate_id, delay)
+    }
+
+    pub fn time(&self) -> u64 {
+        self.sim.time()
+    }
+
+    pub fn gate_name(&self, gate_id: GateId) -> Result<&str, SimulationError> {
+        self.sim.get_gate_name(gate_id)
+    }
+
+    pub fn default_resolution(&self) -> Resolution {
+        self.sim.default_resolution()
+    }
+
+    pub fn set_default_resolution(&mut self, resolution: Resolution) {
+        if self.sim.default_resolution() != resolution {
+            self.sim.set_default_resolution(resolution)
+        }
+    }
+
+    pub fn resolution(&self, input: InputPin) -> Result<Resolution, SimulationError> {
+        self.sim.get_resolution(input)
+    }
+
+    pub fn set_resolution(
+        &mut self,
+        input: InputPin,
+        resolution: Resolution,
+    ) -> Result<(), SimulationError> {
+        self.sim.set_resolution(input, resolution)
+    }
+
+    pub fn diagnostics(&self) -> impl Iterator<Item = &Diagnostic> + '_ {
+        self.sim.diagnostics()
+    }
+
+    /// Output driving a connection, whose value is the value of the wire.
+    pub fn connection_output(
+        &self,
+        connection: ConnectionId,
+    ) -> Result<OutputPin, SimulationError> {
+        Ok(self.wire_ref(connection)?.output)
+    }
+
+    pub fn waveforms(&self) -> &Waveforms {
+        self.sim.waveforms()
+    }
+
+    /// Starts recording a pin, or stops recording it when it is recorded
+    /// already.
+    pub fn toggle_recording(&mut self, probe: Probe) -> Result<(), SimulationError> {
+        if self.sim.waveforms().is_recorded(probe) {
+            self.sim.stop_recording(probe);
+            Ok(())
+        } else {
+            self.sim.record(probe)
+        }
+    }
+
+    pub fn stop_recording(&mut self, probe: Probe) {
+        self.sim.stop_recording(probe)
+    }
+
+    pub fn set_waveform_capacity(&mut self, capacity: usize) {
+        self.sim.set_waveform_capacity(capacity)
+    }
+
+    /// All recorded pins as a VCD file.
+    pub fn to_vcd(&self) -> Result<String, SimulationError> {
+        let probes: Vec<Probe> = self
+            .sim
+            .waveforms()
+            .traces()
+            .map(|(probe, _)| probe)
+            .collect();
+        vcd::export(&self.sim, &probes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gates::{And, High, Not, OutputPort},
+        signal::Signal,
+    };
+
+    #[test]
+    fn dragged_gates_snap_and_line_up() {
+        assert_eq!(snap(Vec2::new(12., 38.), 25.), Vec2::new(0., 50.));
+
+        let mut board = BoardSimulation::new();
+        let not = board.add_gate(Not, Vec2::new(0., 0.));
+        let and = board.add_gate(And, Vec2::new(300., 300.));
+        let alone = BTreeSet::new();
+
+        // the input of the inverter is snapped to the grid
+        let (pos, guides) = board
+            .align_gate(not, Vec2::new(110., 140.), &alone, Some(25.), 0.)
+            .unwrap();
+        assert_eq!(
+            pos + pin_offset(gate_size(1, 1), 1, 0, false),
+            Vec2::new(100., 150.)
+        );
+        assert_eq!(guides, Guides::default());
+
+        // all pins of a gate with more inputs than outputs end up on the grid
+        for pitch in [GRID_PITCH, GRID_PITCH / 3.] {
+            let (pos, _) = board
+                .align_gate(and, Vec2::new(33., 71.), &alone, Some(pitch), 0.)
+                .unwrap();
+            let size = gate_size(2, 1);
+            let pins = [
+                pin_offset(size, 2, 0, false),
+                pin_offset(size, 2, 1, false),
+                pin_offset(size, 1, 0, true),
+            ];
+            for pin in pins {
+                let pin = pos + pin;
+                assert!(
+                    (snap(pin, pitch) - pin).length() < 1e-3,
+                    "{pin} is off the grid"
+                );
+            }
+        }
+
+        // the output of the inverter lines up with the second input of the
+        // gate, its left edge with the left edge of the gate
+        let and_input = pin_offset(gate_size(2, 1), 2, 1, false).y;
+        let not_output = pin_offset(gate_size(1, 1), 1, 0, true).y;
+        let dragged = Vec2::new(297., 300. + and_input - not_output - 0.5);
+        let (pos, guides) = board.align_gate(not, dragged, &alone, None, 5.).unwrap();
+        assert_eq!(pos, Vec2::new(300., 300. + and_input - not_output));
+        assert_eq!(
+            guides,
+            Guides {
+                x: Some(300.),
+                y: Some(300. + and_input),
+            }
+        );
+
+        // gates dragged together do not line up
+        let together = BTreeSet::from([and]);
+        let (pos, guides) = board.align_gate(not, dragged, &together, None, 5.).unwrap();
+        assert_eq!((pos, guides), (dragged, Guides::default()));
+    }
+
+    fn wires(board: &BoardSimulation) -> Vec<WireEntry> {
+        board.to_file().wires
+    }
+
+    #[test]
+    fn undoing_removal_restores_connections() {
+        let mut board = BoardSimulation::new();
+        let high = board.add_gate(High, Vec2::ZERO);
+        let and = board.add_gate(And, Vec2::new(100., 0.));
+        let not = board.add_gate(Not, Vec2::new(200., 0.));
+        board.add_connection(high.output(0), and.input(0)).unwrap();
+        board.add_connection(high.output(0), and.input(1)).unwrap();
+        board.add_connection(and.output(0), not.input(0)).unwrap();
+        board
+            .set_resolution(and.input(1), Resolution::WiredOr)
+            .unwrap();
+        let before = board.to_file();
+
+        board.remove_gate(and).unwrap();
+        assert_eq!(board.connections.len(), 0);
+
+        assert!(board.undo().unwrap());
+        assert_eq!(board.to_file(), before);
+
+        // the restored gate has a new id, redoing removes it again
+        assert!(board.redo().unwrap());
+        assert_eq!(board.gates.len(), 2);
+        assert!(board.undo().unwrap());
+        assert_eq!(wires(&board), before.wires);
+    }
+
+    #[test]
+    fn failed_undo_keeps_the_edit() {
+        let mut board = BoardSimulation::new();
+        let high = board.add_gate(High, Vec2::ZERO);
+        let not = board.add_gate(Not, Vec2::new(100., 0.));
+        let connection = board.add_connection(high.output(0), not.input(0)).unwrap();
+
+        // the connection is gone behind the back of the history
+        board.sim.remove_connection(connection).unwrap();
+        assert!(board.undo().is_err());
+        assert!(board.history.can_undo());
+        assert!(!board.history.can_redo());
+
+        // once the connection is back, undoing it works
+        let again = board
+            .sim
+            .add_connection(high.output(0), not.input(0))
+            .unwrap();
+        board.history.connection_added(connection, again);
+        assert!(board.undo().unwrap());
+        assert!(board.history.can_redo());
+    }
+
+    #[test]
+    fn packaged_selection_is_replaced_by_an_instance() {
+        let mut board = BoardSimulation::new();
+        let high = board.add_gate(High, Vec2::ZERO);
+        let first = board.add_gate(Not, Vec2::new(100., 0.));
+        let second = board.add_gate(Not, Vec2::new(200., 0.));
+        let out = board.add_gate(OutputPort::default(), Vec2::new(300., 0.));
+        board
+            .add_connection(high.output(0), first.input(0))
+            .unwrap();
+        board
+            .add_connection(first.output(0), second.input(0))
+            .unwrap();
+        board
+            .add_connection(second.output(0), out.input(0))
+            .unwrap();
+
+        let instance = board
+            .package_selection("BUF", &BTreeSet::from([first, second]))
+            .unwrap();
+        assert_eq!(board.gates.len(), 3);
+        assert_eq!(board.gate_pos(instance).unwrap(), Vec2::new(100., 0.));
+        assert_eq!(board.sim.get_gate_name(instance).unwrap(), "BUF");
+        // the wires into and out of the selection go through new ports
+        let circuit = &board.circuits[0].board;
+        let kinds: Vec<&str> = circuit
+            .gates
+            .iter()
+            .map(|gate| gate.kind.as_str())
+            .collect();
+        assert_eq!(kinds, ["NOT", "NOT", "IN", "OUT"]);
+        assert_eq!(circuit.wires.len(), 3);
+
+        while !board.sim.is_stable() {
+            board.simulate();
+        }
+        let (inputs, _) = board.sim.get_gate_state(out).unwrap();
+        assert_eq!(inputs[0].bit(0), Signal::High);
+
+        // the instance replaces the gates in a single edit
+        assert!(board.undo().unwrap());
+        assert_eq!(board.gates.len(), 4);
+        assert_eq!(board.connections.len(), 3);
+        assert!(board
+            .package_selection("BUF", &BTreeSet::from([high]))
+            .is_err());
+    }
+
+    #[test]
+    fn pasted_gates_keep_their_connections() {
+        let mut board = BoardSimulation::new();
+        let high = board.add_gate(High, Vec2::ZERO);
+        let first = board.add_gate(Not, Vec2::new(100., 0.));
+        let second = board.add_gate(Not, Vec2::new(200., 0.));
+        board
+            .add_connection(high.output(0), first.input(0))
+            .unwrap();
+        board
+            .add_connection(first.output(0), second.input(0))
+            .unwrap();
+        board
+            .set_resolution(second.input(0), Resolution::WiredAnd)
+            .unwrap();
+
+        // only the connection between the copied gates is copied
+        let copied = board.copy(&BTreeSet::from([first, second]));
+        assert_eq!(copied.gates.len(), 2);
+        assert_eq!(copied.wires.len(), 1);
+        assert_eq!(copied.nets, [((1, 0), Resolution::WiredAnd)]);
+
+        // the clipboard goes through the file format
+        let copied = BoardFile::parse(&copied.to_string()).unwrap();
+        let mut other = BoardSimulation::new();
+        let pasted = other.paste(&copied, Vec2::new(0., 100.)).unwrap();
+        assert_eq!(pasted.len(), 2);
+        assert_eq!(other.gate_pos(pasted[0]).unwrap(), Vec2::new(100., 100.));
+        assert_eq!(other.connections.len(), 1);
+        assert_eq!(
+            other.resolution(pasted[1].input(0)).unwrap(),
+            Resolution::WiredAnd
+        );
+
+        // pasting is undone as a whole
+        assert!(other.undo().unwrap());
+        assert!(other.gates.is_empty());
+        assert!(other.redo().unwrap());
+        assert_eq!(other.gates.len(), 2);
+        assert_eq!(other.connections.len(), 1);
+    }
+
+    #[test]
+    fn removed_selection_is_restored_at_once() {
+        let mut board = BoardSimulation::new();
+        let high = board.add_gate(High, Vec2::ZERO);
+        let not = board.add_gate(Not, Vec2::new(100., 0.));
+        let far = board.add_gate(Not, Vec2::new(100., 300.));
+        board.add_connection(high.output(0), not.input(0)).unwrap();
+        let before = board.to_file();
+
+        let selection = board.gates_in(Rect::new(-10., -10., 200., 100.));
+        assert_eq!(selection, BTreeSet::from([high, not]));
+        board.remove_gates(&selection).unwrap();
+        assert_eq!(board.gates.keys().collect::<Vec<_>>(), [&far]);
+
+        assert!(board.undo().unwrap());
+        assert_eq!(board.to_file(), before);
+    }
+
+    #[test]
+    fn waypoints_are_saved_and_restored() {
+        let mut board = BoardSimulation::new();
+        let high = board.add_gate(High, Vec2::ZERO);
+        let not = board.add_gate(Not, Vec2::new(200., 100.));
+        let connection = board.add_connection(high.output(0), not.input(0)).unwrap();
+
+        let waypoint = Vec2::new(100., 200.);
+        assert_eq!(board.add_waypoint(connection, waypoint).unwrap(), 0);
+        let (_, route, waypoints, _) = board.connection_iter().next().unwrap();
+        assert_eq!(waypoints, [waypoint]);
+        assert_eq!(routing::distance(route, waypoint), 0.);
+
+        let file = board.to_file();
+        assert_eq!(file.wires[0].waypoints, [(100., 200.)]);
+        let loaded = BoardSimulation::from_file(&file).unwrap();
+        assert_eq!(loaded.to_file(), file);
+
+        board.remove_connection(connection).unwrap();
+        board.undo().unwrap();
+        assert_eq!(board.to_file(), file);
+    }
+
+    #[test]
+    fn edits_follow_gates_added_again() {
+        let mut board = BoardSimulation::new();
+        let high = board.add_gate(High, Vec2::ZERO);
+        let not = board.add_gate(Not, Vec2::ZERO);
+        board.gates.insert(not, Vec2::new(50., 0.));
+        board.finish_move(&[(not, Vec2::ZERO)]).unwrap();
+        board.add_connection(high.output(0), not.input(0)).unwrap();
+
+        // undo everything, then redo everything with new ids
+        while board.undo().unwrap() {}
+        assert!(board.gates.is_empty());
+        while board.redo().unwrap() {}
+
+        let file = board.to_file();
+        assert_eq!(file.gates[1].pos, (50., 0.));
+        assert_eq!(file.wires.len(), 1);
+        assert!(!board.redo().unwrap());
+
+        // a new edit drops the undone edits
+        board.undo().unwrap();
+        board.add_gate(Not, Vec2::ZERO);
+        assert!(!board.redo().unwrap());
+    }
+}
@@ -0,0 +1,656 @@
+//! Text format of saved boards.
+//!
+//! A board file starts with a header with the version of the format, followed
+//! by one item per line, fields are separated by whitespace. Empty lines and
+//! lines starting with `#` are ignored.
+//!
+//! ```text
+//! logic-sim 3
+//! mode tick
+//! nets tri-state
+//! subcircuits nested
+//! # circuit <name> ... end
+//! circuit INV
+//! gate 0 IN 0 0 1 1
+//! gate 1 NOT 100 0 1
+//! gate 2 OUT 200 0 1 1
+//! wire 0.0 1.0
+//! wire 1.0 2.0
+//! end
+//! # gate <id> <kind> <x> <y> <delay> [<param>...]
+//! gate 0 SW 100 100 1
+//! gate 1 CLK 100 200 1 10 50 0
+//! gate 2 AND 300 150 2
+//! # wire <gate>.<output> <gate>.<input> [<x>,<y>...]
+//! wire 0.0 2.0
+//! wire 1.0 2.1 250,240 250,175
+//! # net <gate>.<input> <resolution>
+//! net 2.1 wired-or
+//! ```
+//!
+//! - `mode` is the simulation mode, `tick` or `event`.
+//! - `nets` is the default resolution policy of nets.
+//! - `gate` adds a gate of given kind, the kind is the name of the gate and the
+//!   parameters are those returned by [`Gate::params`]. Ids are local to the
+//!   file, gates get new ids when loaded.
+//! - `wire` connects an output of a gate to an input of a gate, the wire is
+//!   routed through the optional bend points in order.
+//! - `net` sets the resolution policy of the net driving an input.
+//! - `subcircuits` selects how subcircuits are simulated, `nested` or
+//!   `flattened`.
+//! - `circuit` defines a subcircuit, the lines up to `end` describe its board
+//!   with `nets`, `gate`, `wire` and `net` lines. The name of the circuit is
+//!   used as the kind of its gates, circuits can use circuits defined before
+//!   them.
+//!
+//! Files written by older versions of the format are migrated to the current
+//! version when loaded.
+
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    gates::*,
+    id::{ConnectionId, GateId, InputPin, OutputPin},
+    logic_simulation::{LogicSimulation, SimulationError, SimulationMode},
+    net::Resolution,
+    subcircuit::{Circuit, SubcircuitMode},
+};
+
+/// Version of the format written by [`BoardFile`].
+pub const VERSION: usize = 3;
+
+/// Rewrites the body of a file, without the header, from one version of the
+/// format to the next one.
+type Migration = fn(&str) -> Result<String, FileError>;
+
+/// Migrations of the format, the first one upgrades version 1 to version 2.
+const MIGRATIONS: [Migration; VERSION - 1] = [upgrade_v1, upgrade_v2];
+
+/// Version 2 added subcircuits, so any version 1 file is a valid version 2
+/// file.
+fn upgrade_v1(body: &str) -> Result<String, FileError> {
+    Ok(body.to_string())
+}
+
+/// Version 3 added bend points of wires, wires of version 2 files have none
+/// and are routed automatically.
+fn upgrade_v2(body: &str) -> Result<String, FileError> {
+    Ok(body.to_string())
+}
+
+/// Problem with the contents of a board file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileError(pub String);
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<SimulationError> for FileError {
+    fn from(error: SimulationError) -> Self {
+        FileError(error.to_string())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GateEntry {
+    pub id: usize,
+    pub kind: String,
+    pub pos: (f32, f32),
+    pub delay: u64,
+    pub params: Vec<u64>,
+}
+
+/// Connection from an output of a gate to an input of a gate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WireEntry {
+    pub output: (usize, usize),
+    pub input: (usize, usize),
+    /// Bend points the wire is routed through, in order from the output
+    pub waypoints: Vec<(f32, f32)>,
+}
+
+/// Board as stored in a file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BoardFile {
+    pub mode: SimulationMode,
+    pub default_resolution: Resolution,
+    pub gates: Vec<GateEntry>,
+    pub wires: Vec<WireEntry>,
+    /// Nets that do not use the default resolution policy, keyed by the gate
+    /// and input driven by the net
+    pub nets: Vec<((usize, usize), Resolution)>,
+    pub subcircuits: SubcircuitMode,
+    /// Library of subcircuits, only present in the top level board
+    pub circuits: Vec<Circuit>,
+}
+
+impl BoardFile {
+    /// Parses a board file of any supported version.
+    pub fn parse(text: &str) -> Result<BoardFile, FileError> {
+        let (header, body) = text.split_once('\n').unwrap_or((text, ""));
+        let version = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            ["logic-sim", version] => version
+                .parse::<usize>()
+                .map_err(|_| FileError(format!("invalid version {version}")))?,
+            _ => return Err(FileError("not a logic-sim board file".to_string())),
+        };
+
+        if !(1..=VERSION).contains(&version) {
+            return Err(FileError(format!("unsupported version {version}")));
+        }
+
+        let mut body = body.to_string();
+        for migrate in &MIGRATIONS[version - 1..] {
+            body = migrate(&body)?;
+        }
+
+        let mut file = BoardFile::default();
+        let mut circuit: Option<Circuit> = None;
+        for (index, line) in body.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let result = match (&fields[..], circuit.as_mut()) {
+                (["circuit", name], None) => Circuit::check_name(name, &file.circuits).map(|()| {
+                    circuit = Some(Circuit {
+                        name: name.to_string(),
+                        board: BoardFile::default(),
+                    })
+                }),
+                (["end"], Some(_)) => {
+                    file.circuits.extend(circuit.take());
+                    Ok(())
+                }
+                (["circuit" | "end" | "mode" | "subcircuits", ..], Some(_)) => {
+                    Err(FileError(format!("invalid line in circuit \"{line}\"")))
+                }
+                (_, Some(circuit)) => circuit.board.parse_line(line),
+                (_, None) => file.parse_line(line),
+            };
+
+            // the header is the first line
+            let line_number = index + 2;
+            result.map_err(|FileError(message)| {
+                FileError(format!("line {line_number}: {message}"))
+            })?;
+        }
+
+        if let Some(circuit) = circuit {
+            return Err(FileError(format!("circuit {} has no end", circuit.name)));
+        }
+
+        Ok(file)
+    }
+
+    /// Ids of the `IN` and `OUT` ports of the board, ordered top to bottom,
+    /// as the pins of the board used as a subcircuit.
+    pub fn ports(&self) -> (Vec<usize>, Vec<usize>) {
+        let ports = |kind: &str| {
+            let mut ports: Vec<_> = self.gates.iter().filter(|gate| gate.kind == kind).collect();
+            ports.sort_by(|a, b| {
+                let (ax, ay) = a.pos;
+                let (bx, by) = b.pos;
+                ay.total_cmp(&by).then(ax.total_cmp(&bx))
+            });
+            ports.into_iter().map(|gate| gate.id).collect()
+        };
+
+        (ports("IN"), ports("OUT"))
+    }
+
+    /// Adds the gates, wires and nets of the board to the simulation, with
+    /// subcircuits from given library. Returns the ids of the added gates
+    /// keyed by their ids in the file, and the connections added for the
+    /// wires in the order of the wires.
+    pub fn build(
+        &self,
+        sim: &mut LogicSimulation,
+        library: &[Circuit],
+        mode: SubcircuitMode,
+    ) -> Result<(BTreeMap<usize, GateId>, Vec<ConnectionId>), FileError> {
+        let mut ids = BTreeMap::new();
+        for gate in &self.gates {
+            let id = add_gate(sim, library, mode, &gate.kind, &gate.params)?;
+            sim.set_gate_delay(id, gate.delay)?;
+            if ids.insert(gate.id, id).is_some() {
+                return Err(FileError(format!("duplicate gate {}", gate.id)));
+            }
+        }
+
+        let mut connections = Vec::new();
+        for wire in &self.wires {
+            let output = sim_output(sim, &ids, wire.output)?;
+            let input = sim_input(sim, &ids, wire.input)?;
+            let connection = sim
+                .add_connection(output, input)
+                .map_err(|error| match error {
+                    SimulationError::WidthMismatch { .. } => FileError(format!(
+                        "wire {}.{} {}.{} connects pins of different widths",
+                        wire.output.0, wire.output.1, wire.input.0, wire.input.1
+                    )),
+                    error => error.into(),
+                })?;
+            connections.push(connection);
+        }
+
+        for (input, resolution) in &self.nets {
+            let input = sim_input(sim, &ids, *input)?;
+            sim.set_resolution(input, *resolution)?;
+        }
+
+        Ok((ids, connections))
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), FileError> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields[..] {
+            [] => {}
+            [comment, ..] if comment.starts_with('#') => {}
+            ["mode", mode] => {
+                self.mode = match mode {
+                    "tick" => SimulationMode::Tick,
+                    "event" => SimulationMode::Event,
+                    _ => return Err(FileError(format!("unknown mode {mode}"))),
+                }
+            }
+            ["nets", resolution] => self.default_resolution = parse_resolution(resolution)?,
+            ["subcircuits", mode] => {
+                self.subcircuits = match mode {
+                    "nested" => SubcircuitMode::Nested,
+                    "flattened" => SubcircuitMode::Flattened,
+                    _ => return Err(FileError(format!("unknown subcircuit mode {mode}"))),
+                }
+            }
+            ["gate", id, kind, x, y, delay, ref params @ ..] => self.gates.push(GateEntry {
+                id: parse_number(id)?,
+                kind: kind.to_string(),
+                pos: (parse_number(x)?, parse_number(y)?),
+                delay: parse_number(delay)?,
+                params: params
+                    .iter()
+                    .map(|param| parse_number(param))
+                    .collect::<Result<_, _>>()?,
+            }),
+            ["wire", output, input, ref waypoints @ ..] => self.wires.push(WireEntry {
+                output: parse_pin(output)?,
+                input: parse_pin(input)?,
+                waypoints: waypoints
+                    .iter()
+                    .map(|point| parse_point(point))
+                    .collect::<Result<_, _>>()?,
+            }),
+            ["net", input, resolution] => self
+                .nets
+                .push((parse_pin(input)?, parse_resolution(resolution)?)),
+            _ => return Err(FileError(format!("invalid line \"{line}\""))),
+        }
+
+        Ok(())
+    }
+}
+
+/// Looks up an input referred to by a file in the simulation.
+pub fn sim_input(
+    sim: &LogicSimulation,
+    ids: &BTreeMap<usize, GateId>,
+    pin: (usize, usize),
+) -> Result<InputPin, FileError> {
+    Ok(sim_gate(sim, ids, pin, false)?.input(pin.1))
+}
+
+/// Looks up an output referred to by a file in the simulation.
+pub fn sim_output(
+    sim: &LogicSimulation,
+    ids: &BTreeMap<usize, GateId>,
+    pin: (usize, usize),
+) -> Result<OutputPin, FileError> {
+    Ok(sim_gate(sim, ids, pin, true)?.output(pin.1))
+}
+
+/// Looks up the gate of a pin referred to by a file and checks that the pin
+/// exists.
+fn sim_gate(
+    sim: &LogicSimulation,
+    ids: &BTreeMap<usize, GateId>,
+    (gate, pin): (usize, usize),
+    output: bool,
+) -> Result<GateId, FileError> {
+    let id = *ids
+        .get(&gate)
+        .ok_or_else(|| FileError(format!("unknown gate {gate}")))?;
+    let (inputs, outputs) = sim.get_gate_state(id)?;
+    let pins = if output { outputs.len() } else { inputs.len() };
+    if pin >= pins {
+        return Err(FileError(format!("gate {gate} has no pin {pin}")));
+    }
+
+    Ok(id)
+}
+
+fn parse_number<T: std::str::FromStr>(field: &str) -> Result<T, FileError> {
+    field
+        .parse()
+        .map_err(|_| FileError(format!("invalid number {field}")))
+}
+
+fn parse_pin(field: &str) -> Result<(usize, usize), FileError> {
+    let (gate, pin) = field
+        .split_once('.')
+        .ok_or_else(|| FileError(format!("invalid pin {field}")))?;
+    Ok((parse_number(gate)?, parse_number(pin)?))
+}
+
+fn parse_point(field: &str) -> Result<(f32, f32), FileError> {
+    let (x, y) = field
+        .split_once(',')
+        .ok_or_else(|| FileError(format!("invalid point {field}")))?;
+    Ok((parse_number(x)?, parse_number(y)?))
+}
+
+fn parse_resolution(field: &str) -> Result<Resolution, FileError> {
+    Resolution::from_name(field).ok_or_else(|| FileError(format!("unknown resolution {field}")))
+}
+
+impl fmt::Display for BoardFile {
+    /// Writes the board in the current version of the format.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "logic-sim {VERSION}")?;
+        let mode = match self.mode {
+            SimulationMode::Tick => "tick",
+            SimulationMode::Event => "event",
+        };
+        writeln!(f, "mode {mode}")?;
+        writeln!(f, "subcircuits {}", self.subcircuits.name())?;
+
+        for circuit in &self.circuits {
+            writeln!(f, "circuit {}", circuit.name)?;
+            circuit.board.write_items(f)?;
+            writeln!(f, "end")?;
+        }
+
+        self.write_items(f)
+    }
+}
+
+impl BoardFile {
+    /// Writes the contents of the board, shared by the top level board and
+    /// subcircuits.
+    fn write_items(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "nets {}", self.default_resolution.name())?;
+
+        for gate in &self.gates {
+            let (x, y) = gate.pos;
+            write!(f, "gate {} {} {x} {y} {}", gate.id, gate.kind, gate.delay)?;
+            for param in &gate.params {
+                write!(f, " {param}")?;
+            }
+            writeln!(f)?;
+        }
+
+        for wire in &self.wires {
+            let (output, input) = (wire.output, wire.input);
+            write!(f, "wire {}.{} {}.{}", output.0, output.1, input.0, input.1)?;
+            for (x, y) in &wire.waypoints {
+                write!(f, " {x},{y}")?;
+            }
+            writeln!(f)?;
+        }
+
+        for ((gate, input), resolution) in &self.nets {
+            writeln!(f, "net {gate}.{input} {}", resolution.name())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the kind is the name of a gate implemented in [`crate::gates`].
+pub fn is_builtin(kind: &str) -> bool {
+    matches!(
+        kind,
+        "AND"
+            | "NAND"
+            | "OR"
+            | "NOR"
+            | "XOR"
+            | "XNOR"
+            | "NOT"
+            | "YES"
+            | "HIGH"
+            | "LOW"
+            | "SW"
+            | "BTN"
+            | "LED"
+            | "7SEG"
+            | "HEX"
+            | "LUT"
+            | "SR"
+            | "DFF"
+            | "JKFF"
+            | "TFF"
+            | "CLK"
+            | "SPLIT"
+            | "MERGE"
+            | "IN"
+            | "OUT"
+    )
+}
+
+/// Adds a gate of given kind with parameters returned by [`Gate::params`] to
+/// the simulation, kinds that are not built in are looked up in the library
+/// of subcircuits. Returns the id of the gate.
+pub fn add_gate(
+    sim: &mut LogicSimulation,
+    library: &[Circuit],
+    mode: SubcircuitMode,
+    kind: &str,
+    params: &[u64],
+) -> Result<GateId, FileError> {
+    let invalid = || FileError(format!("invalid parameters of {kind}"));
+    let width = || match params {
+        [width] if (1..=64).contains(width) => Ok(*width as usize),
+        _ => Err(invalid()),
+    };
+    let parts = || {
+        // widths are checked one by one first, so huge widths cannot
+        // overflow the total
+        let valid =
+            params.iter().all(|width| (1..=64).contains(width)) && params.iter().sum::<u64>() <= 64;
+        if valid {
+            Ok(params
+                .iter()
+                .map(|width| *width as usize)
+                .collect::<Vec<_>>())
+        } else {
+            Err(invalid())
+        }
+    };
+
+    let id = match (kind, params) {
+        ("AND", []) => sim.add_gate(And),
+        ("AND", _) => sim.add_gate(Wide::new(And, width()?)),
+        ("NAND", []) => sim.add_gate(Nand),
+        ("OR", []) => sim.add_gate(Or),
+        ("OR", _) => sim.add_gate(Wide::new(Or, width()?)),
+        ("NOR", []) => sim.add_gate(Nor),
+        ("XOR", []) => sim.add_gate(Xor),
+        ("XOR", _) => sim.add_gate(Wide::new(Xor, width()?)),
+        ("XNOR", []) => sim.add_gate(Xnor),
+        ("NOT", []) => sim.add_gate(Not),
+        ("NOT", _) => sim.add_gate(Wide::new(Not, width()?)),
+        ("YES", []) => sim.add_gate(Yes),
+        ("IN", _) => sim.add_gate(InputPort::new(width()?)),
+        ("OUT", _) => sim.add_gate(OutputPort::new(width()?)),
+        ("HIGH", []) => sim.add_gate(High),
+        ("LOW", []) => sim.add_gate(Low),
+        ("SW", []) => sim.add_gate(Switch::default()),
+        ("BTN", []) => sim.add_gate(Button::default()),
+        ("LED", []) => sim.add_gate(Led),
+        ("7SEG", []) => sim.add_gate(SevenSegment),
+        ("HEX", []) => sim.add_gate(HexDisplay),
+        ("LUT", [inputs, table]) => match inputs {
+            1 => sim.add_gate(Lut::<1>::new(*table)),
+            2 => sim.add_gate(Lut::<2>::new(*table)),
+            3 => sim.add_gate(Lut::<3>::new(*table)),
+            4 => sim.add_gate(Lut::<4>::new(*table)),
+            5 => sim.add_gate(Lut::<5>::new(*table)),
+            6 => sim.add_gate(Lut::<6>::new(*table)),
+            _ => return Err(invalid()),
+        },
+        ("SR", []) => sim.add_gate(SrLatch::default()),
+        ("DFF", []) => sim.add_gate(DFlipFlop::default()),
+        ("JKFF", []) => sim.add_gate(JkFlipFlop::default()),
+        ("TFF", []) => sim.add_gate(TFlipFlop::default()),
+        ("CLK", [period, duty_cycle, phase]) if *period >= 2 && *duty_cycle <= 100 => {
+            sim.add_gate(Clock::new(*period, *duty_cycle as u8, *phase))
+        }
+        ("SPLIT", _) => {
+            let widths = parts()?;
+            match widths.len() {
+                2 => sim.add_gate(Splitter::<2>::new(widths.try_into().unwrap())),
+                4 => sim.add_gate(Splitter::<4>::new(widths.try_into().unwrap())),
+                8 => sim.add_gate(Splitter::<8>::new(widths.try_into().unwrap())),
+                16 => sim.add_gate(Splitter::<16>::new(widths.try_into().unwrap())),
+                _ => return Err(invalid()),
+            }
+        }
+        ("MERGE", _) => {
+            let widths = parts()?;
+            match widths.len() {
+                2 => sim.add_gate(Merger::<2>::new(widths.try_into().unwrap())),
+                4 => sim.add_gate(Merger::<4>::new(widths.try_into().unwrap())),
+                8 => sim.add_gate(Merger::<8>::new(widths.try_into().unwrap())),
+                16 => sim.add_gate(Merger::<16>::new(widths.try_into().unwrap())),
+                _ => return Err(invalid()),
+            }
+        }
+        _ if is_builtin(kind) => return Err(invalid()),
+        (_, []) => {
+            // each circuit can only use the circuits defined before it, so
+            // circuits cannot contain themselves
+            let index = library
+                .iter()
+                .position(|circuit| circuit.name == kind)
+                .ok_or_else(|| FileError(format!("unknown gate kind {kind}")))?;
+            library[index].instantiate(sim, &library[..index], mode)?
+        }
+        _ => return Err(invalid()),
+    };
+
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD: &str = "logic-sim 1
+mode event
+nets wired-or
+# a clocked flip-flop
+gate 0 CLK 10 20.5 1 4 50 1
+gate 3 DFF 200 20 2
+gate 4 AND 300 20 2 8
+
+wire 0.0 3.1
+net 3.0 exclusive
+";
+
+    const BOARD_V3: &str = "logic-sim 3
+gate 0 SW 0 0 1
+gate 1 LED 200 100 1
+wire 0.0 1.0 100,15 100.5,140
+";
+
+    #[test]
+    fn parse() {
+        let file = BoardFile::parse(BOARD).unwrap();
+        assert_eq!(file.mode, SimulationMode::Event);
+        assert_eq!(file.default_resolution, Resolution::WiredOr);
+        assert_eq!(file.gates.len(), 3);
+        assert_eq!(
+            file.gates[0],
+            GateEntry {
+                id: 0,
+                kind: "CLK".to_string(),
+                pos: (10., 20.5),
+                delay: 1,
+                params: vec![4, 50, 1],
+            }
+        );
+        assert_eq!(
+            file.wires,
+            [WireEntry {
+                output: (0, 0),
+                input: (3, 1),
+                waypoints: Vec::new(),
+            }]
+        );
+        assert_eq!(file.nets, [((3, 0), Resolution::Exclusive)]);
+
+        let file = BoardFile::parse(BOARD_V3).unwrap();
+        assert_eq!(file.wires[0].waypoints, [(100., 15.), (100.5, 140.)]);
+        assert_eq!(BoardFile::parse(&file.to_string()).unwrap(), file);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let file = BoardFile::parse(BOARD).unwrap();
+        assert_eq!(BoardFile::parse(&file.to_string()).unwrap(), file);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |text: &str| BoardFile::parse(text).unwrap_err().0;
+        assert_eq!(error("board 1\n"), "not a logic-sim board file");
+        assert_eq!(error("logic-sim 0\n"), "unsupported version 0");
+        assert_eq!(
+            error(&format!("logic-sim {}\n", VERSION + 1)),
+            format!("unsupported version {}", VERSION + 1)
+        );
+        assert_eq!(error("logic-sim 1\n\nwire 1 2.0"), "line 3: invalid pin 1");
+        assert_eq!(
+            error("logic-sim 3\nwire 0.0 1.0 5"),
+            "line 2: invalid point 5"
+        );
+        assert_eq!(
+            error("logic-sim 1\nnets any"),
+            "line 2: unknown resolution any"
+        );
+        assert_eq!(
+            error("logic-sim 2\ncircuit A\ngate 0 IN 0 0 1 1"),
+            "circuit A has no end"
+        );
+        assert_eq!(
+            error("logic-sim 2\ncircuit A\nmode tick\nend"),
+            "line 3: invalid line in circuit \"mode tick\""
+        );
+        assert_eq!(
+            error("logic-sim 2\ncircuit AND\nend"),
+            "line 2: circuit name AND is already used"
+        );
+    }
+
+    #[test]
+    fn gate_kinds() {
+        let mut sim = LogicSimulation::new();
+        let id = add_gate(&mut sim, &[], SubcircuitMode::Nested, "XOR", &[8]).unwrap();
+        assert_eq!(sim.get_gate_name(id).unwrap(), "XOR");
+        assert_eq!(sim.get_gate_params(id).unwrap(), [8]);
+        assert_eq!(sim.get_gate_state(id).unwrap().0[0].width(), 8);
+
+        let id = add_gate(&mut sim, &[], SubcircuitMode::Nested, "SPLIT", &[4, 4]).unwrap();
+        assert_eq!(sim.get_gate_params(id).unwrap(), [4, 4]);
+
+        let id = add_gate(&mut sim, &[], SubcircuitMode::Nested, "CLK", &[6, 50, 2]).unwrap();
+        assert_eq!(sim.get_gate_params(id).unwrap(), [6, 50, 2]);
+
+        assert!(add_gate(&mut sim, &[], SubcircuitMode::Nested, "CLK", &[1, 50, 0]).is_err());
+        assert!(add_gate(&mut sim, &[], SubcircuitMode::Nested, "NAND", &[2]).is_err());
+        assert!(add_gate(&mut sim, &[], SubcircuitMode::Nested, "AND", &[65]).is_err());
+        let huge = [u64::MAX, 2];
+        assert!(add_gate(&mut sim, &[], SubcircuitMode::Nested, "MERGE", &huge).is_err());
+        assert!(add_gate(&mut sim, &[], SubcircuitMode::Nested, "FOO", &[]).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    fmt,
+    ops::{BitAnd, BitOr, BitXor, Not},
+};
+
+use crate::signal::Signal;
+
+/// Value of a pin or wire carrying one or more bits, each bit is a four-state
+/// [`Signal`].
+///
+/// The bits are stored in two planes, for each bit the pair of `value` and
+/// `unknown` bits encodes low (0, 0), high (1, 0), unknown (1, 1) and high
+/// impedance (0, 1). Bitwise operators work on buses of the same width.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Bus {
+    width: u8,
+    value: u64,
+    unknown: u64,
+}
+
+impl Bus {
+    pub const MAX_WIDTH: usize = 64;
+
+    /// Bus with all bits set to the same signal.
+    pub fn new(width: usize, signal: Signal) -> Bus {
+        let all = mask(width);
+        let (value, unknown) = match signal {
+            Signal::Low => (0, 0),
+            Signal::High => (all, 0),
+            Signal::Unknown => (all, all),
+            Signal::HighZ => (0, all),
+        };
+
+        Bus {
+            value,
+            unknown,
+            ..Bus::from_u64(width, 0)
+        }
+    }
+
+    /// Bus holding a known value, bits above the width are ignored.
+    pub fn from_u64(width: usize, value: u64) -> Bus {
+        assert!(
+            (1..=Bus::MAX_WIDTH).contains(&width),
+            "invalid bus width {width}"
+        );
+        Bus {
+            width: width as u8,
+            value: value & mask(width),
+            unknown: 0,
+        }
+    }
+
+    /// Bus made of given bits, the first bit is the least significant one.
+    pub fn from_bits(bits: impl IntoIterator<Item = Signal>) -> Bus {
+        let mut bus = Bus {
+            width: 0,
+            value: 0,
+            unknown: 0,
+        };
+
+        for (index, signal) in bits.into_iter().enumerate() {
+            assert!(
+                index < Bus::MAX_WIDTH,
+                "bus wider than {} bits",
+                Bus::MAX_WIDTH
+            );
+            bus.width += 1;
+            bus.set_bit(index, signal);
+        }
+
+        assert!(bus.width > 0, "bus without bits");
+        bus
+    }
+
+    pub fn width(&self) -> usize {
+        self.width as usize
+    }
+
+    /// Returns given bit, bits outside of the bus are in high impedance.
+    pub fn bit(&self, index: usize) -> Signal {
+        if index >= self.width() {
+            return Signal::HighZ;
+        }
+
+        let value = (self.value >> index) & 1 == 1;
+        let unknown = (self.unknown >> index) & 1 == 1;
+        match (value, unknown) {
+            (false, false) => Signal::Low,
+            (true, false) => Signal::High,
+            (true, true) => Signal::Unknown,
+            (false, true) => Signal::HighZ,
+        }
+    }
+
+    pub fn set_bit(&mut self, index: usize, signal: Signal) {
+        let (value, unknown) = match signal {
+            Signal::Low => (0, 0),
+            Signal::High => (1, 0),
+            Signal::Unknown => (1, 1),
+            Signal::HighZ => (0, 1),
+        };
+
+        self.value = (self.value & !(1 << index)) | (value << index);
+        self.unknown = (self.unknown & !(1 << index)) | (unknown << index);
+    }
+
+    pub fn bits(&self) -> impl Iterator<Item = Signal> + '_ {
+        (0..self.width()).map(|index| self.bit(index))
+    }
+
+    /// Numeric value of the bus, if all of its bits are known.
+    pub fn to_u64(self) -> Option<u64> {
+        if self.unknown == 0 {
+            Some(self.value)
+        } else {
+            None
+        }
+    }
+
+    /// Value as seen by a gate input, bits in high impedance read as unknown.
+    pub fn driven(self) -> Bus {
+        Bus {
+            value: self.value | self.unknown,
+            ..self
+        }
+    }
+
+    fn known_low(&self) -> u64 {
+        !self.value & !self.unknown & mask(self.width())
+    }
+
+    fn known_high(&self) -> u64 {
+        self.value & !self.unknown
+    }
+
+    /// Builds a bus from masks of bits known to be low and high, all other
+    /// bits are unknown.
+    fn from_known(width: usize, low: u64, high: u64) -> Bus {
+        let unknown = !(low | high) & mask(width);
+        Bus {
+            width: width as u8,
+            value: high | unknown,
+            unknown,
+        }
+    }
+}
+
+fn mask(width: usize) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+impl From<Signal> for Bus {
+    fn from(signal: Signal) -> Self {
+        Bus::new(1, signal)
+    }
+}
+
+impl fmt::Display for Bus {
+    /// Single bits are shown as the signal, known values in hex and anything
+    /// else in binary, most significant bit first.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.width() == 1 {
+            return write!(f, "{}", self.bit(0));
+        }
+
+        if let Some(value) = self.to_u64() {
+            let digits = self.width().div_ceil(4);
+            return write!(f, "0x{value:0digits$x}");
+        }
+
+        for index in (0..self.width()).rev() {
+            write!(f, "{}", self.bit(index))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Not for Bus {
+    type Output = Bus;
+
+    fn not(self) -> Bus {
+        Bus::from_known(self.width(), self.known_high(), self.known_low())
+    }
+}
+
+impl BitAnd for Bus {
+    type Output = Bus;
+
+    fn bitand(self, rhs: Bus) -> Bus {
+        // a single low bit decides the result even if the other is unknown
+        Bus::from_known(
+            self.width().max(rhs.width()),
+            self.known_low() | rhs.known_low(),
+            self.known_high() & rhs.known_high(),
+        )
+    }
+}
+
+impl BitOr for Bus {
+    type Output = Bus;
+
+    fn bitor(self, rhs: Bus) -> Bus {
+        // a single high bit decides the result even if the other is unknown
+        Bus::from_known(
+            self.width().max(rhs.width()),
+            self.known_low() & rhs.known_low(),
+            self.known_high() | rhs.known_high(),
+        )
+    }
+}
+
+impl BitXor for Bus {
+    type Output = Bus;
+
+    fn bitxor(self, rhs: Bus) -> Bus {
+        let known = (self.known_low() | self.known_high()) & (rhs.known_low() | rhs.known_high());
+        let high = (self.known_high() ^ rhs.known_high()) & known;
+        Bus::from_known(self.width().max(rhs.width()), known & !high, high)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNALS: [Signal; 4] = [Signal::Low, Signal::High, Signal::Unknown, Signal::HighZ];
+
+    #[test]
+    fn bits_roundtrip() {
+        let bus = Bus::from_bits(SIGNALS);
+        assert_eq!(bus.width(), 4);
+        assert_eq!(bus.bits().collect::<Vec<_>>(), SIGNALS);
+        assert_eq!(bus.bit(4), Signal::HighZ);
+    }
+
+    #[test]
+    fn operators_match_signal_operators() {
+        for a in SIGNALS {
+            assert_eq!(!Bus::from(a), Bus::from(!a));
+
+            for b in SIGNALS {
+                assert_eq!(Bus::from(a) & Bus::from(b), Bus::from(a & b));
+                assert_eq!(Bus::from(a) | Bus::from(b), Bus::from(a | b));
+                assert_eq!(Bus::from(a) ^ Bus::from(b), Bus::from(a ^ b));
+            }
+        }
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Bus::from(Signal::High).to_string(), "1");
+        assert_eq!(Bus::from_u64(8, 0x3f).to_string(), "0x3f");
+        assert_eq!(Bus::from_u64(12, 0x3f).to_string(), "0x03f");
+        assert_eq!(Bus::from_bits(SIGNALS).to_string(), "ZX10");
+    }
+
+    #[test]
+    fn full_width() {
+        let bus = Bus::from_u64(64, u64::MAX);
+        assert_eq!((!bus).to_u64(), Some(0));
+        assert_eq!((bus ^ Bus::from_u64(64, 1)).to_u64(), Some(u64::MAX - 1));
+    }
+}
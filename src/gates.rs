@@ -1,91 +1,809 @@
+use crate::{bus::Bus, signal::Signal};
+
+/// Information about the simulation step in which a gate is updated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Context {
+    tick: u64,
+}
+
+impl Context {
+    pub fn new(tick: u64) -> Context {
+        Context { tick }
+    }
+
+    /// Current simulation time, starting at one for the first step after the
+    /// simulation was created or reset.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+}
+
+/// Component with fixed number of inputs and outputs.
+///
+/// Gates may keep internal state between updates, e.g. flip-flops remember
+/// their value and counters their count, the state is cleared by
+/// [`Gate::reset`].
 pub trait Gate<const INPUTS: usize, const OUTPUTS: usize> {
     const NAME: &'static str;
 
-    fn update(&self, inputs: &[bool; INPUTS], outputs: &mut [bool; OUTPUTS]);
+    /// Propagation delay in time units, used by the event-driven simulation.
+    const DELAY: u64 = 1;
+
+    fn update(&mut self, ctx: &Context, inputs: &[Bus; INPUTS], outputs: &mut [Bus; OUTPUTS]);
+
+    /// Returns the gate to its initial state.
+    fn reset(&mut self) {}
+
+    /// Whether the gate has to be updated on every tick even if its inputs did
+    /// not change, e.g. because its outputs depend on time.
+    fn needs_tick(&self) -> bool {
+        false
+    }
+
+    /// Whether users can interact with the gate on the board, e.g. flip a
+    /// switch by clicking it.
+    fn is_interactive(&self) -> bool {
+        false
+    }
+
+    /// Called when the user presses the gate on the board.
+    fn press(&mut self) {}
+
+    /// Called when the user releases the previously pressed gate.
+    fn release(&mut self) {}
 
     fn name(&self) -> &'static str {
         Self::NAME
     }
+
+    fn delay(&self) -> u64 {
+        Self::DELAY
+    }
+
+    /// Per-instance parameters needed to create the same gate again, e.g.
+    /// when the gate is saved to a file.
+    fn params(&self) -> Vec<u64> {
+        Vec::new()
+    }
+
+    /// Number of bits carried by each input, single bit by default.
+    fn input_widths(&self) -> [usize; INPUTS] {
+        [1; INPUTS]
+    }
+
+    /// Number of bits carried by each output, single bit by default.
+    fn output_widths(&self) -> [usize; OUTPUTS] {
+        [1; OUTPUTS]
+    }
 }
 
+/// Gates that apply the same operation to every bit of their pins and thus can
+/// be widened to operate on whole buses with [`Wide`].
+pub trait Bitwise {}
+
 pub struct And;
 
+impl Bitwise for And {}
+
 impl Gate<2, 1> for And {
     const NAME: &'static str = "AND";
+    const DELAY: u64 = 2;
 
-    fn update(&self, inputs: &[bool; 2], outputs: &mut [bool; 1]) {
-        outputs[0] = inputs[0] && inputs[1];
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 2], outputs: &mut [Bus; 1]) {
+        outputs[0] = inputs[0] & inputs[1];
     }
 }
 
 pub struct Nand;
 
+impl Bitwise for Nand {}
+
 impl Gate<2, 1> for Nand {
     const NAME: &'static str = "NAND";
 
-    fn update(&self, inputs: &[bool; 2], outputs: &mut [bool; 1]) {
-        outputs[0] = !(inputs[0] && inputs[1]);
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 2], outputs: &mut [Bus; 1]) {
+        outputs[0] = !(inputs[0] & inputs[1]);
     }
 }
 
 pub struct Or;
 
+impl Bitwise for Or {}
+
 impl Gate<2, 1> for Or {
     const NAME: &'static str = "OR";
+    const DELAY: u64 = 2;
 
-    fn update(&self, inputs: &[bool; 2], outputs: &mut [bool; 1]) {
-        outputs[0] = inputs[0] || inputs[1];
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 2], outputs: &mut [Bus; 1]) {
+        outputs[0] = inputs[0] | inputs[1];
     }
 }
 
 pub struct Nor;
 
+impl Bitwise for Nor {}
+
 impl Gate<2, 1> for Nor {
     const NAME: &'static str = "NOR";
 
-    fn update(&self, inputs: &[bool; 2], outputs: &mut [bool; 1]) {
-        outputs[0] = !(inputs[0] || inputs[1]);
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 2], outputs: &mut [Bus; 1]) {
+        outputs[0] = !(inputs[0] | inputs[1]);
     }
 }
 
 pub struct Xor;
 
+impl Bitwise for Xor {}
+
 impl Gate<2, 1> for Xor {
     const NAME: &'static str = "XOR";
+    const DELAY: u64 = 3;
 
-    fn update(&self, inputs: &[bool; 2], outputs: &mut [bool; 1]) {
-        outputs[0] = inputs[0] != inputs[1];
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 2], outputs: &mut [Bus; 1]) {
+        outputs[0] = inputs[0] ^ inputs[1];
     }
 }
 
 pub struct Xnor;
 
+impl Bitwise for Xnor {}
+
 impl Gate<2, 1> for Xnor {
     const NAME: &'static str = "XNOR";
+    const DELAY: u64 = 3;
 
-    fn update(&self, inputs: &[bool; 2], outputs: &mut [bool; 1]) {
-        outputs[0] = !(inputs[0] != inputs[1]);
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 2], outputs: &mut [Bus; 1]) {
+        outputs[0] = !(inputs[0] ^ inputs[1]);
     }
 }
 
 pub struct Not;
 
+impl Bitwise for Not {}
+
 impl Gate<1, 1> for Not {
     const NAME: &'static str = "NOT";
 
-    fn update(&self, inputs: &[bool; 1], outputs: &mut [bool; 1]) {
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 1], outputs: &mut [Bus; 1]) {
         outputs[0] = !inputs[0];
     }
 }
 
 pub struct Yes;
 
+impl Bitwise for Yes {}
+
 impl Gate<1, 1> for Yes {
     const NAME: &'static str = "YES";
 
-    fn update(&self, inputs: &[bool; 1], outputs: &mut [bool; 1]) {
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 1], outputs: &mut [Bus; 1]) {
+        outputs[0] = inputs[0].driven();
+    }
+}
+
+/// Bitwise gate operating on buses of given width instead of single bits.
+pub struct Wide<G> {
+    gate: G,
+    width: usize,
+}
+
+impl<G> Wide<G> {
+    pub fn new(gate: G, width: usize) -> Wide<G> {
+        assert!(
+            (1..=Bus::MAX_WIDTH).contains(&width),
+            "invalid bus width {width}"
+        );
+        Wide { gate, width }
+    }
+}
+
+impl<G, const INPUTS: usize, const OUTPUTS: usize> Gate<INPUTS, OUTPUTS> for Wide<G>
+where
+    G: Gate<INPUTS, OUTPUTS> + Bitwise,
+{
+    const NAME: &'static str = G::NAME;
+    const DELAY: u64 = G::DELAY;
+
+    fn update(&mut self, ctx: &Context, inputs: &[Bus; INPUTS], outputs: &mut [Bus; OUTPUTS]) {
+        self.gate.update(ctx, inputs, outputs)
+    }
+
+    fn reset(&mut self) {
+        self.gate.reset()
+    }
+
+    fn needs_tick(&self) -> bool {
+        self.gate.needs_tick()
+    }
+
+    fn params(&self) -> Vec<u64> {
+        vec![self.width as u64]
+    }
+
+    fn input_widths(&self) -> [usize; INPUTS] {
+        [self.width; INPUTS]
+    }
+
+    fn output_widths(&self) -> [usize; OUTPUTS] {
+        [self.width; OUTPUTS]
+    }
+}
+
+/// Splits a bus into parts of given widths, the first output carries the least
+/// significant bits. Bits are passed as they are, including high impedance.
+pub struct Splitter<const PARTS: usize> {
+    widths: [usize; PARTS],
+}
+
+impl<const PARTS: usize> Splitter<PARTS> {
+    pub fn new(widths: [usize; PARTS]) -> Splitter<PARTS> {
+        assert!(widths.iter().all(|width| *width > 0), "empty part");
+        assert!(
+            widths.iter().sum::<usize>() <= Bus::MAX_WIDTH,
+            "bus too wide"
+        );
+        Splitter { widths }
+    }
+
+    /// Splits a bus of `PARTS` bits into single bits.
+    pub fn bits() -> Splitter<PARTS> {
+        Splitter::new([1; PARTS])
+    }
+}
+
+impl<const PARTS: usize> Gate<1, PARTS> for Splitter<PARTS> {
+    const NAME: &'static str = "SPLIT";
+
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 1], outputs: &mut [Bus; PARTS]) {
+        let mut bits = inputs[0].bits();
+        for (output, width) in outputs.iter_mut().zip(self.widths) {
+            *output = Bus::from_bits(bits.by_ref().take(width));
+        }
+    }
+
+    fn params(&self) -> Vec<u64> {
+        self.widths.iter().map(|width| *width as u64).collect()
+    }
+
+    fn input_widths(&self) -> [usize; 1] {
+        [self.widths.iter().sum()]
+    }
+
+    fn output_widths(&self) -> [usize; PARTS] {
+        self.widths
+    }
+}
+
+/// Merges buses of given widths into a single bus, the first input provides
+/// the least significant bits.
+pub struct Merger<const PARTS: usize> {
+    widths: [usize; PARTS],
+}
+
+impl<const PARTS: usize> Merger<PARTS> {
+    pub fn new(widths: [usize; PARTS]) -> Merger<PARTS> {
+        assert!(widths.iter().all(|width| *width > 0), "empty part");
+        assert!(
+            widths.iter().sum::<usize>() <= Bus::MAX_WIDTH,
+            "bus too wide"
+        );
+        Merger { widths }
+    }
+
+    /// Merges `PARTS` single bits into a bus.
+    pub fn bits() -> Merger<PARTS> {
+        Merger::new([1; PARTS])
+    }
+}
+
+impl<const PARTS: usize> Gate<PARTS, 1> for Merger<PARTS> {
+    const NAME: &'static str = "MERGE";
+
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; PARTS], outputs: &mut [Bus; 1]) {
+        let bits = inputs
+            .iter()
+            .zip(self.widths)
+            .flat_map(|(input, width)| (0..width).map(move |index| input.bit(index)));
+        outputs[0] = Bus::from_bits(bits);
+    }
+
+    fn params(&self) -> Vec<u64> {
+        self.widths.iter().map(|width| *width as u64).collect()
+    }
+
+    fn input_widths(&self) -> [usize; PARTS] {
+        self.widths
+    }
+
+    fn output_widths(&self) -> [usize; 1] {
+        [self.widths.iter().sum()]
+    }
+}
+
+/// Value stored by the flip-flops, updated on the rising edge of the clock.
+#[derive(Default)]
+struct FlipFlopState {
+    q: Signal,
+    clock: Signal,
+}
+
+impl FlipFlopState {
+    /// Applies asynchronous set and reset, which take precedence over the
+    /// clock, or computes the next value from the current one on the rising
+    /// edge of the clock. Unconnected set and reset inputs are inactive.
+    fn update(&mut self, clock: Bus, set: Bus, reset: Bus, next: impl FnOnce(Signal) -> Signal) {
+        let inactive = |bus: Bus| match bus.bit(0) {
+            Signal::HighZ => Signal::Low,
+            signal => signal,
+        };
+
+        let clock = clock.bit(0).driven();
+        let previous_clock = std::mem::replace(&mut self.clock, clock);
+
+        self.q = match (inactive(set), inactive(reset)) {
+            (Signal::Low, Signal::Low) => {
+                if previous_clock == Signal::Low && clock == Signal::High {
+                    next(self.q)
+                } else if previous_clock != Signal::High
+                    && clock != Signal::Low
+                    && previous_clock != clock
+                {
+                    // the clock may or may not have risen, the value is only
+                    // known if it would not change
+                    let next = next(self.q);
+                    if next == self.q {
+                        next
+                    } else {
+                        Signal::Unknown
+                    }
+                } else {
+                    self.q
+                }
+            }
+            (Signal::High, Signal::Low) => Signal::High,
+            (Signal::Low, Signal::High) => Signal::Low,
+            _ => Signal::Unknown,
+        };
+    }
+
+    fn write(&self, outputs: &mut [Bus; 2]) {
+        outputs[0] = self.q.into();
+        outputs[1] = (!self.q).into();
+    }
+}
+
+/// Edge-triggered D flip-flop, inputs are D, clock, set and reset, outputs are
+/// Q and inverted Q.
+#[derive(Default)]
+pub struct DFlipFlop {
+    state: FlipFlopState,
+}
+
+impl Gate<4, 2> for DFlipFlop {
+    const NAME: &'static str = "DFF";
+
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 4], outputs: &mut [Bus; 2]) {
+        let [d, clock, set, reset] = *inputs;
+        self.state.update(clock, set, reset, |_| d.bit(0).driven());
+        self.state.write(outputs);
+    }
+
+    fn reset(&mut self) {
+        self.state = FlipFlopState::default();
+    }
+}
+
+/// Edge-triggered JK flip-flop, inputs are J, K, clock, set and reset,
+/// outputs are Q and inverted Q.
+#[derive(Default)]
+pub struct JkFlipFlop {
+    state: FlipFlopState,
+}
+
+impl Gate<5, 2> for JkFlipFlop {
+    const NAME: &'static str = "JKFF";
+
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 5], outputs: &mut [Bus; 2]) {
+        let [j, k, clock, set, reset] = *inputs;
+        self.state.update(clock, set, reset, |q| {
+            let (j, k) = (j.bit(0), k.bit(0));
+            (j & !q) | (!k & q)
+        });
+        self.state.write(outputs);
+    }
+
+    fn reset(&mut self) {
+        self.state = FlipFlopState::default();
+    }
+}
+
+/// Edge-triggered T flip-flop, inputs are T, clock, set and reset, outputs are
+/// Q and inverted Q.
+#[derive(Default)]
+pub struct TFlipFlop {
+    state: FlipFlopState,
+}
+
+impl Gate<4, 2> for TFlipFlop {
+    const NAME: &'static str = "TFF";
+
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 4], outputs: &mut [Bus; 2]) {
+        let [t, clock, set, reset] = *inputs;
+        self.state.update(clock, set, reset, |q| t.bit(0) ^ q);
+        self.state.write(outputs);
+    }
+
+    fn reset(&mut self) {
+        self.state = FlipFlopState::default();
+    }
+}
+
+/// Level-sensitive SR latch, inputs are set and reset, outputs are Q and
+/// inverted Q. Setting and resetting at the same time makes the value unknown.
+#[derive(Default)]
+pub struct SrLatch {
+    q: Signal,
+}
+
+impl Gate<2, 2> for SrLatch {
+    const NAME: &'static str = "SR";
+
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 2], outputs: &mut [Bus; 2]) {
+        self.q = match (inputs[0].bit(0).driven(), inputs[1].bit(0).driven()) {
+            (Signal::Low, Signal::Low) => self.q,
+            (Signal::High, Signal::Low) => Signal::High,
+            (Signal::Low, Signal::High) => Signal::Low,
+            _ => Signal::Unknown,
+        };
+        outputs[0] = self.q.into();
+        outputs[1] = (!self.q).into();
+    }
+
+    fn reset(&mut self) {
+        self.q = Signal::Unknown;
+    }
+}
+
+/// Lookup table computing any function of up to six inputs. Bit `i` of the
+/// table is the output for the inputs forming the number `i`, the first input
+/// being the least significant bit. Unknown inputs make the output unknown,
+/// unless the table gives the same value for all their possible values.
+pub struct Lut<const INPUTS: usize> {
+    table: u64,
+}
+
+impl<const INPUTS: usize> Lut<INPUTS> {
+    pub fn new(table: u64) -> Lut<INPUTS> {
+        assert!(INPUTS <= 6, "lookup tables have at most 6 inputs");
+        let rows = 1u32 << INPUTS;
+        Lut {
+            table: table & u64::MAX.checked_shr(64 - rows).unwrap_or(0),
+        }
+    }
+}
+
+impl<const INPUTS: usize> Gate<INPUTS, 1> for Lut<INPUTS> {
+    const NAME: &'static str = "LUT";
+
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; INPUTS], outputs: &mut [Bus; 1]) {
+        let mut known = 0;
+        let mut unknown = 0;
+        for (index, input) in inputs.iter().enumerate() {
+            match input.bit(0).driven() {
+                Signal::High => known |= 1 << index,
+                Signal::Low => {}
+                _ => unknown |= 1 << index,
+            }
+        }
+
+        // go through all subsets of the unknown inputs
+        let value = |row: usize| self.table >> row & 1 == 1;
+        let first = value(known);
+        let mut subset = unknown;
+        while subset != 0 {
+            if value(known | subset) != first {
+                outputs[0] = Signal::Unknown.into();
+                return;
+            }
+            subset = (subset - 1) & unknown;
+        }
+        outputs[0] = Signal::from(first).into();
+    }
+
+    fn params(&self) -> Vec<u64> {
+        vec![INPUTS as u64, self.table]
+    }
+}
+
+/// Source driving its output high.
+pub struct High;
+
+impl Gate<0, 1> for High {
+    const NAME: &'static str = "HIGH";
+
+    fn update(&mut self, _ctx: &Context, _inputs: &[Bus; 0], outputs: &mut [Bus; 1]) {
+        outputs[0] = Signal::High.into();
+    }
+}
+
+/// Source driving its output low.
+pub struct Low;
+
+impl Gate<0, 1> for Low {
+    const NAME: &'static str = "LOW";
+
+    fn update(&mut self, _ctx: &Context, _inputs: &[Bus; 0], outputs: &mut [Bus; 1]) {
+        outputs[0] = Signal::Low.into();
+    }
+}
+
+/// Toggle switch, each press flips its output between low and high.
+#[derive(Default)]
+pub struct Switch {
+    on: bool,
+}
+
+impl Gate<0, 1> for Switch {
+    const NAME: &'static str = "SW";
+
+    fn update(&mut self, _ctx: &Context, _inputs: &[Bus; 0], outputs: &mut [Bus; 1]) {
+        outputs[0] = Signal::from(self.on).into();
+    }
+
+    fn reset(&mut self) {
+        self.on = false;
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+
+    fn press(&mut self) {
+        self.on = !self.on;
+    }
+}
+
+/// Momentary push button, its output is high only while it is held.
+#[derive(Default)]
+pub struct Button {
+    pressed: bool,
+}
+
+impl Gate<0, 1> for Button {
+    const NAME: &'static str = "BTN";
+
+    fn update(&mut self, _ctx: &Context, _inputs: &[Bus; 0], outputs: &mut [Bus; 1]) {
+        outputs[0] = Signal::from(self.pressed).into();
+    }
+
+    fn reset(&mut self) {
+        self.pressed = false;
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+
+    fn press(&mut self) {
+        self.pressed = true;
+    }
+
+    fn release(&mut self) {
+        self.pressed = false;
+    }
+}
+
+/// Source toggling its output on its own, high for the first part of each
+/// period given by the duty cycle and low for the rest. The phase delays the
+/// whole waveform by given number of ticks.
+pub struct Clock {
+    period: u64,
+    duty_cycle: u8,
+    phase: u64,
+}
+
+impl Clock {
+    /// Creates a clock with period in ticks and duty cycle in percent, the
+    /// period has to be at least two ticks long.
+    pub fn new(period: u64, duty_cycle: u8, phase: u64) -> Clock {
+        assert!(period >= 2, "clock period has to be at least two ticks");
+        assert!(duty_cycle <= 100, "duty cycle is in percent");
+        Clock {
+            period,
+            duty_cycle,
+            phase,
+        }
+    }
+
+    /// Number of ticks the output is high in each period, there is always at
+    /// least one high and one low tick.
+    fn high_ticks(&self) -> u64 {
+        let high = (self.period * self.duty_cycle as u64 + 50) / 100;
+        high.clamp(1, self.period - 1)
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::new(10, 50, 0)
+    }
+}
+
+impl Gate<0, 1> for Clock {
+    const NAME: &'static str = "CLK";
+
+    fn update(&mut self, ctx: &Context, _inputs: &[Bus; 0], outputs: &mut [Bus; 1]) {
+        let shift = self.period - self.phase % self.period;
+        let position = (ctx.tick().saturating_sub(1) + shift) % self.period;
+        outputs[0] = Signal::from(position < self.high_ticks()).into();
+    }
+
+    fn needs_tick(&self) -> bool {
+        true
+    }
+
+    fn params(&self) -> Vec<u64> {
+        vec![self.period, self.duty_cycle as u64, self.phase]
+    }
+}
+
+/// Indicator lit while its input is high.
+pub struct Led;
+
+impl Gate<1, 0> for Led {
+    const NAME: &'static str = "LED";
+
+    fn update(&mut self, _ctx: &Context, _inputs: &[Bus; 1], _outputs: &mut [Bus; 0]) {}
+}
+
+/// Seven-segment display, inputs drive segments a to g, clockwise from the
+/// top segment with the middle segment last.
+pub struct SevenSegment;
+
+impl Gate<7, 0> for SevenSegment {
+    const NAME: &'static str = "7SEG";
+
+    fn update(&mut self, _ctx: &Context, _inputs: &[Bus; 7], _outputs: &mut [Bus; 0]) {}
+}
+
+/// Display showing a hexadecimal digit, inputs are the bits of the digit
+/// starting with the least significant one.
+pub struct HexDisplay;
+
+impl HexDisplay {
+    /// Digit given by the inputs, if all of them are known.
+    pub fn digit(inputs: &[Bus]) -> Option<u8> {
+        inputs
+            .iter()
+            .enumerate()
+            .try_fold(0, |digit, (index, input)| match input.bit(0) {
+                Signal::Low => Some(digit),
+                Signal::High => Some(digit | 1 << index),
+                _ => None,
+            })
+    }
+
+    /// Segments a to g lit for given digit, as on a [`SevenSegment`] display.
+    pub fn segments(digit: u8) -> [bool; 7] {
+        const FONT: [u8; 16] = [
+            0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07, 0x7f, 0x6f, 0x77, 0x7c, 0x39, 0x5e,
+            0x79, 0x71,
+        ];
+        let pattern = FONT[digit as usize & 0xf];
+        std::array::from_fn(|segment| pattern >> segment & 1 == 1)
+    }
+}
+
+impl Gate<4, 0> for HexDisplay {
+    const NAME: &'static str = "HEX";
+
+    fn update(&mut self, _ctx: &Context, _inputs: &[Bus; 4], _outputs: &mut [Bus; 0]) {}
+}
+
+/// Input port of a subcircuit, passes the value driven into the subcircuit
+/// to the gates inside it. A 1-bit port that nothing drives, e.g. one on the
+/// board itself, is toggled between low and high by pressing it, it passes
+/// high impedance until it is pressed for the first time.
+pub struct InputPort {
+    width: usize,
+    toggled: Option<bool>,
+}
+
+impl InputPort {
+    pub fn new(width: usize) -> InputPort {
+        assert!(
+            (1..=Bus::MAX_WIDTH).contains(&width),
+            "invalid bus width {width}"
+        );
+        InputPort {
+            width,
+            toggled: None,
+        }
+    }
+}
+
+impl Default for InputPort {
+    fn default() -> Self {
+        InputPort::new(1)
+    }
+}
+
+impl Gate<1, 1> for InputPort {
+    const NAME: &'static str = "IN";
+
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 1], outputs: &mut [Bus; 1]) {
+        let driven = inputs[0] != Bus::new(self.width, Signal::HighZ);
+        outputs[0] = match self.toggled {
+            Some(on) if !driven => Signal::from(on).into(),
+            _ => inputs[0],
+        };
+    }
+
+    fn reset(&mut self) {
+        self.toggled = None;
+    }
+
+    fn is_interactive(&self) -> bool {
+        self.width == 1
+    }
+
+    fn press(&mut self) {
+        self.toggled = Some(!self.toggled.unwrap_or(false));
+    }
+
+    fn params(&self) -> Vec<u64> {
+        vec![self.width as u64]
+    }
+
+    fn input_widths(&self) -> [usize; 1] {
+        [self.width]
+    }
+
+    fn output_widths(&self) -> [usize; 1] {
+        [self.width]
+    }
+}
+
+/// Output port of a subcircuit, passes the value computed inside the
+/// subcircuit out of it.
+pub struct OutputPort {
+    width: usize,
+}
+
+impl OutputPort {
+    pub fn new(width: usize) -> OutputPort {
+        assert!(
+            (1..=Bus::MAX_WIDTH).contains(&width),
+            "invalid bus width {width}"
+        );
+        OutputPort { width }
+    }
+}
+
+impl Default for OutputPort {
+    fn default() -> Self {
+        OutputPort::new(1)
+    }
+}
+
+impl Gate<1, 1> for OutputPort {
+    const NAME: &'static str = "OUT";
+
+    fn update(&mut self, _ctx: &Context, inputs: &[Bus; 1], outputs: &mut [Bus; 1]) {
         outputs[0] = inputs[0];
     }
+
+    fn params(&self) -> Vec<u64> {
+        vec![self.width as u64]
+    }
+
+    fn input_widths(&self) -> [usize; 1] {
+        [self.width]
+    }
+
+    fn output_widths(&self) -> [usize; 1] {
+        [self.width]
+    }
 }
 
 #[cfg(test)]
@@ -94,24 +812,28 @@ mod tests {
 
     // test are just checks against truth tables
 
-    const Y: bool = true;
-    const N: bool = false;
+    const Y: Signal = Signal::High;
+    const N: Signal = Signal::Low;
+    const X: Signal = Signal::Unknown;
+    const Z: Signal = Signal::HighZ;
 
     struct TruthTable<const INPUTS: usize, const OUTPUTS: usize, const ROWS: usize>(
-        [([bool; INPUTS], [bool; OUTPUTS]); ROWS],
+        [([Signal; INPUTS], [Signal; OUTPUTS]); ROWS],
     );
 
     fn test_gate<const INPUTS: usize, const OUTPUTS: usize>(
-        gate: impl Gate<INPUTS, OUTPUTS>,
-        io: ([bool; INPUTS], [bool; OUTPUTS]),
+        mut gate: impl Gate<INPUTS, OUTPUTS>,
+        io: ([Signal; INPUTS], [Signal; OUTPUTS]),
     ) {
         let (inputs, expected_outputs) = io;
+        let inputs = inputs.map(Bus::from);
 
-        // invert the expected outputs, so we always check changed values, some
-        // test may pass with default 'false' values
-        let mut outputs = expected_outputs.map(|output| !output);
+        // start with values different from the expected outputs, so we always
+        // check changed values, some test may pass with default values
+        let mut outputs = expected_outputs.map(|output| Bus::from(if output == N { Y } else { N }));
+        let expected_outputs = expected_outputs.map(Bus::from);
 
-        gate.update(&inputs, &mut outputs);
+        gate.update(&Context::new(1), &inputs, &mut outputs);
 
         assert_eq!(outputs, expected_outputs);
     }
@@ -124,6 +846,10 @@ mod tests {
             ([N, Y], [N]),
             ([Y, N], [N]),
             ([Y, Y], [Y]),
+            ([N, X], [N]),
+            ([X, Y], [X]),
+            ([Y, Z], [X]),
+            ([Z, N], [N]),
         ]);
 
         for row in table.0 {
@@ -139,6 +865,10 @@ mod tests {
             ([N, Y], [Y]),
             ([Y, N], [Y]),
             ([Y, Y], [N]),
+            ([N, X], [Y]),
+            ([X, Y], [X]),
+            ([Y, Z], [X]),
+            ([Z, N], [Y]),
         ]);
 
         for row in table.0 {
@@ -154,6 +884,10 @@ mod tests {
             ([N, Y], [Y]),
             ([Y, N], [Y]),
             ([Y, Y], [Y]),
+            ([Y, X], [Y]),
+            ([X, N], [X]),
+            ([N, Z], [X]),
+            ([Z, Y], [Y]),
         ]);
 
         for row in table.0 {
@@ -169,6 +903,10 @@ mod tests {
             ([N, Y], [N]),
             ([Y, N], [N]),
             ([Y, Y], [N]),
+            ([Y, X], [N]),
+            ([X, N], [X]),
+            ([N, Z], [X]),
+            ([Z, Y], [N]),
         ]);
 
         for row in table.0 {
@@ -184,6 +922,10 @@ mod tests {
             ([N, Y], [Y]),
             ([Y, N], [Y]),
             ([Y, Y], [N]),
+            ([Y, X], [X]),
+            ([X, N], [X]),
+            ([N, Z], [X]),
+            ([Z, Z], [X]),
         ]);
 
         for row in table.0 {
@@ -199,6 +941,10 @@ mod tests {
             ([N, Y], [N]),
             ([Y, N], [N]),
             ([Y, Y], [Y]),
+            ([Y, X], [X]),
+            ([X, N], [X]),
+            ([N, Z], [X]),
+            ([Z, Z], [X]),
         ]);
 
         for row in table.0 {
@@ -212,6 +958,8 @@ mod tests {
         let table = TruthTable([
             ([N], [Y]),
             ([Y], [N]),
+            ([X], [X]),
+            ([Z], [X]),
         ]);
 
         for row in table.0 {
@@ -225,10 +973,295 @@ mod tests {
         let table = TruthTable([
             ([N], [N]),
             ([Y], [Y]),
+            ([X], [X]),
+            ([Z], [X]),
         ]);
 
         for row in table.0 {
             test_gate(Yes, row);
         }
     }
+
+    #[test]
+    fn wide() {
+        let mut outputs = [Bus::from_u64(8, 0)];
+
+        Wide::new(And, 8).update(
+            &Context::new(1),
+            &[Bus::from_u64(8, 0b1100_1010), Bus::from_u64(8, 0b1010_0110)],
+            &mut outputs,
+        );
+        assert_eq!(outputs[0], Bus::from_u64(8, 0b1000_0010));
+
+        Wide::new(Not, 8).update(&Context::new(1), &[Bus::new(8, X)], &mut outputs);
+        assert_eq!(outputs[0], Bus::new(8, X));
+
+        Wide::new(Xor, 8).update(
+            &Context::new(1),
+            &[
+                Bus::from_u64(8, 0b1100_1010),
+                Bus::from_bits([Y, Z, N, N, N, N, N, N]),
+            ],
+            &mut outputs,
+        );
+        assert_eq!(outputs[0], Bus::from_bits([Y, X, N, Y, N, N, Y, Y]));
+    }
+
+    #[test]
+    fn split_and_merge() {
+        let bus = Bus::from_u64(8, 0xa5);
+
+        let mut parts = [Bus::from(N); 2];
+        Splitter::new([4, 4]).update(&Context::new(1), &[bus], &mut parts);
+        assert_eq!(parts, [Bus::from_u64(4, 0x5), Bus::from_u64(4, 0xa)]);
+
+        let mut bits = [Bus::from(N); 8];
+        Splitter::bits().update(&Context::new(1), &[bus], &mut bits);
+        assert_eq!(bits.map(|bit| bit.bit(0)), [Y, N, Y, N, N, Y, N, Y]);
+
+        let mut merged = [Bus::from(N)];
+        Merger::bits().update(&Context::new(1), &bits, &mut merged);
+        assert_eq!(merged, [bus]);
+
+        Merger::new([4, 4]).update(&Context::new(1), &[parts[0], Bus::new(4, Z)], &mut merged);
+        assert_eq!(
+            merged[0].bits().collect::<Vec<_>>(),
+            [Y, N, Y, N, Z, Z, Z, Z]
+        );
+    }
+
+    /// Feeds the gate with inputs one step after another and checks the
+    /// outputs after each of them.
+    fn test_sequence<const INPUTS: usize, const OUTPUTS: usize>(
+        mut gate: impl Gate<INPUTS, OUTPUTS>,
+        sequence: &[([Signal; INPUTS], [Signal; OUTPUTS])],
+    ) {
+        let mut outputs = [Bus::from(Z); OUTPUTS];
+        for (step, (inputs, expected_outputs)) in sequence.iter().enumerate() {
+            gate.update(
+                &Context::new(step as u64 + 1),
+                &inputs.map(Bus::from),
+                &mut outputs,
+            );
+            assert_eq!(
+                outputs.map(|output| output.bit(0)),
+                *expected_outputs,
+                "step {step}"
+            );
+        }
+    }
+
+    #[test]
+    fn d_flip_flop() {
+        #[rustfmt::skip]
+        test_sequence(DFlipFlop::default(), &[
+            // d  clk set rst
+            ([Y, N, Z, Z], [X, X]),
+            ([Y, Y, Z, Z], [Y, N]),
+            ([N, Y, Z, Z], [Y, N]),
+            ([N, N, Z, Z], [Y, N]),
+            ([N, Y, Z, Z], [N, Y]),
+            ([Y, N, Y, Z], [Y, N]),
+            ([Y, N, N, Y], [N, Y]),
+            ([Y, Y, Y, Y], [X, X]),
+            ([Y, N, N, N], [X, X]),
+            ([Y, X, N, N], [X, X]),
+            ([Y, Y, N, N], [X, X]),
+            ([Y, N, N, N], [X, X]),
+            ([Y, Y, N, N], [Y, N]),
+        ]);
+    }
+
+    #[test]
+    fn jk_flip_flop() {
+        #[rustfmt::skip]
+        test_sequence(JkFlipFlop::default(), &[
+            // j  k  clk set rst
+            ([N, N, N, Z, Y], [N, Y]),
+            ([N, N, Y, Z, Z], [N, Y]),
+            ([Y, N, N, Z, Z], [N, Y]),
+            ([Y, N, Y, Z, Z], [Y, N]),
+            ([N, N, N, Z, Z], [Y, N]),
+            ([N, Y, Y, Z, Z], [N, Y]),
+            ([Y, Y, N, Z, Z], [N, Y]),
+            ([Y, Y, Y, Z, Z], [Y, N]),
+            ([Y, Y, N, Z, Z], [Y, N]),
+            ([Y, Y, Y, Z, Z], [N, Y]),
+        ]);
+    }
+
+    #[test]
+    fn t_flip_flop() {
+        #[rustfmt::skip]
+        test_sequence(TFlipFlop::default(), &[
+            // t  clk set rst
+            ([Y, N, Y, Z], [Y, N]),
+            ([Y, N, Z, Z], [Y, N]),
+            ([Y, Y, Z, Z], [N, Y]),
+            ([Y, N, Z, Z], [N, Y]),
+            ([Y, Y, Z, Z], [Y, N]),
+            ([N, N, Z, Z], [Y, N]),
+            ([N, Y, Z, Z], [Y, N]),
+        ]);
+    }
+
+    #[test]
+    fn sr_latch() {
+        #[rustfmt::skip]
+        test_sequence(SrLatch::default(), &[
+            // s  r
+            ([N, N], [X, X]),
+            ([Y, N], [Y, N]),
+            ([N, N], [Y, N]),
+            ([N, Y], [N, Y]),
+            ([N, N], [N, Y]),
+            ([Y, Y], [X, X]),
+        ]);
+    }
+
+    #[test]
+    fn lut() {
+        // multiplexer choosing the first input when the third is low
+        #[rustfmt::skip]
+        let table = TruthTable([
+            ([Y, N, N], [Y]),
+            ([N, Y, N], [N]),
+            ([N, Y, Y], [Y]),
+            ([Y, N, Y], [N]),
+            ([Y, Y, X], [Y]),
+            ([Y, N, Z], [X]),
+            ([X, N, Y], [N]),
+        ]);
+
+        for row in table.0 {
+            test_gate(Lut::<3>::new(0xca), row);
+        }
+        assert_eq!(Lut::<1>::new(0xff).params(), [1, 0b11]);
+        assert_eq!(Lut::<6>::new(u64::MAX).params(), [6, u64::MAX]);
+    }
+
+    #[test]
+    fn flip_flops_reset() {
+        let mut gate = DFlipFlop::default();
+        let mut outputs = [Bus::from(Z); 2];
+        let clock = |clock| [Y, clock, N, N].map(Bus::from);
+
+        gate.update(&Context::new(1), &clock(N), &mut outputs);
+        gate.update(&Context::new(2), &clock(Y), &mut outputs);
+        assert_eq!(outputs[0].bit(0), Y);
+
+        gate.reset();
+        gate.update(&Context::new(1), &clock(Y), &mut outputs);
+        assert_eq!(outputs[0].bit(0), X);
+    }
+
+    fn clock_waveform(mut clock: Clock, ticks: u64) -> String {
+        let mut outputs = [Bus::from(Z)];
+        (1..=ticks)
+            .map(|tick| {
+                clock.update(&Context::new(tick), &[], &mut outputs);
+                outputs[0].to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clock() {
+        assert_eq!(clock_waveform(Clock::default(), 20), "11111000001111100000");
+        assert_eq!(clock_waveform(Clock::new(4, 25, 0), 8), "10001000");
+        assert_eq!(clock_waveform(Clock::new(4, 25, 1), 8), "01000100");
+        assert_eq!(clock_waveform(Clock::new(4, 75, 6), 8), "10111011");
+        assert_eq!(clock_waveform(Clock::new(2, 0, 0), 4), "1010");
+        assert_eq!(clock_waveform(Clock::new(2, 100, 0), 4), "1010");
+    }
+
+    #[test]
+    fn constants() {
+        test_gate(High, ([], [Y]));
+        test_gate(Low, ([], [N]));
+    }
+
+    #[test]
+    fn switch_toggles_on_press() {
+        let mut switch = Switch::default();
+        let mut outputs = [Bus::from(Z)];
+        let mut states = Vec::new();
+        for _ in 0..3 {
+            switch.update(&Context::new(1), &[], &mut outputs);
+            states.push(outputs[0].bit(0));
+            switch.press();
+            switch.release();
+        }
+        assert_eq!(states, [N, Y, N]);
+
+        switch.reset();
+        switch.update(&Context::new(1), &[], &mut outputs);
+        assert_eq!(outputs[0].bit(0), N);
+    }
+
+    #[test]
+    fn button_is_high_while_held() {
+        let mut button = Button::default();
+        let mut outputs = [Bus::from(Z)];
+
+        button.press();
+        button.update(&Context::new(1), &[], &mut outputs);
+        assert_eq!(outputs[0].bit(0), Y);
+
+        button.release();
+        button.update(&Context::new(2), &[], &mut outputs);
+        assert_eq!(outputs[0].bit(0), N);
+    }
+
+    #[test]
+    fn hex_display() {
+        let digit = |bits: [Signal; 4]| HexDisplay::digit(&bits.map(Bus::from));
+        assert_eq!(digit([Y, N, Y, N]), Some(5));
+        assert_eq!(digit([Y, Y, Y, Y]), Some(0xf));
+        assert_eq!(digit([Y, X, N, N]), None);
+        assert_eq!(digit([Y, N, Z, N]), None);
+
+        let lit = |digit| {
+            HexDisplay::segments(digit)
+                .iter()
+                .filter(|lit| **lit)
+                .count()
+        };
+        assert_eq!(lit(8), 7);
+        assert_eq!(lit(1), 2);
+        assert!(!HexDisplay::segments(0)[6]);
+        assert_eq!(
+            HexDisplay::segments(0xb),
+            [false, false, true, true, true, true, true]
+        );
+    }
+
+    #[test]
+    fn ports_pass_values() {
+        test_gate(InputPort::default(), ([Z], [Z]));
+        test_gate(OutputPort::default(), ([Y], [Y]));
+
+        let mut port = InputPort::new(8);
+        let mut outputs = [Bus::new(8, X)];
+        port.update(&Context::new(1), &[Bus::from_u64(8, 0x5a)], &mut outputs);
+        assert_eq!(outputs[0], Bus::from_u64(8, 0x5a));
+        assert!(!port.is_interactive());
+
+        // a pressed port drives its value unless something drives the port
+        let mut port = InputPort::default();
+        port.press();
+        let mut outputs = [Bus::from(X)];
+        port.update(&Context::new(1), &[Bus::from(Z)], &mut outputs);
+        assert_eq!(outputs[0], Bus::from(Y));
+        port.update(&Context::new(2), &[Bus::from(N)], &mut outputs);
+        assert_eq!(outputs[0], Bus::from(N));
+        port.press();
+        port.update(&Context::new(3), &[Bus::from(Z)], &mut outputs);
+        assert_eq!(outputs[0], Bus::from(N));
+
+        // resetting forgets the toggled value
+        port.reset();
+        port.update(&Context::new(1), &[Bus::from(Z)], &mut outputs);
+        assert_eq!(outputs[0], Bus::from(Z));
+    }
 }
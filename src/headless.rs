@@ -0,0 +1,486 @@
+//! Runs saved boards without a window, e.g. in CI.
+//!
+//! ```text
+//! logic-sim-headless BOARD [--stimulus FILE] [--ticks N] [--output FILE]
+//!     [--vcd FILE]
+//! ```
+//!
+//! The `logic-sim-headless` binary does not need the `gui` feature, with it
+//! `logic-sim --headless` takes the same arguments.
+//!
+//! The inputs of the board are its `IN` ports and the outputs its `OUT` ports,
+//! both numbered from the top, as the pins of the board used as a subcircuit.
+//! `.blif` and `.json` files are read as netlists by [`netlist`].
+//! Without a stimulus file the board runs for given number of ticks, or until
+//! it is stable, and the outputs are printed.
+//!
+//! A stimulus file has one command per line, empty lines and lines starting
+//! with `#` are ignored:
+//!
+//! ```text
+//! # drive the first input high and the second one with a byte
+//! set in0 1
+//! set in1 0x3f
+//! # run for 5 ticks, then until nothing changes, at most 100 ticks
+//! tick 5
+//! settle 100
+//! # check the first output and output 0 of gate 3 in the board file
+//! expect out0 1
+//! expect 3.0 X
+//! print
+//! # record output 1 of gate 2 in the board file into the VCD file
+//! record 2.1
+//! ```
+//!
+//! Values are a single `0`, `1`, `X` or `Z` for single bits, otherwise hex
+//! numbers starting with `0x` or all bits written most significant first.
+//! `print` writes the time and the values of all outputs. With `--vcd` the
+//! values of all ports, including the ports of subcircuits, and of the
+//! outputs given to `record` are written to a
+//! [`vcd`] file from the start of the run, or from the `record`
+//! command for the outputs recorded by it. The runner exits
+//! with 1 when an expectation fails or the board does not settle, and with 2
+//! when the board or the stimulus cannot be read.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    board_file::{self, BoardFile},
+    bus::Bus,
+    id::GateId,
+    logic_simulation::{LogicSimulation, SimulationError},
+    netlist,
+    signal::Signal,
+    vcd,
+    waveform::Probe,
+};
+
+/// Number of ticks a board gets to settle when no limit is given.
+const SETTLE_TICKS: u64 = 1000;
+
+/// Simulation of a board driven by a stimulus.
+pub struct Runner {
+    sim: LogicSimulation,
+    /// Ids of the gates in the simulation keyed by their ids in the file
+    ids: BTreeMap<usize, GateId>,
+    inputs: Vec<GateId>,
+    outputs: Vec<GateId>,
+    /// Recorded pins in the order they are written to VCD files
+    probes: Vec<Probe>,
+    /// Printed outputs
+    pub report: String,
+    /// Failed expectations and boards that did not settle
+    pub failures: Vec<String>,
+}
+
+impl Runner {
+    pub fn new(file: &BoardFile) -> Result<Runner, String> {
+        let mut sim = LogicSimulation::new();
+        sim.set_mode(file.mode);
+        sim.set_default_resolution(file.default_resolution);
+        let (ids, _) = file
+            .build(&mut sim, &file.circuits, file.subcircuits)
+            .map_err(|error| error.to_string())?;
+
+        let (inputs, outputs) = file.ports();
+        Ok(Runner {
+            sim,
+            inputs: inputs.iter().map(|id| ids[id]).collect(),
+            outputs: outputs.iter().map(|id| ids[id]).collect(),
+            ids,
+            probes: Vec::new(),
+            report: String::new(),
+            failures: Vec::new(),
+        })
+    }
+
+    /// Applies all commands of a stimulus file.
+    pub fn run(&mut self, stimulus: &str) -> Result<(), String> {
+        for (index, line) in stimulus.lines().enumerate() {
+            self.command(line)
+                .map_err(|message| format!("line {}: {message}", index + 1))?;
+        }
+
+        Ok(())
+    }
+
+    fn command(&mut self, line: &str) -> Result<(), String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields[..] {
+            [] => {}
+            [comment, ..] if comment.starts_with('#') => {}
+            ["set", input, value] => {
+                let port = input
+                    .strip_prefix("in")
+                    .and_then(|index| index.parse::<usize>().ok())
+                    .and_then(|index| self.inputs.get(index).copied())
+                    .ok_or_else(|| format!("unknown input {input}"))?;
+                let width = self.sim.get_gate_state(port).map_err(error)?.0[0].width();
+                let value = parse_value(value, width)
+                    .ok_or_else(|| format!("invalid value {value} of {width} bits"))?;
+                self.sim.drive_input(port.input(0), value).map_err(error)?;
+            }
+            ["tick"] => self.sim.simulate(),
+            ["tick", ticks] => {
+                let ticks: u64 = ticks
+                    .parse()
+                    .map_err(|_| format!("invalid number {ticks}"))?;
+                for _ in 0..ticks {
+                    self.sim.simulate();
+                }
+            }
+            ["settle"] => self.settle(SETTLE_TICKS),
+            ["settle", ticks] => {
+                let ticks = ticks
+                    .parse()
+                    .map_err(|_| format!("invalid number {ticks}"))?;
+                self.settle(ticks);
+            }
+            ["expect", output, expected] => {
+                let actual = self.output(output)?;
+                let expected = parse_value(expected, actual.width()).ok_or_else(|| {
+                    format!("invalid value {expected} of {} bits", actual.width())
+                })?;
+                if actual != expected {
+                    self.failures.push(format!(
+                        "expected {output} to be {expected} at time {}, got {actual}",
+                        self.sim.time()
+                    ));
+                }
+            }
+            ["print"] => self.print(),
+            ["record", output] => {
+                let unknown = || format!("unknown output {output}");
+                let pin = board_file::sim_output(&self.sim, &self.ids, parse_pin(output)?)
+                    .map_err(|_| unknown())?;
+                self.record(Probe::Output(pin)).map_err(error)?;
+            }
+            _ => return Err(format!("invalid command \"{line}\"")),
+        }
+
+        Ok(())
+    }
+
+    /// Simulates until the board is stable, at most given number of ticks.
+    pub fn settle(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.sim.simulate();
+            if self.sim.is_stable() {
+                return;
+            }
+        }
+
+        self.failures
+            .push(format!("board did not settle within {ticks} ticks"));
+    }
+
+    /// Value of an output port `out<index>` or of an output of a gate
+    /// `<gate>.<output>` with the id of the gate in the file.
+    fn output(&self, output: &str) -> Result<Bus, String> {
+        let unknown = || format!("unknown output {output}");
+        if let Some(index) = output.strip_prefix("out") {
+            let index: usize = index.parse().map_err(|_| unknown())?;
+            let port = *self.outputs.get(index).ok_or_else(unknown)?;
+            return Ok(self.sim.get_gate_state(port).map_err(error)?.1[0]);
+        }
+
+        let pin = board_file::sim_output(&self.sim, &self.ids, parse_pin(output)?)
+            .map_err(|_| unknown())?;
+        Ok(self.sim.get_gate_state(pin.gate).map_err(error)?.1[pin.index])
+    }
+
+    /// Records a pin from now on, the whole run is kept.
+    pub fn record(&mut self, probe: Probe) -> Result<(), SimulationError> {
+        self.sim.set_waveform_capacity(usize::MAX);
+        self.sim.record(probe)?;
+        if !self.probes.contains(&probe) {
+            self.probes.push(probe);
+        }
+        Ok(())
+    }
+
+    /// Records the values of all input and output ports, including the ports
+    /// of subcircuits.
+    pub fn record_ports(&mut self) -> Result<(), SimulationError> {
+        let ports: Vec<GateId> = self.inputs.iter().chain(&self.outputs).copied().collect();
+        for port in ports {
+            self.record(Probe::Output(port.output(0)))?;
+        }
+        for (path, port) in self.sim.subcircuit_ports() {
+            let probe = Probe::Output(port.output(0));
+            if path.is_empty() {
+                self.record(probe)?;
+            } else {
+                self.sim.record_nested(&path, probe)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Recorded pins as a VCD file.
+    pub fn vcd(&self) -> Result<String, SimulationError> {
+        vcd::export(&self.sim, &self.probes)
+    }
+
+    /// Writes the time and the values of all output ports to the report.
+    pub fn print(&mut self) {
+        self.report += &format!("time {}", self.sim.time());
+        for (index, port) in self.outputs.iter().enumerate() {
+            if let Ok((_, outputs)) = self.sim.get_gate_state(*port) {
+                self.report += &format!(" out{index}={}", outputs[0]);
+            }
+        }
+        self.report += "\n";
+    }
+}
+
+fn error(error: SimulationError) -> String {
+    error.to_string()
+}
+
+fn parse_pin(field: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("unknown output {field}");
+    let (gate, output) = field.split_once('.').ok_or_else(invalid)?;
+    Ok((
+        gate.parse().map_err(|_| invalid())?,
+        output.parse().map_err(|_| invalid())?,
+    ))
+}
+
+/// Parses a value of a pin with given width, written as by the [`Bus`]
+/// display, a signal, a hex number or bits with the most significant first.
+fn parse_value(text: &str, width: usize) -> Option<Bus> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        let value = u64::from_str_radix(hex, 16).ok()?;
+        let bus = Bus::from_u64(width, value);
+        return (bus.to_u64() == Some(value)).then_some(bus);
+    }
+
+    if text.len() != width {
+        return None;
+    }
+
+    let bits = text.chars().rev().map(|c| match c {
+        '0' => Some(Signal::Low),
+        '1' => Some(Signal::High),
+        'X' | 'x' => Some(Signal::Unknown),
+        'Z' | 'z' => Some(Signal::HighZ),
+        _ => None,
+    });
+    bits.collect::<Option<Vec<_>>>().map(Bus::from_bits)
+}
+
+/// Runs the headless mode with the arguments following `--headless`, returns
+/// the exit code.
+pub fn run(args: &[String]) -> i32 {
+    match try_run(args) {
+        Ok(failures) => {
+            for failure in &failures {
+                eprintln!("{failure}");
+            }
+            i32::from(!failures.is_empty())
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            2
+        }
+    }
+}
+
+fn try_run(args: &[String]) -> Result<Vec<String>, String> {
+    const USAGE: &str = "usage: logic-sim-headless BOARD [--stimulus FILE] [--ticks N] \
+        [--output FILE] [--vcd FILE]";
+
+    let mut board = None;
+    let mut stimulus = None;
+    let mut ticks = None;
+    let mut output = None;
+    let mut vcd = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| USAGE.to_string());
+        match arg.as_str() {
+            "--stimulus" => stimulus = Some(value()?),
+            "--ticks" => ticks = Some(value()?.parse::<u64>().map_err(|_| USAGE.to_string())?),
+            "--output" => output = Some(value()?),
+            "--vcd" => vcd = Some(value()?),
+            _ if board.is_none() && !arg.starts_with("--") => board = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let board = board.ok_or_else(|| USAGE.to_string())?;
+    let read = |path: &str| {
+        std::fs::read_to_string(path).map_err(|error| format!("cannot read {path}: {error}"))
+    };
+
+    let text = read(board)?;
+    let file = if netlist::is_netlist(board) {
+        netlist::import(&text)
+    } else {
+        BoardFile::parse(&text)
+    };
+    let file = file.map_err(|error| format!("{board}: {error}"))?;
+    let mut runner = Runner::new(&file).map_err(|error| format!("{board}: {error}"))?;
+    if vcd.is_some() {
+        runner.record_ports().map_err(error)?;
+    }
+
+    if let Some(stimulus) = stimulus {
+        runner
+            .run(&read(stimulus)?)
+            .map_err(|error| format!("{stimulus}: {error}"))?;
+    }
+
+    match ticks {
+        Some(ticks) => {
+            for _ in 0..ticks {
+                runner.sim.simulate();
+            }
+        }
+        None if stimulus.is_none() => runner.settle(SETTLE_TICKS),
+        None => {}
+    }
+    runner.print();
+
+    match output {
+        Some(path) => std::fs::write(path, &runner.report)
+            .map_err(|error| format!("cannot write {path}: {error}"))?,
+        None => print!("{}", runner.report),
+    }
+
+    if let Some(path) = vcd {
+        let dump = runner.vcd().map_err(error)?;
+        std::fs::write(path, dump).map_err(|error| format!("cannot write {path}: {error}"))?;
+    }
+
+    Ok(runner.failures)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inverts a byte and a bit.
+    const BOARD: &str = "logic-sim 2
+gate 0 IN 0 0 1 8
+gate 1 IN 0 100 1 1
+gate 2 NOT 100 0 1 8
+gate 3 NOT 100 100 1
+gate 4 OUT 200 0 1 8
+gate 5 OUT 200 100 1 1
+wire 0.0 2.0
+wire 1.0 3.0
+wire 2.0 4.0
+wire 3.0 5.0
+";
+
+    fn runner() -> Runner {
+        Runner::new(&BoardFile::parse(BOARD).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn stimulus() {
+        let mut runner = runner();
+        runner
+            .run(
+                "set in0 0x0f
+set in1 0
+settle
+expect out0 0xf0
+expect out1 1
+expect 3.0 1
+print
+set in0 1010X0Z1
+tick 4
+expect out0 0101X1X0
+print",
+            )
+            .unwrap();
+
+        assert_eq!(runner.failures, Vec::<String>::new());
+        assert_eq!(
+            runner.report,
+            "time 4 out0=0xf0 out1=1\ntime 8 out0=0101X1X0 out1=1\n"
+        );
+    }
+
+    #[test]
+    fn failed_expectation() {
+        let mut runner = runner();
+        runner.run("set in1 1\ntick 3\nexpect out1 1").unwrap();
+        assert_eq!(runner.failures, ["expected out1 to be 1 at time 3, got 0"]);
+    }
+
+    #[test]
+    fn invalid_stimulus() {
+        let mut runner = runner();
+        assert_eq!(
+            runner.run("set in2 1"),
+            Err("line 1: unknown input in2".to_string())
+        );
+        assert_eq!(
+            runner.run("\nset in0 0x100"),
+            Err("line 2: invalid value 0x100 of 8 bits".to_string())
+        );
+        assert_eq!(
+            runner.run("expect 7.0 1"),
+            Err("line 1: unknown output 7.0".to_string())
+        );
+        assert_eq!(
+            runner.run("jump"),
+            Err("line 1: invalid command \"jump\"".to_string())
+        );
+    }
+
+    #[test]
+    fn recorded_ports_are_dumped() {
+        let mut runner = runner();
+        runner.record_ports().unwrap();
+        runner.run("set in1 0\ntick 2\nrecord 3.0\ntick 2").unwrap();
+
+        let vcd = runner.vcd().unwrap();
+        assert_eq!(vcd.matches("$var wire").count(), 5);
+        assert!(vcd.contains("$var wire 8 ! IN_0_out0 $end"));
+        assert!(vcd.contains("$var wire 1 % NOT_3_out0 $end"));
+        // the output of the NOT gate is recorded from time 2, the output
+        // port follows it a step later
+        assert!(vcd.ends_with("#1\nbzzzzzzzz !\n0\"\n#2\n1%\n#3\n1$\n#5\n"));
+    }
+
+    #[test]
+    fn ports_of_subcircuits_are_dumped() {
+        for mode in ["nested", "flattened"] {
+            let file = format!(
+                "logic-sim 3
+subcircuits {mode}
+circuit INV
+gate 0 IN 0 0 1 1
+gate 1 NOT 100 0 1
+gate 2 OUT 200 0 1 1
+wire 0.0 1.0
+wire 1.0 2.0
+end
+gate 0 HIGH 0 0 1
+gate 1 INV 100 0 1
+wire 0.0 1.0
+"
+            );
+            let mut runner = Runner::new(&BoardFile::parse(&file).unwrap()).unwrap();
+            runner.record_ports().unwrap();
+            runner.settle(10);
+
+            let vcd = runner.vcd().unwrap();
+            assert_eq!(vcd.matches("$scope module INV_").count(), 1, "{vcd}");
+            assert_eq!(vcd.matches("$var wire 1").count(), 2, "{vcd}");
+        }
+    }
+
+    #[test]
+    fn clocked_board_does_not_settle() {
+        let file = BoardFile::parse("logic-sim 2\ngate 0 CLK 0 0 1 2 50 0").unwrap();
+        let mut runner = Runner::new(&file).unwrap();
+        runner.settle(10);
+        assert_eq!(runner.failures, ["board did not settle within 10 ticks"]);
+    }
+}
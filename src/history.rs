@@ -0,0 +1,256 @@
+//! Edit history of a [`BoardSimulation`](crate::board::BoardSimulation), for
+//! undoing and redoing edits. Only available with the `gui` feature.
+//!
+//! Gates and connections brought back by undoing or redoing an edit get new
+//! ids, the history keeps track of them so older edits still apply to the
+//! right gates and connections.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use macroquad::prelude::Vec2;
+
+use crate::{
+    id::{ConnectionId, GateId, InputPin, OutputPin},
+    net::Resolution,
+};
+
+/// Number of edits kept by default.
+pub const DEFAULT_DEPTH: usize = 100;
+
+/// Everything needed to add a removed gate again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GateSnapshot {
+    pub id: GateId,
+    pub kind: String,
+    pub params: Vec<u64>,
+    pub delay: u64,
+    pub pos: Vec2,
+    /// Resolution policies of the inputs of the gate that do not use the
+    /// default one
+    pub nets: Vec<(usize, Resolution)>,
+    /// Connections to and from the gate
+    pub wires: Vec<WireSnapshot>,
+}
+
+/// Everything needed to add a removed connection again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WireSnapshot {
+    pub connection: ConnectionId,
+    pub output: OutputPin,
+    pub input: InputPin,
+    pub waypoints: Vec<Vec2>,
+}
+
+/// Single edit of a board.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit {
+    AddGate(GateSnapshot),
+    RemoveGate(GateSnapshot),
+    MoveGate {
+        gate: GateId,
+        from: Vec2,
+        to: Vec2,
+    },
+    AddConnection(WireSnapshot),
+    RemoveConnection(WireSnapshot),
+    /// Edits undone and redone together, like those of a pasted or removed
+    /// selection, in the order they were made
+    Batch(Vec<Edit>),
+}
+
+pub struct History {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    depth: usize,
+    /// New ids of gates added again, keyed by their previous ids
+    gates: BTreeMap<GateId, GateId>,
+    /// New ids of connections added again, keyed by their previous ids
+    connections: BTreeMap<ConnectionId, ConnectionId>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new(DEFAULT_DEPTH)
+    }
+}
+
+impl History {
+    /// Creates a history keeping at most given number of edits.
+    pub fn new(depth: usize) -> History {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            depth,
+            gates: BTreeMap::new(),
+            connections: BTreeMap::new(),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Changes the number of kept edits, the oldest edits are dropped.
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        while self.undo.len() > depth {
+            self.undo.pop_front();
+        }
+        self.redo.truncate(depth);
+    }
+
+    /// Records a new edit, edits undone before can no longer be redone.
+    pub fn push(&mut self, edit: Edit) {
+        self.redo.clear();
+        self.push_undo(edit);
+    }
+
+    fn push_undo(&mut self, edit: Edit) {
+        if self.depth == 0 {
+            return;
+        }
+        if self.undo.len() == self.depth {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(edit);
+    }
+
+    /// Takes the last edit to undo it, the edit has to be passed to
+    /// [`History::undone`] once undone, or to [`History::undo_failed`] when it
+    /// could not be undone.
+    pub fn take_undo(&mut self) -> Option<Edit> {
+        self.undo.pop_back()
+    }
+
+    pub fn undone(&mut self, edit: Edit) {
+        self.redo.push(edit);
+    }
+
+    /// Puts back an edit taken to undo it, to undo it again later.
+    pub fn undo_failed(&mut self, edit: Edit) {
+        self.undo.push_back(edit);
+    }
+
+    /// Takes the last undone edit to redo it, the edit has to be passed to
+    /// [`History::redone`] once redone, or to [`History::redo_failed`] when it
+    /// could not be redone.
+    pub fn take_redo(&mut self) -> Option<Edit> {
+        self.redo.pop()
+    }
+
+    pub fn redone(&mut self, edit: Edit) {
+        self.push_undo(edit);
+    }
+
+    /// Puts back an edit taken to redo it, to redo it again later.
+    pub fn redo_failed(&mut self, edit: Edit) {
+        self.redo.push(edit);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forgets all edits, for when the ids of all gates change.
+    pub fn clear(&mut self) {
+        *self = History::new(self.depth);
+    }
+
+    /// Current id of a gate recorded in an edit.
+    pub fn gate(&self, mut gate: GateId) -> GateId {
+        while let Some(next) = self.gates.get(&gate) {
+            gate = *next;
+        }
+        gate
+    }
+
+    pub fn input(&self, input: InputPin) -> InputPin {
+        self.gate(input.gate).input(input.index)
+    }
+
+    pub fn output(&self, output: OutputPin) -> OutputPin {
+        self.gate(output.gate).output(output.index)
+    }
+
+    /// Current id of a connection recorded in an edit.
+    pub fn connection(&self, mut connection: ConnectionId) -> ConnectionId {
+        while let Some(next) = self.connections.get(&connection) {
+            connection = *next;
+        }
+        connection
+    }
+
+    /// Records the new id of a gate recorded with given id.
+    pub fn gate_added(&mut self, recorded: GateId, id: GateId) {
+        let current = self.gate(recorded);
+        if current != id {
+            self.gates.insert(current, id);
+        }
+    }
+
+    /// Records the new id of a connection recorded with given id.
+    pub fn connection_added(&mut self, recorded: ConnectionId, id: ConnectionId) {
+        let current = self.connection(recorded);
+        if current != id {
+            self.connections.insert(current, id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gates::Not, logic_simulation::LogicSimulation};
+
+    fn moved(gate: GateId, x: f32) -> Edit {
+        Edit::MoveGate {
+            gate,
+            from: Vec2::ZERO,
+            to: Vec2::new(x, 0.),
+        }
+    }
+
+    #[test]
+    fn depth_limits_kept_edits() {
+        let mut sim = LogicSimulation::new();
+        let gate = sim.add_gate(Not);
+        let mut history = History::new(2);
+        for x in 0..3 {
+            history.push(moved(gate, x as f32));
+        }
+
+        assert_eq!(history.take_undo(), Some(moved(gate, 2.)));
+        history.undone(moved(gate, 2.));
+        assert_eq!(history.take_undo(), Some(moved(gate, 1.)));
+        assert_eq!(history.take_undo(), None);
+
+        // a new edit drops the undone ones
+        history.push(moved(gate, 3.));
+        assert!(!history.can_redo());
+
+        history.set_depth(0);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn ids_of_gates_added_again_are_followed() {
+        let mut sim = LogicSimulation::new();
+        let first = sim.add_gate(Not);
+        sim.remove_gate(first).unwrap();
+        let second = sim.add_gate(Not);
+        sim.remove_gate(second).unwrap();
+        let third = sim.add_gate(Not);
+
+        let mut history = History::default();
+        history.gate_added(first, second);
+        // edits recorded with either of the older ids lead to the newest one
+        history.gate_added(second, third);
+        assert_eq!(history.gate(first), third);
+        assert_eq!(history.gate(second), third);
+        assert_eq!(history.input(first.input(1)), third.input(1));
+    }
+}
@@ -0,0 +1,189 @@
+//! Handles of gates, pins and connections in a
+//! [`LogicSimulation`](crate::logic_simulation::LogicSimulation).
+//!
+//! Gate ids are generational, the slot of a removed gate is reused by a later
+//! gate with the next generation, so an id kept after its gate was removed
+//! never refers to the new gate.
+
+use std::{fmt, ops::Index};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GateId {
+    index: usize,
+    generation: u32,
+}
+
+impl GateId {
+    pub fn input(self, index: usize) -> InputPin {
+        InputPin { gate: self, index }
+    }
+
+    pub fn output(self, index: usize) -> OutputPin {
+        OutputPin { gate: self, index }
+    }
+}
+
+impl fmt::Display for GateId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.index)?;
+        if self.generation > 0 {
+            write!(f, "v{}", self.generation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Input of a gate, with the index of the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InputPin {
+    pub gate: GateId,
+    pub index: usize,
+}
+
+/// Output of a gate, with the index of the output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OutputPin {
+    pub gate: GateId,
+    pub index: usize,
+}
+
+/// Connection from an output to an input, ids are never reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConnectionId(pub(crate) u64);
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/// Values addressed by [`GateId`]s, iterated in ascending order of the ids.
+pub(crate) struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    /// Indices of empty slots
+    free: Vec<usize>,
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Arena<T> {
+        Arena {
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, value: T) -> GateId {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.generation += 1;
+                slot.value = Some(value);
+                GateId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                });
+                GateId {
+                    index: self.slots.len() - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: GateId) -> Option<T> {
+        let slot = self.slots.get_mut(id.index)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+
+        let value = slot.value.take()?;
+        self.free.push(id.index);
+        Some(value)
+    }
+
+    pub fn get(&self, id: GateId) -> Option<&T> {
+        let slot = self.slots.get(id.index)?;
+        (slot.generation == id.generation)
+            .then_some(slot.value.as_ref())
+            .flatten()
+    }
+
+    pub fn get_mut(&mut self, id: GateId) -> Option<&mut T> {
+        let slot = self.slots.get_mut(id.index)?;
+        (slot.generation == id.generation)
+            .then_some(slot.value.as_mut())
+            .flatten()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (GateId, &T)> + '_ {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let id = GateId {
+                index,
+                generation: slot.generation,
+            };
+            Some((id, slot.value.as_ref()?))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (GateId, &mut T)> + '_ {
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| {
+                let id = GateId {
+                    index,
+                    generation: slot.generation,
+                };
+                Some((id, slot.value.as_mut()?))
+            })
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = GateId> + '_ {
+        self.iter().map(|(id, _)| id)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> + '_ {
+        self.iter().map(|(_, value)| value)
+    }
+}
+
+impl<T> Index<GateId> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id: GateId) -> &T {
+        self.get(id).expect("no gate with the id")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_ids_are_not_reused() {
+        let mut arena = Arena::new();
+        let a = arena.insert("a");
+        let b = arena.insert("b");
+        assert_eq!(arena.remove(a), Some("a"));
+        assert_eq!(arena.remove(a), None);
+
+        // the slot is reused with a new generation
+        let c = arena.insert("c");
+        assert_ne!(c, a);
+        assert_eq!(arena.get(a), None);
+        assert_eq!(arena.get(c), Some(&"c"));
+        assert_eq!(arena.keys().collect::<Vec<_>>(), [c, b]);
+        assert_eq!((a.to_string(), c.to_string()), ("0".into(), "0v1".into()));
+    }
+}
@@ -0,0 +1,54 @@
+//! Simulation of digital logic circuits.
+//!
+//! Gates implement the [`gates::Gate`] trait and are connected and simulated
+//! by [`logic_simulation::LogicSimulation`], which refers to gates, pins and
+//! connections by the handles in [`id`]:
+//!
+//! ```
+//! use logic_sim::{gates::*, logic_simulation::LogicSimulation, signal::Signal};
+//!
+//! let mut sim = LogicSimulation::new();
+//! let high = sim.add_gate(High);
+//! let low = sim.add_gate(Low);
+//! let and = sim.add_gate(And);
+//! sim.add_connection(high.output(0), and.input(0))?;
+//! sim.add_connection(low.output(0), and.input(1))?;
+//!
+//! while !sim.is_stable() {
+//!     sim.simulate();
+//! }
+//! assert_eq!(sim.get_gate_state(and)?.1[0].bit(0), Signal::Low);
+//!
+//! // the gate has no third input
+//! assert!(sim.add_connection(high.output(0), and.input(2)).is_err());
+//! # Ok::<(), logic_sim::logic_simulation::SimulationError>(())
+//! ```
+//!
+//! Selected pins can be recorded step by step into [`waveform`] traces and
+//! exported as [`vcd`] files. Saved boards are read by [`board_file`], can be
+//! run without a window by [`headless`] and exported to [`verilog`], and
+//! netlists synthesized by Yosys are imported as boards by [`netlist`].
+//!
+//! The [`board`] module with the positions of gates on the board, the edit
+//! [`history`] of a board and the [`routing`] of its wires are only available
+//! with the `gui` feature, which is enabled by default and pulls in macroquad.
+
+#[cfg(feature = "gui")]
+pub mod board;
+pub mod board_file;
+pub mod bus;
+pub mod gates;
+pub mod headless;
+#[cfg(feature = "gui")]
+pub mod history;
+pub mod id;
+pub mod logic_simulation;
+pub mod net;
+pub mod netlist;
+#[cfg(feature = "gui")]
+pub mod routing;
+pub mod signal;
+pub mod subcircuit;
+pub mod vcd;
+pub mod verilog;
+pub mod waveform;
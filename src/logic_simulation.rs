@@ -1,112 +1,1677 @@
-use std::collections::HashMap;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt,
+};
 
-use crate::gates::Gate;
+use crate::{
+    bus::Bus,
+    gates::{Context, Gate},
+    id::{Arena, ConnectionId, GateId, InputPin, OutputPin},
+    net::{Conflict, Resolution},
+    signal::Signal,
+    waveform::{Probe, Waveforms},
+};
 
-type UpdateFn = Box<dyn Fn(&[bool], &mut [bool])>;
+/// Object safe counterpart of [`Gate`], so gates with different numbers of
+/// inputs and outputs can be stored together.
+trait DynGate {
+    fn update(&mut self, ctx: &Context, inputs: &[Bus], outputs: &mut [Bus]);
+    fn reset(&mut self);
+    fn needs_tick(&self) -> bool;
+    fn is_interactive(&self) -> bool;
+    fn press(&mut self);
+    fn release(&mut self);
+    fn params(&self) -> Vec<u64>;
+
+    /// Nested subcircuit the gate stands for
+    fn nested(&self) -> Option<&Nested> {
+        None
+    }
+
+    fn nested_mut(&mut self) -> Option<&mut Nested> {
+        None
+    }
+}
+
+struct GateBox<G, const INPUTS: usize, const OUTPUTS: usize>(G);
+
+impl<G, const INPUTS: usize, const OUTPUTS: usize> DynGate for GateBox<G, INPUTS, OUTPUTS>
+where
+    G: Gate<INPUTS, OUTPUTS>,
+{
+    fn update(&mut self, ctx: &Context, inputs: &[Bus], outputs: &mut [Bus]) {
+        self.0
+            .update(ctx, inputs.try_into().unwrap(), outputs.try_into().unwrap())
+    }
+
+    fn reset(&mut self) {
+        self.0.reset()
+    }
+
+    fn needs_tick(&self) -> bool {
+        self.0.needs_tick()
+    }
+
+    fn is_interactive(&self) -> bool {
+        self.0.is_interactive()
+    }
+
+    fn press(&mut self) {
+        self.0.press()
+    }
+
+    fn release(&mut self) {
+        self.0.release()
+    }
+
+    fn params(&self) -> Vec<u64> {
+        self.0.params()
+    }
+}
+
+/// Gate standing for a flattened subcircuit, its pins mirror the ports of the
+/// subcircuit and it is never updated itself.
+struct Boundary;
+
+impl DynGate for Boundary {
+    fn update(&mut self, _ctx: &Context, _inputs: &[Bus], _outputs: &mut [Bus]) {}
+
+    fn reset(&mut self) {}
+
+    fn needs_tick(&self) -> bool {
+        false
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+
+    fn press(&mut self) {}
+
+    fn release(&mut self) {}
+
+    fn params(&self) -> Vec<u64> {
+        Vec::new()
+    }
+}
+
+/// Subcircuit simulated by its own simulation, advanced by one step whenever
+/// the subcircuit is updated.
+struct Nested {
+    sim: LogicSimulation,
+    inputs: Vec<GateId>,
+    outputs: Vec<GateId>,
+}
+
+impl DynGate for Nested {
+    fn update(&mut self, ctx: &Context, inputs: &[Bus], outputs: &mut [Bus]) {
+        // the inner simulation was stable since it was last updated
+        self.sim.catch_up(ctx.tick() - 1);
+        for (port, value) in self.inputs.iter().zip(inputs) {
+            self.sim.drive(port.input(0), *value);
+        }
+
+        self.sim.simulate();
+
+        for (port, value) in self.outputs.iter().zip(outputs) {
+            *value = self.sim.gates[*port].outputs[0];
+        }
+    }
+
+    fn reset(&mut self) {
+        self.sim.reset()
+    }
+
+    fn needs_tick(&self) -> bool {
+        !self.sim.is_stable()
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+
+    fn press(&mut self) {}
+
+    fn release(&mut self) {}
+
+    fn params(&self) -> Vec<u64> {
+        Vec::new()
+    }
+
+    fn nested(&self) -> Option<&Nested> {
+        Some(self)
+    }
+
+    fn nested_mut(&mut self) -> Option<&mut Nested> {
+        Some(self)
+    }
+}
+
+/// Ports and contents of a flattened subcircuit.
+struct Shell {
+    /// All gates added for the subcircuit, including the ports
+    gates: Vec<GateId>,
+    inputs: Vec<GateId>,
+    outputs: Vec<GateId>,
+}
 
 struct GateState {
-    inputs: Box<[bool]>,
-    outputs: Box<[bool]>,
-    update_fn: UpdateFn,
-    name: &'static str,
+    inputs: Box<[Bus]>,
+    outputs: Box<[Bus]>,
+    /// Output values the gate will have once all of its scheduled events are
+    /// applied, used by the event-driven mode to avoid scheduling no-op events
+    projected_outputs: Box<[Bus]>,
+    gate: Box<dyn DynGate>,
+    name: Cow<'static, str>,
+    delay: u64,
 }
 
 impl GateState {
-    fn update(&mut self) {
-        (self.update_fn)(&self.inputs, &mut self.outputs);
+    fn update(&mut self, ctx: &Context) {
+        self.gate.update(ctx, &self.inputs, &mut self.outputs);
+    }
+
+    fn reset(&mut self) {
+        self.gate.reset();
+        for input in self.inputs.iter_mut() {
+            *input = Bus::new(input.width(), Signal::HighZ);
+        }
+        for output in self.outputs.iter_mut() {
+            *output = Bus::new(output.width(), Signal::Unknown);
+        }
+        self.projected_outputs.copy_from_slice(&self.outputs);
     }
 }
 
+/// Selects how [`LogicSimulation::simulate`] advances the simulation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SimulationMode {
+    /// Every gate is updated on every tick, outputs of all gates propagate to
+    /// the connected inputs with a delay of exactly one tick.
+    #[default]
+    Tick,
+    /// Only gates whose inputs changed are updated, each output change is
+    /// scheduled to happen after the propagation delay of the gate.
+    Event,
+}
+
+/// Output change scheduled to happen at some point in time.
+struct Event {
+    output: OutputPin,
+    value: Bus,
+}
+
+/// Problem with a net found during the last update of its input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub input: InputPin,
+    pub conflict: Conflict,
+}
+
+/// Invalid gate, pin or connection passed to a [`LogicSimulation`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimulationError {
+    /// No gate with the id was added, or it was removed since
+    UnknownGate(GateId),
+    UnknownInput(InputPin),
+    UnknownOutput(OutputPin),
+    UnknownConnection(ConnectionId),
+    /// The gate belongs to a flattened subcircuit, it is only removed with the
+    /// subcircuit and cannot belong to another one
+    InSubcircuit(GateId),
+    /// A port of a flattened subcircuit is not one of its gates
+    PortOutsideSubcircuit(GateId),
+    /// The gate does not stand for a nested subcircuit
+    NotNested(GateId),
+    /// Connected pins, or a pin and the value driving it, have different
+    /// widths
+    WidthMismatch {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimulationError::UnknownGate(gate) => write!(f, "unknown gate {gate}"),
+            SimulationError::UnknownInput(pin) => {
+                write!(f, "gate {} has no input {}", pin.gate, pin.index)
+            }
+            SimulationError::UnknownOutput(pin) => {
+                write!(f, "gate {} has no output {}", pin.gate, pin.index)
+            }
+            SimulationError::UnknownConnection(connection) => {
+                write!(f, "unknown connection {connection}")
+            }
+            SimulationError::InSubcircuit(gate) => {
+                write!(f, "gate {gate} belongs to a flattened subcircuit")
+            }
+            SimulationError::PortOutsideSubcircuit(gate) => {
+                write!(f, "port {gate} is not a gate of the subcircuit")
+            }
+            SimulationError::NotNested(gate) => {
+                write!(f, "gate {gate} is not a nested subcircuit")
+            }
+            SimulationError::WidthMismatch { expected, found } => {
+                write!(f, "expected {expected} bits, got {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+/// Simulation of gates and connections between them.
+///
+/// The evaluation order is deterministic, gates are always updated in
+/// ascending order of their ids. All gates updated in one step observe the
+/// inputs computed from the outputs of the previous step, so the order of
+/// updates within a step never changes the results and the same circuit
+/// always produces the same waveforms, including circuits with feedback such
+/// as latches.
+///
+/// Methods taking ids of gates or pins check them and return a
+/// [`SimulationError`] when the gate does not exist or has no such pin, so the
+/// simulation itself never refers to a missing gate.
 pub struct LogicSimulation {
-    counter: usize,
-    gates: HashMap<usize, GateState>,
-    connections: Vec<(usize, usize, usize, usize)>,
+    gates: Arena<GateState>,
+    /// Id of the next connection
+    counter: u64,
+    connections: BTreeMap<ConnectionId, (OutputPin, InputPin)>,
+    /// Connections from each output, to find the gates affected by a change
+    /// without going through all connections
+    fanout: BTreeMap<OutputPin, BTreeSet<ConnectionId>>,
+    /// Connections into each input, to resolve the input from its drivers
+    drivers: BTreeMap<InputPin, BTreeSet<ConnectionId>>,
+    mode: SimulationMode,
+    time: u64,
+    events: BTreeMap<u64, Vec<Event>>,
+    /// Gates that need to be re-evaluated in the next event-driven step
+    dirty: BTreeSet<GateId>,
+    default_resolution: Resolution,
+    /// Resolution policies of nets that do not use the default one, keyed by
+    /// the input driven by the net
+    resolutions: BTreeMap<InputPin, Resolution>,
+    diagnostics: BTreeMap<InputPin, Diagnostic>,
+    /// Values driven into inputs from outside of the simulation
+    external: BTreeMap<InputPin, Bus>,
+    /// Flattened subcircuits, keyed by the gates standing for them
+    shells: BTreeMap<GateId, Shell>,
+    /// Gates standing for nested subcircuits
+    nested: BTreeSet<GateId>,
+    /// Whether any input or output changed in the last step
+    changed: bool,
+    /// Recorded values of selected pins, sampled after every step
+    waveforms: Waveforms,
+}
+
+impl Default for LogicSimulation {
+    fn default() -> Self {
+        LogicSimulation::new()
+    }
 }
 
 impl LogicSimulation {
     pub fn new() -> LogicSimulation {
         LogicSimulation {
+            gates: Arena::new(),
             counter: 0,
-            gates: HashMap::new(),
-            connections: Vec::new(),
+            connections: BTreeMap::new(),
+            fanout: BTreeMap::new(),
+            drivers: BTreeMap::new(),
+            mode: SimulationMode::default(),
+            time: 0,
+            events: BTreeMap::new(),
+            dirty: BTreeSet::new(),
+            default_resolution: Resolution::default(),
+            resolutions: BTreeMap::new(),
+            diagnostics: BTreeMap::new(),
+            external: BTreeMap::new(),
+            shells: BTreeMap::new(),
+            nested: BTreeSet::new(),
+            changed: true,
+            waveforms: Waveforms::default(),
         }
     }
 
     pub fn add_gate<const INPUTS: usize, const OUTPUTS: usize>(
         &mut self,
         gate: impl Gate<INPUTS, OUTPUTS> + 'static,
-    ) -> usize {
-        let inputs = Box::new([false; INPUTS]);
-        let outputs = Box::new([false; OUTPUTS]);
-        let id = self.counter;
+    ) -> GateId {
+        // nothing drives the inputs until connected, and the outputs are
+        // unknown until the gate is updated for the first time
+        let inputs = Box::new(
+            gate.input_widths()
+                .map(|width| Bus::new(width, Signal::HighZ)),
+        );
+        let outputs = Box::new(
+            gate.output_widths()
+                .map(|width| Bus::new(width, Signal::Unknown)),
+        );
         let name = gate.name();
+        let delay = gate.delay();
+        self.insert_gate(
+            inputs,
+            outputs,
+            Box::new(GateBox(gate)),
+            Cow::Borrowed(name),
+            delay,
+        )
+    }
 
-        let update_fn: UpdateFn = Box::new(move |inputs, outputs| {
-            gate.update(inputs.try_into().unwrap(), outputs.try_into().unwrap())
+    fn insert_gate(
+        &mut self,
+        inputs: Box<[Bus]>,
+        outputs: Box<[Bus]>,
+        gate: Box<dyn DynGate>,
+        name: Cow<'static, str>,
+        delay: u64,
+    ) -> GateId {
+        let projected_outputs = outputs.clone();
+        let id = self.gates.insert(GateState {
+            inputs,
+            outputs,
+            projected_outputs,
+            gate,
+            name,
+            delay,
         });
+        self.dirty.insert(id);
+        id
+    }
+
+    /// Adds a gate standing for a subcircuit whose gates were already added to
+    /// this simulation, the inputs and outputs are ports of the subcircuit,
+    /// gates with a single input and a single output passing the value.
+    ///
+    /// Connections to the pins of the subcircuit lead to its ports, so the
+    /// subcircuit behaves exactly as if its gates were placed on the board.
+    /// Removing the subcircuit removes all of its gates, which cannot be
+    /// removed on their own.
+    pub fn add_flattened(
+        &mut self,
+        name: String,
+        gates: Vec<GateId>,
+        inputs: Vec<GateId>,
+        outputs: Vec<GateId>,
+    ) -> Result<GateId, SimulationError> {
+        let mut members = BTreeSet::new();
+        for gate in &gates {
+            if self.get_gate_parent(*gate)?.is_some() || !members.insert(*gate) {
+                return Err(SimulationError::InSubcircuit(*gate));
+            }
+        }
+        if let Some(port) = inputs
+            .iter()
+            .chain(&outputs)
+            .find(|port| !gates.contains(port))
+        {
+            return Err(SimulationError::PortOutsideSubcircuit(*port));
+        }
+        let input_pins = port_pins(self, &inputs, false)?;
+        let output_pins = port_pins(self, &outputs, true)?;
 
-        self.gates.insert(
+        let id = self.insert_gate(
+            input_pins,
+            output_pins,
+            Box::new(Boundary),
+            Cow::Owned(name),
+            1,
+        );
+        self.dirty.remove(&id);
+        self.shells.insert(
             id,
-            GateState {
+            Shell {
+                gates,
                 inputs,
                 outputs,
-                update_fn,
-                name,
             },
         );
+        Ok(id)
+    }
+
+    /// Adds a subcircuit simulated by its own simulation, the inputs and
+    /// outputs are ports of the subcircuit in that simulation, gates with a
+    /// single input and a single output passing the value.
+    ///
+    /// The inner simulation advances by one step in every step of this
+    /// simulation, so in the tick mode the subcircuit produces the same
+    /// waveforms as when flattened.
+    pub fn add_nested(
+        &mut self,
+        name: String,
+        sim: LogicSimulation,
+        inputs: Vec<GateId>,
+        outputs: Vec<GateId>,
+    ) -> Result<GateId, SimulationError> {
+        let input_pins = port_pins(&sim, &inputs, false)?;
+        let output_pins = port_pins(&sim, &outputs, true)?;
+        let nested = Nested {
+            sim,
+            inputs,
+            outputs,
+        };
+
+        let id = self.insert_gate(
+            input_pins,
+            output_pins,
+            Box::new(nested),
+            Cow::Owned(name),
+            1,
+        );
+        self.nested.insert(id);
+        Ok(id)
+    }
+
+    /// Drives an input from outside of the simulation, the value is resolved
+    /// together with the outputs connected to the input.
+    pub fn drive_input(&mut self, input: InputPin, value: Bus) -> Result<(), SimulationError> {
+        let width = self.input_width(input)?;
+        if value.width() != width {
+            return Err(SimulationError::WidthMismatch {
+                expected: width,
+                found: value.width(),
+            });
+        }
+
+        self.drive(self.input_target(input), value);
+        Ok(())
+    }
+
+    fn drive(&mut self, input: InputPin, value: Bus) {
+        if self.external.insert(input, value) != Some(value) {
+            self.dirty.insert(input.gate);
+        }
+    }
+
+    fn state(&self, id: GateId) -> Result<&GateState, SimulationError> {
+        self.gates.get(id).ok_or(SimulationError::UnknownGate(id))
+    }
+
+    fn state_mut(&mut self, id: GateId) -> Result<&mut GateState, SimulationError> {
+        self.gates
+            .get_mut(id)
+            .ok_or(SimulationError::UnknownGate(id))
+    }
+
+    /// Checks that the input exists, returns its width.
+    fn input_width(&self, input: InputPin) -> Result<usize, SimulationError> {
+        let state = self.state(input.gate)?;
+        let bus = state
+            .inputs
+            .get(input.index)
+            .ok_or(SimulationError::UnknownInput(input))?;
+        Ok(bus.width())
+    }
+
+    /// Checks that the output exists, returns its width.
+    fn output_width(&self, output: OutputPin) -> Result<usize, SimulationError> {
+        let state = self.state(output.gate)?;
+        let bus = state
+            .outputs
+            .get(output.index)
+            .ok_or(SimulationError::UnknownOutput(output))?;
+        Ok(bus.width())
+    }
+
+    /// Input a connection to given input leads to, inputs of flattened
+    /// subcircuits lead to their ports. The input must exist.
+    fn input_target(&self, mut input: InputPin) -> InputPin {
+        while let Some(shell) = self.shells.get(&input.gate) {
+            input = shell.inputs[input.index].input(0);
+        }
+        input
+    }
+
+    /// Output a connection from given output leads from, outputs of flattened
+    /// subcircuits lead from their ports. The output must exist.
+    fn output_source(&self, mut output: OutputPin) -> OutputPin {
+        while let Some(shell) = self.shells.get(&output.gate) {
+            output = shell.outputs[output.index].output(0);
+        }
+        output
+    }
+
+    /// Copies the values of the ports of flattened subcircuits to their pins.
+    fn update_shells(&mut self) {
+        for (id, shell) in &self.shells {
+            let inputs: Vec<Bus> = shell
+                .inputs
+                .iter()
+                .map(|port| self.gates[*port].inputs[0])
+                .collect();
+            let outputs: Vec<Bus> = shell
+                .outputs
+                .iter()
+                .map(|port| self.gates[*port].outputs[0])
+                .collect();
+
+            let state = self
+                .gates
+                .get_mut(*id)
+                .expect("shells are removed with their gates");
+            state.inputs.copy_from_slice(&inputs);
+            state.outputs.copy_from_slice(&outputs);
+            state.projected_outputs.copy_from_slice(&outputs);
+        }
+    }
+
+    /// Removes a gate and its connections, gates of flattened subcircuits are
+    /// removed with the subcircuit.
+    pub fn remove_gate(&mut self, id: GateId) -> Result<(), SimulationError> {
+        if self.get_gate_parent(id)?.is_some() {
+            return Err(SimulationError::InSubcircuit(id));
+        }
+        self.remove(id);
+        Ok(())
+    }
+
+    /// Removes an existing gate, with the gates of its shell.
+    fn remove(&mut self, id: GateId) {
+        self.nested.remove(&id);
+        if let Some(shell) = self.shells.remove(&id) {
+            for gate in shell.gates {
+                self.remove(gate);
+            }
+        }
+
+        let state = self.gates.remove(id).expect("the gate exists");
+        let outputs = (0..state.outputs.len()).map(|index| id.output(index));
+        let inputs = (0..state.inputs.len()).map(|index| id.input(index));
+        let connections: Vec<ConnectionId> = outputs
+            .filter_map(|output| self.fanout.get(&output))
+            .chain(inputs.filter_map(|input| self.drivers.get(&input)))
+            .flatten()
+            .copied()
+            .collect();
+        for connection in connections {
+            if let Some((_, input)) = self.unlink(connection) {
+                self.dirty.insert(input.gate);
+            }
+        }
+        self.dirty.remove(&id);
+        self.resolutions.retain(|input, _| input.gate != id);
+        self.diagnostics.retain(|input, _| input.gate != id);
+        self.external.retain(|input, _| input.gate != id);
+        self.waveforms.remove_gate(id);
+    }
+
+    /// Connects an output to an input, only pins of the same width can be
+    /// connected.
+    pub fn add_connection(
+        &mut self,
+        output: OutputPin,
+        input: InputPin,
+    ) -> Result<ConnectionId, SimulationError> {
+        let output_width = self.output_width(output)?;
+        let input_width = self.input_width(input)?;
+        if output_width != input_width {
+            return Err(SimulationError::WidthMismatch {
+                expected: input_width,
+                found: output_width,
+            });
+        }
+
+        let (output, input) = (self.output_source(output), self.input_target(input));
+        let id = ConnectionId(self.counter);
         self.counter += 1;
-        id
+        self.connections.insert(id, (output, input));
+        self.fanout.entry(output).or_default().insert(id);
+        self.drivers.entry(input).or_default().insert(id);
+        self.dirty.insert(input.gate);
+        Ok(id)
     }
 
-    pub fn remove_gate(&mut self, id: usize) {
-        if let Some(_) = self.gates.remove(&id) {
-            self.connections
-                .retain(|(output_gate_id, _, input_gate_id, _)| {
-                    *output_gate_id != id && *input_gate_id != id
-                });
+    pub fn remove_connection(&mut self, id: ConnectionId) -> Result<(), SimulationError> {
+        let (_, input) = self
+            .unlink(id)
+            .ok_or(SimulationError::UnknownConnection(id))?;
+        self.dirty.insert(input.gate);
+        Ok(())
+    }
+
+    /// Removes a connection together with its entries in the fanout and
+    /// driver indices, returns the connected pins.
+    fn unlink(&mut self, id: ConnectionId) -> Option<(OutputPin, InputPin)> {
+        let (output, input) = self.connections.remove(&id)?;
+        if let Some(connections) = self.fanout.get_mut(&output) {
+            connections.remove(&id);
+            if connections.is_empty() {
+                self.fanout.remove(&output);
+            }
         }
+        if let Some(connections) = self.drivers.get_mut(&input) {
+            connections.remove(&id);
+            if connections.is_empty() {
+                self.drivers.remove(&input);
+            }
+        }
+        Some((output, input))
+    }
+
+    pub fn get_gate_state(&self, id: GateId) -> Result<(&[Bus], &[Bus]), SimulationError> {
+        let gate = self.state(id)?;
+        Ok((&gate.inputs, &gate.outputs))
     }
 
-    pub fn add_connection(&mut self, from: usize, output: usize, to: usize, input: usize) {
-        self.connections.push((from, output, to, input));
+    pub fn get_gate_name(&self, id: GateId) -> Result<&str, SimulationError> {
+        Ok(&self.state(id)?.name)
     }
 
-    pub fn remove_connection(&mut self, from: usize, output: usize, to: usize, input: usize) {
-        self.connections
-            .retain(|connection| *connection != (from, output, to, input))
+    /// Returns the gate standing for the flattened subcircuit given gate was
+    /// added for, if any.
+    pub fn get_gate_parent(&self, id: GateId) -> Result<Option<GateId>, SimulationError> {
+        self.state(id)?;
+        Ok(self
+            .shells
+            .iter()
+            .find(|(_, shell)| shell.gates.contains(&id))
+            .map(|(parent, _)| *parent))
     }
 
-    pub fn get_gate_state(&self, id: usize) -> (&[bool], &[bool]) {
-        let gate = self.gates.get(&id).unwrap();
-        (&gate.inputs, &gate.outputs)
+    /// Returns the parameters needed to create the same gate again.
+    pub fn get_gate_params(&self, id: GateId) -> Result<Vec<u64>, SimulationError> {
+        Ok(self.state(id)?.gate.params())
+    }
+
+    pub fn is_gate_interactive(&self, id: GateId) -> Result<bool, SimulationError> {
+        Ok(self.state(id)?.gate.is_interactive())
+    }
+
+    /// Presses an interactive gate, such as a switch or a button, the change
+    /// shows up on its outputs in the next step.
+    pub fn press_gate(&mut self, id: GateId) -> Result<(), SimulationError> {
+        self.state_mut(id)?.gate.press();
+        self.dirty.insert(id);
+        Ok(())
+    }
+
+    pub fn release_gate(&mut self, id: GateId) -> Result<(), SimulationError> {
+        self.state_mut(id)?.gate.release();
+        self.dirty.insert(id);
+        Ok(())
+    }
+
+    /// Returns the propagation delay of the gate in time units.
+    pub fn get_gate_delay(&self, id: GateId) -> Result<u64, SimulationError> {
+        Ok(self.state(id)?.delay)
+    }
+
+    /// Overrides the propagation delay of a single gate, a delay of zero is
+    /// treated as one, as changes cannot propagate instantly.
+    pub fn set_gate_delay(&mut self, id: GateId, delay: u64) -> Result<(), SimulationError> {
+        let state = self.state_mut(id)?;
+        state.delay = delay;
+
+        // outputs scheduled with the old delay are scheduled again
+        state.projected_outputs.copy_from_slice(&state.outputs);
+        for events in self.events.values_mut() {
+            events.retain(|event| event.output.gate != id);
+        }
+        self.events.retain(|_, events| !events.is_empty());
+        self.dirty.insert(id);
+        Ok(())
+    }
+
+    /// Resolution policy used by nets without an explicit policy.
+    pub fn default_resolution(&self) -> Resolution {
+        self.default_resolution
+    }
+
+    pub fn set_default_resolution(&mut self, resolution: Resolution) {
+        self.default_resolution = resolution;
+        self.dirty.extend(self.gates.keys());
+    }
+
+    /// Returns the resolution policy of the net driving given input.
+    pub fn get_resolution(&self, input: InputPin) -> Result<Resolution, SimulationError> {
+        Ok(self
+            .resolution_override(input)?
+            .unwrap_or(self.default_resolution))
+    }
+
+    pub fn set_resolution(
+        &mut self,
+        input: InputPin,
+        resolution: Resolution,
+    ) -> Result<(), SimulationError> {
+        self.input_width(input)?;
+        let input = self.input_target(input);
+        self.resolutions.insert(input, resolution);
+        self.dirty.insert(input.gate);
+        Ok(())
     }
 
-    pub fn get_gate_name(&self, id: usize) -> &'static str {
-        self.gates.get(&id).unwrap().name
+    /// Returns the resolution policy of the net driving given input, if it
+    /// does not use the default one.
+    pub fn resolution_override(
+        &self,
+        input: InputPin,
+    ) -> Result<Option<Resolution>, SimulationError> {
+        self.input_width(input)?;
+        Ok(self.resolutions.get(&self.input_target(input)).copied())
+    }
+
+    /// Conflicting drivers found while updating the inputs, ordered by gate
+    /// and input.
+    pub fn diagnostics(&self) -> impl Iterator<Item = &Diagnostic> + '_ {
+        self.diagnostics.values()
+    }
+
+    pub fn mode(&self) -> SimulationMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: SimulationMode) {
+        if self.mode == mode {
+            return;
+        }
+
+        self.mode = mode;
+
+        // pending events are meaningless in the other mode, start over from
+        // the current outputs and let every gate settle again
+        self.events.clear();
+        self.dirty.clear();
+        for (id, state) in self.gates.iter_mut() {
+            state.projected_outputs.copy_from_slice(&state.outputs);
+            self.dirty.insert(id);
+        }
+    }
+
+    /// Current simulation time, advanced by one on every call to
+    /// [`LogicSimulation::simulate`].
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Returns the simulation to its initial state, the time starts over and
+    /// all gates and signals are reset, gates and connections are kept.
+    pub fn reset(&mut self) {
+        self.time = 0;
+        self.events.clear();
+        self.diagnostics.clear();
+        self.external.clear();
+        self.changed = true;
+        self.dirty.clear();
+        for (id, state) in self.gates.iter_mut() {
+            state.reset();
+            self.dirty.insert(id);
+        }
+        self.update_shells();
+        self.waveforms.clear();
+        self.sample();
     }
 
     pub fn simulate(&mut self) {
-        // set all gates' inputs to false, we always propagate output state to
-        // input state for all gates below, and this way we can check if
-        // something changed the input
-        for (_, state) in &mut self.gates {
-            for input in state.inputs.iter_mut() {
-                *input = false;
-            }
+        self.time += 1;
+
+        match self.mode {
+            SimulationMode::Tick => self.simulate_tick(),
+            SimulationMode::Event => self.simulate_event(),
+        }
+
+        self.update_shells();
+        self.sample();
+    }
+
+    /// Recorded values of the pins passed to [`LogicSimulation::record`].
+    pub fn waveforms(&self) -> &Waveforms {
+        &self.waveforms
+    }
+
+    /// Starts recording the value of a pin after every step, beginning with
+    /// its current value.
+    pub fn record(&mut self, probe: Probe) -> Result<(), SimulationError> {
+        let value = self.probe_value(probe)?;
+        if self.waveforms.add(probe) {
+            self.waveforms.push(probe, self.time, value);
         }
+        Ok(())
+    }
 
-        for (from, output, to, input) in &self.connections {
-            let output_state = self.gates.get(from).unwrap().outputs[*output];
-            let input_state = &mut self.gates.get_mut(to).unwrap().inputs[*input];
-            if *input_state {
-                // input was already set to true, do not allow it to be switched off
+    /// Stops recording a pin and drops its trace.
+    pub fn stop_recording(&mut self, probe: Probe) {
+        self.waveforms.remove(probe);
+    }
+
+    /// Starts recording a pin of a gate inside nested subcircuits, given by
+    /// the gates standing for the nested subcircuits from the outermost one.
+    /// The recorded values are kept by the simulation of the innermost
+    /// subcircuit, at the times of this simulation.
+    pub fn record_nested(&mut self, path: &[GateId], probe: Probe) -> Result<(), SimulationError> {
+        let Some((first, path)) = path.split_first() else {
+            return self.record(probe);
+        };
+        let nested = self
+            .state_mut(*first)?
+            .gate
+            .nested_mut()
+            .ok_or(SimulationError::NotNested(*first))?;
+        nested.sim.record_nested(path, probe)
+    }
+
+    /// Nested subcircuits with the simulations of their gates, in the order
+    /// of the ids of the gates standing for them.
+    pub fn nested(&self) -> impl Iterator<Item = (GateId, &LogicSimulation)> + '_ {
+        self.nested.iter().filter_map(|id| {
+            let nested = self.gates.get(*id)?.gate.nested()?;
+            Some((*id, &nested.sim))
+        })
+    }
+
+    /// Ports of all subcircuits, including the ones inside subcircuits, with
+    /// the gates standing for the nested subcircuits leading to the
+    /// simulation of the port, as passed to [`LogicSimulation::record_nested`].
+    pub fn subcircuit_ports(&self) -> Vec<(Vec<GateId>, GateId)> {
+        let mut ports: Vec<(Vec<GateId>, GateId)> = self
+            .shells
+            .values()
+            .flat_map(|shell| shell.inputs.iter().chain(&shell.outputs))
+            .map(|port| (Vec::new(), *port))
+            .collect();
+        for id in &self.nested {
+            let Some(nested) = self.gates[*id].gate.nested() else {
                 continue;
+            };
+            for port in nested.inputs.iter().chain(&nested.outputs) {
+                ports.push((vec![*id], *port));
+            }
+            for (mut path, port) in nested.sim.subcircuit_ports() {
+                path.insert(0, *id);
+                ports.push((path, port));
+            }
+        }
+        ports
+    }
+
+    /// Changes the number of samples kept in each trace, the oldest samples
+    /// are dropped. Traces of nested subcircuits keep the same number.
+    pub fn set_waveform_capacity(&mut self, capacity: usize) {
+        self.waveforms.set_capacity(capacity);
+        for id in &self.nested {
+            if let Some(nested) = self
+                .gates
+                .get_mut(*id)
+                .and_then(|state| state.gate.nested_mut())
+            {
+                nested.sim.set_waveform_capacity(capacity);
+            }
+        }
+    }
+
+    fn probe_value(&self, probe: Probe) -> Result<Bus, SimulationError> {
+        match probe {
+            Probe::Input(input) => {
+                self.input_width(input)?;
+                Ok(self.gates[input.gate].inputs[input.index])
+            }
+            Probe::Output(output) => {
+                self.output_width(output)?;
+                Ok(self.gates[output.gate].outputs[output.index])
+            }
+        }
+    }
+
+    /// Advances the time of a stable simulation without updating any gate,
+    /// as steps of a stable simulation change nothing, recorded pins keep
+    /// their values for the skipped steps.
+    fn catch_up(&mut self, time: u64) {
+        if self.waveforms.probes().next().is_none() && self.nested.is_empty() {
+            self.time = self.time.max(time);
+        }
+        while self.time < time {
+            self.time += 1;
+            self.sample();
+        }
+    }
+
+    /// Records the current values of all recorded pins, nested subcircuits
+    /// not updated in this step catch up with its time first.
+    fn sample(&mut self) {
+        let time = self.time;
+        for id in &self.nested {
+            if let Some(nested) = self
+                .gates
+                .get_mut(*id)
+                .and_then(|state| state.gate.nested_mut())
+            {
+                nested.sim.catch_up(time);
+            }
+        }
+        let values: Vec<(Probe, Bus)> = self
+            .waveforms
+            .probes()
+            .filter_map(|probe| Some((probe, self.probe_value(probe).ok()?)))
+            .collect();
+        for (probe, value) in values {
+            self.waveforms.push(probe, self.time, value);
+        }
+    }
+
+    /// Whether the simulation settled, that is nothing changed in the last
+    /// step, nothing is scheduled to change and no gate changes on its own,
+    /// so further steps would not change anything either.
+    pub fn is_stable(&self) -> bool {
+        !self.changed
+            && self.dirty.is_empty()
+            && self.events.is_empty()
+            && !self.gates.values().any(|state| state.gate.needs_tick())
+    }
+
+    /// Propagates outputs to the connected inputs of given gates, resolving
+    /// the value of every input from all outputs driving it. Returns whether
+    /// any of the inputs changed.
+    fn update_inputs(&mut self, gates: &BTreeSet<GateId>) -> bool {
+        let mut changed = false;
+        for gate in gates {
+            for index in 0..self.gates[*gate].inputs.len() {
+                let key = gate.input(index);
+                let drivers: Vec<Bus> = self
+                    .drivers
+                    .get(&key)
+                    .into_iter()
+                    .flatten()
+                    .map(|connection| {
+                        let (output, _) = self.connections[connection];
+                        self.gates[output.gate].outputs[output.index]
+                    })
+                    .chain(self.external.get(&key).copied())
+                    .collect();
+
+                let resolution = self
+                    .resolutions
+                    .get(&key)
+                    .copied()
+                    .unwrap_or(self.default_resolution);
+                let state = self.gates.get_mut(*gate).expect("dirty gates exist");
+                let input_state = &mut state.inputs[index];
+                let (bus, conflict) = resolution.resolve(input_state.width(), &drivers);
+
+                changed |= *input_state != bus;
+                *input_state = bus;
+                match conflict {
+                    Some(conflict) => {
+                        self.diagnostics.insert(
+                            key,
+                            Diagnostic {
+                                input: key,
+                                conflict,
+                            },
+                        );
+                    }
+                    None => {
+                        self.diagnostics.remove(&key);
+                    }
+                }
+            }
+        }
+
+        changed
+    }
+
+    fn simulate_tick(&mut self) {
+        // every gate is updated anyway, except the gates standing for
+        // flattened subcircuits, whose pins are only copied from their ports
+        self.dirty.clear();
+        let gates = self
+            .gates
+            .keys()
+            .filter(|id| !self.shells.contains_key(id))
+            .collect();
+        self.changed = self.update_inputs(&gates);
+
+        let ctx = Context::new(self.time);
+        for id in gates {
+            let state = self.gates.get_mut(id).expect("gates exist");
+            let outputs = state.outputs.clone();
+            state.update(&ctx);
+            self.changed |= state.outputs != outputs;
+        }
+    }
+
+    fn simulate_event(&mut self) {
+        let mut dirty = std::mem::take(&mut self.dirty);
+        for (id, state) in self.gates.iter() {
+            if state.gate.needs_tick() {
+                dirty.insert(id);
+            }
+        }
+        dirty.retain(|id| !self.shells.contains_key(id));
+
+        // recompute inputs of the affected gates only
+        self.changed = self.update_inputs(&dirty);
+
+        // the inputs reflect the state at the end of the previous step, so a
+        // gate with delay of one changes its outputs in this step, exactly as
+        // in the tick mode
+        let now = self.time - 1;
+        let ctx = Context::new(self.time);
+        for id in dirty {
+            let state = self.gates.get_mut(id).expect("dirty gates exist");
+            let mut outputs = state.projected_outputs.clone();
+            state.gate.update(&ctx, &state.inputs, &mut outputs);
+
+            let at = now + state.delay.max(1);
+            for (index, (projected, value)) in state
+                .projected_outputs
+                .iter_mut()
+                .zip(outputs.iter())
+                .enumerate()
+            {
+                if *projected == *value {
+                    continue;
+                }
+
+                *projected = *value;
+                self.events.entry(at).or_default().push(Event {
+                    output: id.output(index),
+                    value: *value,
+                });
             }
-            *input_state = output_state;
         }
 
-        for (_, state) in &mut self.gates {
-            state.update();
+        // apply the output changes scheduled for this point in time
+        let mut changed_outputs = HashSet::new();
+        for event in self.events.remove(&self.time).unwrap_or_default() {
+            // the gate may have been removed since the event was scheduled
+            if let Some(state) = self.gates.get_mut(event.output.gate) {
+                if state.outputs[event.output.index] != event.value {
+                    state.outputs[event.output.index] = event.value;
+                    changed_outputs.insert(event.output);
+                }
+            }
+        }
+
+        for output in &changed_outputs {
+            for connection in self.fanout.get(output).into_iter().flatten() {
+                let (_, input) = self.connections[connection];
+                self.dirty.insert(input.gate);
+            }
         }
+        self.changed |= !changed_outputs.is_empty();
+    }
+}
+
+/// Values of the pins of a subcircuit with given ports, gates with a single
+/// input and a single output.
+fn port_pins(
+    sim: &LogicSimulation,
+    ports: &[GateId],
+    output: bool,
+) -> Result<Box<[Bus]>, SimulationError> {
+    ports
+        .iter()
+        .map(|port| {
+            let state = sim.state(*port)?;
+            if output {
+                sim.output_width(port.output(0))?;
+                Ok(state.outputs[0])
+            } else {
+                sim.input_width(port.input(0))?;
+                Ok(state.inputs[0])
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gates::*;
+
+    /// Counts rising edges of its input, the count is on the output.
+    #[derive(Default)]
+    struct EdgeCounter {
+        last: Signal,
+        count: u64,
+    }
+
+    impl Gate<1, 1> for EdgeCounter {
+        const NAME: &'static str = "COUNT";
+
+        fn update(&mut self, _ctx: &Context, inputs: &[Bus; 1], outputs: &mut [Bus; 1]) {
+            let input = inputs[0].bit(0);
+            if self.last == N && input == Y {
+                self.count += 1;
+            }
+            self.last = input;
+            outputs[0] = Bus::from_u64(8, self.count);
+        }
+
+        fn reset(&mut self) {
+            *self = EdgeCounter::default();
+        }
+
+        fn output_widths(&self) -> [usize; 1] {
+            [8]
+        }
+    }
+
+    const Y: Signal = Signal::High;
+    const N: Signal = Signal::Low;
+    const X: Signal = Signal::Unknown;
+
+    fn input(sim: &LogicSimulation, gate: GateId, input: usize) -> Signal {
+        sim.get_gate_state(gate).unwrap().0[input].bit(0)
+    }
+
+    fn output(sim: &LogicSimulation, gate: GateId, output: usize) -> Signal {
+        sim.get_gate_state(gate).unwrap().1[output].bit(0)
+    }
+
+    #[test]
+    fn unconnected_inputs_produce_unknown_outputs() {
+        let mut sim = LogicSimulation::new();
+        let not = sim.add_gate(Not);
+
+        sim.simulate();
+        assert_eq!(input(&sim, not, 0), Signal::HighZ);
+        assert_eq!(output(&sim, not, 0), X);
+    }
+
+    #[test]
+    fn stateful_gates_keep_state_until_reset() {
+        for mode in [SimulationMode::Tick, SimulationMode::Event] {
+            let mut sim = LogicSimulation::new();
+            sim.set_mode(mode);
+
+            let clock = sim.add_gate(Clock::new(4, 50, 1));
+            let counter = sim.add_gate(EdgeCounter::default());
+            sim.add_connection(clock.output(0), counter.input(0))
+                .unwrap();
+
+            for _ in 0..10 {
+                sim.simulate();
+            }
+
+            // the clock rises at ticks 2, 6 and 10, the counter sees the first
+            // two of them
+            assert_eq!(sim.get_gate_state(counter).unwrap().1[0].to_u64(), Some(2));
+
+            sim.reset();
+            assert_eq!(sim.time(), 0);
+            assert_eq!(output(&sim, counter, 0), X);
+
+            // counting starts over
+            for _ in 0..4 {
+                sim.simulate();
+            }
+            assert_eq!(sim.get_gate_state(counter).unwrap().1[0].to_u64(), Some(1));
+        }
+    }
+
+    #[test]
+    fn pressed_switch_drives_its_output() {
+        for mode in [SimulationMode::Tick, SimulationMode::Event] {
+            let mut sim = LogicSimulation::new();
+            sim.set_mode(mode);
+
+            let switch = sim.add_gate(Switch::default());
+            let not = sim.add_gate(Not);
+            sim.add_connection(switch.output(0), not.input(0)).unwrap();
+            assert!(sim.is_gate_interactive(switch).unwrap());
+            assert!(!sim.is_gate_interactive(not).unwrap());
+
+            for _ in 0..5 {
+                sim.simulate();
+            }
+            assert_eq!(output(&sim, not, 0), Y);
+
+            sim.press_gate(switch).unwrap();
+            sim.release_gate(switch).unwrap();
+            for _ in 0..5 {
+                sim.simulate();
+            }
+            assert_eq!(output(&sim, switch, 0), Y);
+            assert_eq!(output(&sim, not, 0), N);
+        }
+    }
+
+    #[test]
+    fn contention_is_reported() {
+        let mut sim = LogicSimulation::new();
+        let high = sim.add_gate(High);
+        let low = sim.add_gate(Low);
+        let yes = sim.add_gate(Yes);
+        sim.add_connection(high.output(0), yes.input(0)).unwrap();
+        sim.add_connection(low.output(0), yes.input(0)).unwrap();
+
+        sim.simulate();
+        sim.simulate();
+        assert_eq!(input(&sim, yes, 0), X);
+        assert_eq!(
+            sim.diagnostics().collect::<Vec<_>>(),
+            [&Diagnostic {
+                input: yes.input(0),
+                conflict: Conflict::Contention
+            }]
+        );
+
+        sim.set_resolution(yes.input(0), Resolution::WiredOr)
+            .unwrap();
+        sim.simulate();
+        assert_eq!(input(&sim, yes, 0), Y);
+        assert_eq!(sim.diagnostics().count(), 0);
+
+        sim.set_resolution(yes.input(0), Resolution::WiredAnd)
+            .unwrap();
+        sim.simulate();
+        assert_eq!(input(&sim, yes, 0), N);
+
+        sim.set_resolution(yes.input(0), Resolution::Exclusive)
+            .unwrap();
+        sim.simulate();
+        assert_eq!(
+            sim.diagnostics().next().unwrap().conflict,
+            Conflict::MultipleDrivers
+        );
+    }
+
+    #[test]
+    fn recorded_pins_are_sampled_every_step() {
+        let mut sim = LogicSimulation::new();
+        let low = sim.add_gate(Low);
+        let not = sim.add_gate(Not);
+        sim.add_connection(low.output(0), not.input(0)).unwrap();
+        let probe = Probe::Output(not.output(0));
+        sim.record(probe).unwrap();
+        assert_eq!(
+            sim.record(Probe::Input(not.input(1))),
+            Err(SimulationError::UnknownInput(not.input(1)))
+        );
+
+        for _ in 0..3 {
+            sim.simulate();
+        }
+        let bits = |sim: &LogicSimulation| -> Vec<(u64, Signal)> {
+            let trace = sim.waveforms().trace(probe).unwrap();
+            trace
+                .samples()
+                .map(|(time, bus)| (time, bus.bit(0)))
+                .collect()
+        };
+        assert_eq!(bits(&sim), [(0, X), (1, X), (2, Y), (3, Y)]);
+
+        // the pin stays recorded from the start
+        sim.reset();
+        assert_eq!(bits(&sim), [(0, X)]);
+
+        sim.remove_gate(not).unwrap();
+        assert!(!sim.waveforms().is_recorded(probe));
+    }
+
+    #[test]
+    fn event_mode_respects_gate_delay() {
+        let mut sim = LogicSimulation::new();
+        sim.set_mode(SimulationMode::Event);
+
+        let high = sim.add_gate(High);
+        let not = sim.add_gate(Not);
+        sim.add_connection(high.output(0), not.input(0)).unwrap();
+        sim.set_gate_delay(not, 3).unwrap();
+
+        // the source settles in the first step, the inverter follows three
+        // steps later
+        for _ in 0..3 {
+            sim.simulate();
+            assert_eq!(output(&sim, not, 0), X);
+        }
+
+        sim.simulate();
+        assert_eq!(output(&sim, not, 0), N);
+    }
+
+    #[test]
+    fn changed_delay_applies_to_scheduled_outputs() {
+        let mut sim = LogicSimulation::new();
+        sim.set_mode(SimulationMode::Event);
+
+        let high = sim.add_gate(High);
+        let not = sim.add_gate(Not);
+        sim.add_connection(high.output(0), not.input(0)).unwrap();
+        sim.set_gate_delay(not, 5).unwrap();
+        sim.simulate();
+        sim.simulate();
+
+        // the change scheduled for step 6 happens in the next step instead
+        sim.set_gate_delay(not, 1).unwrap();
+        assert!(!sim.is_stable());
+        sim.simulate();
+        assert_eq!(output(&sim, not, 0), N);
+
+        while !sim.is_stable() {
+            sim.simulate();
+        }
+        sim.set_gate_delay(not, 2).unwrap();
+        assert!(!sim.is_stable());
+    }
+
+    #[test]
+    fn event_mode_matches_tick_mode_with_unit_delays() {
+        struct Ring {
+            sim: LogicSimulation,
+            gates: Vec<GateId>,
+            /// Connection from the low source to the nand
+            low: ConnectionId,
+            high: GateId,
+        }
+
+        fn build(mode: SimulationMode) -> Ring {
+            let mut sim = LogicSimulation::new();
+            sim.set_mode(mode);
+
+            // ring oscillator made of a nand and two inverters, held in a
+            // known state while the nand is disabled
+            let low = sim.add_gate(Low);
+            let high = sim.add_gate(High);
+            let gates = vec![sim.add_gate(Nand), sim.add_gate(Not), sim.add_gate(Not)];
+            let low = sim
+                .add_connection(low.output(0), gates[0].input(0))
+                .unwrap();
+            for index in 0..gates.len() {
+                let next = gates[(index + 1) % gates.len()];
+                sim.add_connection(gates[index].output(0), next.input(usize::from(index == 2)))
+                    .unwrap();
+                sim.set_gate_delay(gates[index], 1).unwrap();
+            }
+
+            Ring {
+                sim,
+                gates,
+                low,
+                high,
+            }
+        }
+
+        let mut tick = build(SimulationMode::Tick);
+        let mut event = build(SimulationMode::Event);
+
+        for step in 0..40 {
+            if step == 10 {
+                for ring in [&mut tick, &mut event] {
+                    ring.sim.remove_connection(ring.low).unwrap();
+                    ring.sim
+                        .add_connection(ring.high.output(0), ring.gates[0].input(0))
+                        .unwrap();
+                }
+            }
+
+            tick.sim.simulate();
+            event.sim.simulate();
+
+            for (tick_gate, event_gate) in tick.gates.iter().zip(&event.gates) {
+                assert_eq!(
+                    tick.sim.get_gate_state(*tick_gate).unwrap().1,
+                    event.sim.get_gate_state(*event_gate).unwrap().1
+                );
+            }
+        }
+
+        // the ring is oscillating
+        let nand = output(&tick.sim, tick.gates[0], 0);
+        tick.sim.simulate();
+        assert_ne!(output(&tick.sim, tick.gates[0], 0), X);
+        assert_ne!(output(&tick.sim, tick.gates[0], 0), nand);
+    }
+
+    #[test]
+    fn event_mode_only_updates_changed_gates() {
+        let mut sim = LogicSimulation::new();
+        sim.set_mode(SimulationMode::Event);
+
+        let high = sim.add_gate(High);
+        let not = sim.add_gate(Not);
+        let yes = sim.add_gate(Yes);
+        sim.add_connection(high.output(0), not.input(0)).unwrap();
+        sim.add_connection(not.output(0), yes.input(0)).unwrap();
+
+        for _ in 0..10 {
+            sim.simulate();
+        }
+
+        // everything settled, there is nothing left to do
+        assert!(sim.events.is_empty());
+        assert!(sim.dirty.is_empty());
+        assert_eq!(output(&sim, yes, 0), N);
+    }
+
+    #[test]
+    fn connection_indices_follow_removals() {
+        let mut sim = LogicSimulation::new();
+        let high = sim.add_gate(High);
+        let and = sim.add_gate(And);
+        let not = sim.add_gate(Not);
+        let first = sim.add_connection(high.output(0), and.input(0)).unwrap();
+        sim.add_connection(high.output(0), and.input(1)).unwrap();
+        sim.add_connection(and.output(0), not.input(0)).unwrap();
+        assert_eq!(sim.fanout[&high.output(0)].len(), 2);
+
+        sim.remove_connection(first).unwrap();
+        assert_eq!(sim.fanout[&high.output(0)].len(), 1);
+        assert!(!sim.drivers.contains_key(&and.input(0)));
+
+        // connections from and to a removed gate leave both indices
+        sim.remove_gate(and).unwrap();
+        assert!(sim.fanout.is_empty());
+        assert!(sim.drivers.is_empty());
+        assert!(sim.connections.is_empty());
+        assert!(sim.dirty.contains(&not));
+    }
+
+    fn sr_latch_waveform(mode: SimulationMode) -> Vec<(Signal, Signal)> {
+        let mut sim = LogicSimulation::new();
+        sim.set_mode(mode);
+
+        // the latch is set, active low
+        let set = sim.add_gate(Low);
+        let reset = sim.add_gate(High);
+        let q = sim.add_gate(Nand);
+        let q_inv = sim.add_gate(Nand);
+        sim.set_gate_delay(q, 1).unwrap();
+        sim.set_gate_delay(q_inv, 1).unwrap();
+
+        sim.add_connection(set.output(0), q.input(0)).unwrap();
+        sim.add_connection(q_inv.output(0), q.input(1)).unwrap();
+        sim.add_connection(reset.output(0), q_inv.input(1)).unwrap();
+        sim.add_connection(q.output(0), q_inv.input(0)).unwrap();
+
+        (0..16)
+            .map(|_| {
+                sim.simulate();
+                (output(&sim, q, 0), output(&sim, q_inv, 0))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sr_latch_waveform_is_deterministic() {
+        for mode in [SimulationMode::Tick, SimulationMode::Event] {
+            let expected = sr_latch_waveform(mode);
+            for _ in 0..10 {
+                assert_eq!(sr_latch_waveform(mode), expected);
+            }
+
+            // the outputs are unknown until the set input propagates through
+            // both nands
+            assert_eq!(&expected[..4], &[(X, X), (Y, X), (Y, N), (Y, N)]);
+        }
+    }
+
+    #[test]
+    fn simulation_settles() {
+        for mode in [SimulationMode::Tick, SimulationMode::Event] {
+            let mut sim = LogicSimulation::new();
+            sim.set_mode(mode);
+
+            let switch = sim.add_gate(Switch::default());
+            let not = sim.add_gate(Not);
+            let yes = sim.add_gate(Yes);
+            sim.add_connection(switch.output(0), not.input(0)).unwrap();
+            sim.add_connection(not.output(0), yes.input(0)).unwrap();
+
+            let mut steps = 0;
+            while !sim.is_stable() {
+                sim.simulate();
+                steps += 1;
+            }
+            assert_eq!(steps, 4);
+            assert_eq!(output(&sim, yes, 0), Y);
+
+            sim.press_gate(switch).unwrap();
+            assert!(!sim.is_stable());
+            while !sim.is_stable() {
+                sim.simulate();
+            }
+            assert_eq!(output(&sim, yes, 0), N);
+
+            sim.add_gate(Clock::default());
+            sim.simulate();
+            assert!(!sim.is_stable());
+        }
+    }
+
+    #[test]
+    fn flattened_subcircuits_settle() {
+        for mode in [SimulationMode::Tick, SimulationMode::Event] {
+            let mut sim = LogicSimulation::new();
+            sim.set_mode(mode);
+
+            let high = sim.add_gate(High);
+            let port_in = sim.add_gate(InputPort::default());
+            let not = sim.add_gate(Not);
+            let port_out = sim.add_gate(OutputPort::default());
+            sim.add_connection(port_in.output(0), not.input(0)).unwrap();
+            sim.add_connection(not.output(0), port_out.input(0))
+                .unwrap();
+            let inv = sim
+                .add_flattened(
+                    "INV".to_string(),
+                    vec![port_in, not, port_out],
+                    vec![port_in],
+                    vec![port_out],
+                )
+                .unwrap();
+            sim.add_connection(high.output(0), inv.input(0)).unwrap();
+
+            let mut steps = 0;
+            while !sim.is_stable() {
+                sim.simulate();
+                steps += 1;
+                assert!(steps < 20, "{mode:?} mode does not settle");
+            }
+            assert_eq!(output(&sim, inv, 0), N);
+            assert_eq!(input(&sim, inv, 0), Y);
+        }
+    }
+
+    #[test]
+    fn gates_of_flattened_subcircuits_stay_with_them() {
+        let mut sim = LogicSimulation::new();
+        let port_in = sim.add_gate(InputPort::default());
+        let not = sim.add_gate(Not);
+        let port_out = sim.add_gate(OutputPort::default());
+        sim.add_connection(port_in.output(0), not.input(0)).unwrap();
+        sim.add_connection(not.output(0), port_out.input(0))
+            .unwrap();
+        let other = sim.add_gate(OutputPort::default());
+        assert_eq!(
+            sim.add_flattened(
+                "INV".to_string(),
+                vec![port_in, not, port_out],
+                vec![port_in],
+                vec![other],
+            ),
+            Err(SimulationError::PortOutsideSubcircuit(other))
+        );
+        let inv = sim
+            .add_flattened(
+                "INV".to_string(),
+                vec![port_in, not, port_out],
+                vec![port_in],
+                vec![port_out],
+            )
+            .unwrap();
+        assert_eq!(
+            sim.add_flattened("NOT".to_string(), vec![not], Vec::new(), Vec::new()),
+            Err(SimulationError::InSubcircuit(not))
+        );
+
+        assert_eq!(
+            sim.remove_gate(port_in),
+            Err(SimulationError::InSubcircuit(port_in))
+        );
+        sim.simulate();
+        sim.remove_gate(inv).unwrap();
+        for gate in [inv, port_in, not, port_out] {
+            assert_eq!(
+                sim.get_gate_state(gate),
+                Err(SimulationError::UnknownGate(gate))
+            );
+        }
+        sim.simulate();
+    }
+
+    #[test]
+    fn invalid_gates_and_pins_are_errors() {
+        let mut sim = LogicSimulation::new();
+        let high = sim.add_gate(High);
+        let not = sim.add_gate(Not);
+        let wide = sim.add_gate(Wide::new(Not, 8));
+
+        assert_eq!(
+            sim.add_connection(high.output(1), not.input(0)),
+            Err(SimulationError::UnknownOutput(high.output(1)))
+        );
+        assert_eq!(
+            sim.add_connection(high.output(0), not.input(1)),
+            Err(SimulationError::UnknownInput(not.input(1)))
+        );
+        assert_eq!(
+            sim.add_connection(high.output(0), wide.input(0)),
+            Err(SimulationError::WidthMismatch {
+                expected: 8,
+                found: 1
+            })
+        );
+        assert_eq!(
+            sim.drive_input(wide.input(0), Bus::new(1, Signal::High)),
+            Err(SimulationError::WidthMismatch {
+                expected: 8,
+                found: 1
+            })
+        );
+
+        // a removed gate is gone for good, even when its slot is reused
+        let connection = sim.add_connection(high.output(0), not.input(0)).unwrap();
+        sim.remove_gate(high).unwrap();
+        let low = sim.add_gate(Low);
+        assert_ne!(low, high);
+        assert_eq!(
+            sim.remove_connection(connection),
+            Err(SimulationError::UnknownConnection(connection))
+        );
+        assert_eq!(
+            sim.get_gate_state(high).unwrap_err(),
+            SimulationError::UnknownGate(high)
+        );
+        assert_eq!(
+            sim.remove_gate(high),
+            Err(SimulationError::UnknownGate(high))
+        );
+        assert_eq!(
+            sim.press_gate(high),
+            Err(SimulationError::UnknownGate(high))
+        );
+        assert_eq!(
+            sim.get_gate_name(high).unwrap_err().to_string(),
+            format!("unknown gate {high}")
+        );
+
+        sim.simulate();
+        assert_eq!(input(&sim, not, 0), Signal::HighZ);
+        assert_eq!(output(&sim, low, 0), N);
     }
 }
@@ -1,15 +1,26 @@
-use gates::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+use logic_sim::{
+    board::{gate_size, pin_offset, snap, BoardSimulation, GRID_PITCH},
+    board_file::BoardFile,
+    bus::Bus,
+    gates::*,
+    headless,
+    id::{ConnectionId, GateId, InputPin, OutputPin},
+    logic_simulation::SimulationMode,
+    net::{Conflict, Resolution},
+    netlist, routing,
+    signal::Signal,
+    subcircuit::SubcircuitMode,
+    verilog,
+    waveform::{Probe, Trace},
+};
 use macroquad::{
     hash,
     prelude::*,
     ui::{root_ui, Skin},
 };
 
-use crate::board::BoardSimulation;
-
-mod gates;
-mod logic_simulation;
-
 fn is_point_inside_box(
     (point_x, point_y): (f32, f32),
     (box_x, box_y, box_w, box_h): (f32, f32, f32, f32),
@@ -17,24 +28,184 @@ fn is_point_inside_box(
     point_x > box_x && point_x < box_x + box_w && point_y > box_y && point_y < box_y + box_h
 }
 
+fn signal_color(signal: Signal) -> Color {
+    match signal {
+        Signal::Low => GRAY,
+        Signal::High => RED,
+        Signal::Unknown => ORANGE,
+        Signal::HighZ => SKYBLUE,
+    }
+}
+
+fn wire_color(signal: Signal) -> Color {
+    match signal {
+        Signal::Low => WHITE,
+        signal => signal_color(signal),
+    }
+}
+
+fn bus_color(bus: Bus) -> Color {
+    if bus.width() == 1 {
+        return signal_color(bus.bit(0));
+    }
+
+    if bus.bits().all(|bit| bit == Signal::HighZ) {
+        SKYBLUE
+    } else if bus.to_u64().is_none() {
+        ORANGE
+    } else {
+        GOLD
+    }
+}
+
+fn segment_color(signal: Signal) -> Color {
+    match signal {
+        Signal::Low => DARKGRAY,
+        signal => signal_color(signal),
+    }
+}
+
+/// Draws segments a to g of a seven-segment digit into given box.
+fn draw_segments(x: f32, y: f32, w: f32, h: f32, colors: [Color; 7]) {
+    let (left, right) = (x + w * 0.3, x + w * 0.7);
+    let (top, middle, bottom) = (y + h * 0.15, y + h * 0.5, y + h * 0.85);
+    let segments = [
+        (left, top, right, top),
+        (right, top, right, middle),
+        (right, middle, right, bottom),
+        (left, bottom, right, bottom),
+        (left, middle, left, bottom),
+        (left, top, left, middle),
+        (left, middle, right, middle),
+    ];
+
+    let thickness = w * 0.06;
+    for ((x1, y1, x2, y2), color) in segments.into_iter().zip(colors) {
+        draw_line(x1, y1, x2, y2, thickness, color);
+    }
+}
+
+/// Draws the face of output indicators, returns false for gates that are not
+/// indicators.
+fn draw_indicator(name: &str, x: f32, y: f32, w: f32, h: f32, inputs: &[Bus]) -> bool {
+    match name {
+        "LED" => {
+            let radius = h / 2. - 6.;
+            let color = segment_color(inputs[0].bit(0));
+            draw_circle(x + w / 2., y + h / 2., radius, color);
+        }
+        "7SEG" => {
+            let colors = std::array::from_fn(|segment| segment_color(inputs[segment].bit(0)));
+            draw_segments(x, y, w, h, colors);
+        }
+        "HEX" => {
+            let colors = match HexDisplay::digit(inputs) {
+                Some(digit) => HexDisplay::segments(digit).map(|lit| segment_color(lit.into())),
+                None => [ORANGE; 7],
+            };
+            draw_segments(x, y, w, h, colors);
+        }
+        _ => return false,
+    }
+
+    true
+}
+
+fn bus_wire_color(bus: Bus) -> Color {
+    if bus.width() == 1 {
+        wire_color(bus.bit(0))
+    } else {
+        bus_color(bus)
+    }
+}
+
 enum GateMouseHover {
     Input(usize, Vec2),
     Output(usize, Vec2),
     Gate(Vec2),
+    /// The knob of an interactive gate, like a switch or a button.
+    Control,
+}
+
+/// Gate dragged by the mouse with the offset of the mouse, and the positions
+/// the dragged gates started from.
+type Drag = (GateId, Vec2, Vec<(GateId, Vec2)>);
+
+/// Part of the board shown in the window. Gates and wires are placed in
+/// board coordinates, the mouse is in screen coordinates.
+struct View {
+    /// Point of the board in the middle of the window
+    center: Vec2,
+    zoom: f32,
+}
+
+impl View {
+    const ZOOM_STEP: f32 = 1.1;
+    const MIN_ZOOM: f32 = 0.1;
+    const MAX_ZOOM: f32 = 10.;
+
+    /// View with the top left corner of the board in the top left corner of
+    /// the window.
+    fn new() -> View {
+        View {
+            center: Vec2::new(screen_width() / 2., screen_height() / 2.),
+            zoom: 1.,
+        }
+    }
+
+    fn camera(&self) -> Camera2D {
+        let size = Vec2::new(screen_width(), screen_height()) / self.zoom;
+        let corner = self.center - size / 2.;
+        Camera2D::from_display_rect(Rect::new(corner.x, corner.y, size.x, size.y))
+    }
+
+    /// Corners of the part of the board shown in the window.
+    fn visible(&self) -> (Vec2, Vec2) {
+        let camera = self.camera();
+        (
+            camera.screen_to_world(Vec2::ZERO),
+            camera.screen_to_world(Vec2::new(screen_width(), screen_height())),
+        )
+    }
+
+    /// Position of the mouse on the board.
+    fn mouse(&self) -> Vec2 {
+        self.camera().screen_to_world(mouse_position().into())
+    }
+
+    /// Moves the board with the mouse moved by given distance on the screen.
+    fn pan(&mut self, delta: Vec2) {
+        self.center -= delta / self.zoom;
+    }
+
+    /// Zooms in or out by one step, keeping the point of the board under the
+    /// mouse in place.
+    fn zoom_at(&mut self, screen: Vec2, zoom_in: bool) {
+        let fixed = self.camera().screen_to_world(screen);
+        let step = if zoom_in {
+            View::ZOOM_STEP
+        } else {
+            1. / View::ZOOM_STEP
+        };
+        self.zoom = (self.zoom * step).clamp(View::MIN_ZOOM, View::MAX_ZOOM);
+
+        let screen_middle = Vec2::new(screen_width() / 2., screen_height() / 2.);
+        self.center = fixed - (screen - screen_middle) / self.zoom;
+    }
 }
 
 fn draw_gate(
     name: &str,
     x: f32,
     y: f32,
-    inputs: &[bool],
-    outputs: &[bool],
+    inputs: &[Bus],
+    outputs: &[Bus],
+    interactive: bool,
+    mouse: Vec2,
 ) -> Option<GateMouseHover> {
-    let max_io_len = usize::max(inputs.len(), outputs.len()) as f32;
     let io_h = 20f32;
     let io_w = 20f32;
-    let io_spacing = 5f32;
-    let h = max_io_len * io_h + max_io_len * io_spacing + io_spacing;
+    let h = gate_size(inputs.len(), outputs.len());
     let w = h;
 
     let (font_size, font_scale, font_aspect) = camera_font_scale(h / 2.);
@@ -51,15 +222,33 @@ fn draw_gate(
     let whitish = Color::from_rgba(0xcc, 0xcc, 0xcc, 0xff);
     draw_rectangle(x, y, w, h, whitish);
 
-    let mouse_pos = mouse_position();
+    let mouse_pos = (mouse.x, mouse.y);
     let mut mouse_hover = None;
 
-    let dt = h / inputs.len() as f32;
+    if interactive {
+        // the knob takes the middle of the gate, the border around it is
+        // left for dragging
+        let knob_spacing = io_w / 4.;
+        let knob = (
+            x + knob_spacing,
+            y + knob_spacing,
+            w - 2. * knob_spacing,
+            h - 2. * knob_spacing,
+        );
+        let color = outputs.first().map_or(GRAY, |output| bus_color(*output));
+        draw_rectangle(knob.0, knob.1, knob.2, knob.3, color);
+
+        if is_point_inside_box(mouse_pos, knob) {
+            mouse_hover = Some(GateMouseHover::Control);
+            draw_rectangle_lines(knob.0, knob.1, knob.2, knob.3, 2f32, WHITE);
+        }
+    }
+
     for (index, state) in inputs.iter().enumerate() {
-        let t = 0.5 * dt + index as f32 * dt;
+        let t = pin_offset(h, inputs.len(), index, false).y;
         let in_x = x - io_w / 2.;
         let in_y = y + t - (io_h / 2.);
-        draw_rectangle(in_x, in_y, io_w, io_h, if *state { RED } else { GRAY });
+        draw_rectangle(in_x, in_y, io_w, io_h, bus_color(*state));
 
         if is_point_inside_box(mouse_pos, (in_x, in_y, io_w, io_h)) {
             mouse_hover = Some(GateMouseHover::Input(index, (x, in_y + io_h / 2.).into()));
@@ -67,12 +256,11 @@ fn draw_gate(
         }
     }
 
-    let dt = h / outputs.len() as f32;
     for (index, state) in outputs.iter().enumerate() {
-        let t = 0.5 * dt + index as f32 * dt;
+        let t = pin_offset(h, outputs.len(), index, true).y;
         let out_x = x + w - io_w / 2.;
         let out_y = y + t - (io_h / 2.);
-        draw_rectangle(out_x, out_y, io_w, io_h, if *state { RED } else { GRAY });
+        draw_rectangle(out_x, out_y, io_w, io_h, bus_color(*state));
 
         if is_point_inside_box(mouse_pos, (out_x, out_y, io_w, io_h)) {
             mouse_hover = Some(GateMouseHover::Output(
@@ -83,141 +271,384 @@ fn draw_gate(
         }
     }
 
-    draw_text_ex(
-        name,
-        x + (w - text_dimensions.width) / 2.,
-        y + (h - text_dimensions.height) / 2. + text_dimensions.offset_y,
-        text_params,
-    );
+    if !draw_indicator(name, x, y, w, h, inputs) {
+        draw_text_ex(
+            name,
+            x + (w - text_dimensions.width) / 2.,
+            y + (h - text_dimensions.height) / 2. + text_dimensions.offset_y,
+            text_params,
+        );
+    }
 
     if mouse_hover.is_some() {
         mouse_hover
     } else if is_point_inside_box(mouse_pos, (x, y, w, h)) {
-        Some(GateMouseHover::Gate(mouse_pos.into()))
+        Some(GateMouseHover::Gate(mouse))
     } else {
         None
     }
 }
 
-mod board {
-    use std::collections::HashMap;
+fn save_board(simulation: &BoardSimulation, path: &str) -> Result<(), String> {
+    std::fs::write(path, simulation.to_file().to_string()).map_err(|error| error.to_string())
+}
+
+fn read_board_file(path: &str) -> Result<BoardFile, String> {
+    let text = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+    let file = if netlist::is_netlist(path) {
+        netlist::import(&text)
+    } else {
+        BoardFile::parse(&text)
+    };
+    file.map_err(|error| error.to_string())
+}
+
+/// Path a loaded board is saved to, imported netlists are saved as board
+/// files next to them instead of being overwritten.
+fn save_path(path: &str) -> String {
+    if netlist::is_netlist(path) {
+        std::path::Path::new(path)
+            .with_extension("lsim")
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        path.to_string()
+    }
+}
+
+fn load_board(path: &str) -> Result<BoardSimulation, String> {
+    BoardSimulation::from_file(&read_board_file(path)?).map_err(|error| error.to_string())
+}
 
-    use macroquad::prelude::Vec2;
+/// Widths offered for bus gates, splitters and mergers.
+const BUS_WIDTHS: [usize; 4] = [2, 4, 8, 16];
 
-    use crate::{gates::Gate, logic_simulation::LogicSimulation};
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("--headless") {
+        std::process::exit(headless::run(&args[1..]));
+    }
 
-    pub(crate) struct BoardSimulation {
-        sim: LogicSimulation,
-        gates: HashMap<usize, Vec2>,
-        connections: Vec<((usize, usize, Vec2), (usize, usize, Vec2))>,
+    macroquad::Window::new("logic-sim", window_main());
+}
+
+/// Draws the lines of a grid with given pitch over the visible part of the
+/// board.
+fn draw_grid(view: &View, pitch: f32) {
+    // lines closer than a few pixels would cover the whole board
+    if pitch * view.zoom < 4. {
+        return;
     }
 
-    impl BoardSimulation {
-        pub(crate) fn new() -> BoardSimulation {
-            BoardSimulation {
-                sim: LogicSimulation::new(),
-                gates: HashMap::new(),
-                connections: Vec::new(),
-            }
+    let color = Color::from_rgba(0x30, 0x30, 0x30, 0xff);
+    let thickness = 1. / view.zoom;
+    let (min, max) = view.visible();
+    let first = snap(min, pitch);
+    let mut x = first.x;
+    while x < max.x {
+        draw_line(x, min.y, x, max.y, thickness, color);
+        x += pitch;
+    }
+    let mut y = first.y;
+    while y < max.y {
+        draw_line(min.x, y, max.x, y, thickness, color);
+        y += pitch;
+    }
+}
+
+/// Pixels on the screen within which a dragged gate lines up with other gates.
+const ALIGN_TOLERANCE: f32 = 5.;
+
+/// Pixels on the screen within which the mouse is over a wire.
+const WIRE_TOLERANCE: f32 = 5.;
+
+/// Size of the handles of bend points of wires.
+const WAYPOINT_SIZE: f32 = 8.;
+
+/// Height of the title line of the waveform panel.
+const WAVE_HEADER: f32 = 20.;
+
+/// Height of a single trace in the waveform panel.
+const TRACE_HEIGHT: f32 = 30.;
+
+/// Width of the names of the traces in the waveform panel.
+const TRACE_LABEL_WIDTH: f32 = 200.;
+
+/// Part of the recorded waveforms shown in the panel at the bottom of the
+/// window, with the times marked by the cursors.
+struct WaveView {
+    /// Pixels per step of the simulation
+    scale: f32,
+    /// Time at the right edge of the panel, the latest samples are followed
+    /// when none
+    end: Option<u64>,
+    /// Times marked with the left and the right mouse button
+    cursors: [Option<u64>; 2],
+}
+
+impl WaveView {
+    const ZOOM_STEP: f32 = 1.25;
+    const MIN_SCALE: f32 = 0.5;
+    const MAX_SCALE: f32 = 50.;
+
+    fn new() -> WaveView {
+        WaveView {
+            scale: 8.,
+            end: None,
+            cursors: [None; 2],
         }
+    }
 
-        pub(crate) fn add_gate<const INPUTS: usize, const OUTPUTS: usize>(
-            &mut self,
-            gate: impl Gate<INPUTS, OUTPUTS> + 'static,
-            pos: Vec2,
-        ) {
-            let gate_id = self.sim.add_gate(gate);
-            self.gates.insert(gate_id, pos);
+    /// Height of the panel showing given number of traces, the panel takes
+    /// at most half of the window.
+    fn height(traces: usize) -> f32 {
+        if traces == 0 {
+            return 0.;
         }
+        let rows = traces.min(((screen_height() / 2. - WAVE_HEADER) / TRACE_HEIGHT) as usize);
+        WAVE_HEADER + rows as f32 * TRACE_HEIGHT
+    }
 
-        pub(crate) fn remove_gate(&mut self, gate_id: usize) {
-            self.sim.remove_gate(gate_id);
-            if let Some(_) = self.gates.remove(&gate_id) {
-                self.connections
-                    .retain(|(output, input)| output.0 != gate_id && input.0 != gate_id);
+    /// Time of the step drawn at given position of the screen, given the
+    /// time at the right edge.
+    fn time_at(&self, x: f32, end: u64) -> u64 {
+        let steps = ((screen_width() - x) / self.scale).ceil() as u64;
+        (end + 1).saturating_sub(steps.max(1))
+    }
+
+    /// Position on the screen where the step at given time starts.
+    fn x(&self, time: u64, end: u64) -> f32 {
+        screen_width() - (end + 1 - time.min(end + 1)) as f32 * self.scale
+    }
+}
+
+/// Draws runs of equal values of a trace between given times, single bits as
+/// levels and wider buses as boxes with their values.
+fn draw_trace(trace: &Trace, waves: &WaveView, (first, end): (u64, u64), top: f32) {
+    let (high, low) = (top + 5., top + TRACE_HEIGHT - 5.);
+    let middle = (high + low) / 2.;
+    let level = |bus: Bus| match bus.bit(0) {
+        Signal::High => high,
+        Signal::Low => low,
+        _ => middle,
+    };
+
+    let mut runs: Vec<(u64, u64, Bus)> = Vec::new();
+    for time in first..=end {
+        let Some(value) = trace.value_at(time) else {
+            continue;
+        };
+        match runs.last_mut() {
+            Some((_, run_end, run_value)) if *run_end == time && *run_value == value => {
+                *run_end = time + 1;
             }
+            _ => runs.push((time, time + 1, value)),
         }
+    }
 
-        pub(crate) fn add_connection(
-            &mut self,
-            (input_gate_id, input_id, input_offset): (usize, usize, Vec2),
-            (output_gate_id, output_id, output_offset): (usize, usize, Vec2),
-        ) {
-            self.sim
-                .add_connection(output_gate_id, output_id, input_gate_id, input_id);
-            self.connections.push((
-                (output_gate_id, output_id, output_offset),
-                (input_gate_id, input_id, input_offset),
-            ));
+    let mut last_level = None;
+    for (start, run_end, value) in runs {
+        let (x1, x2) = (waves.x(start, end), waves.x(run_end, end));
+        let color = bus_wire_color(value);
+        if value.width() == 1 {
+            let y = level(value);
+            if let Some(last) = last_level {
+                draw_line(x1, last, x1, y, 1., color);
+            }
+            draw_line(x1, y, x2, y, 2., color);
+            last_level = Some(y);
+        } else {
+            draw_line(x1, high, x2, high, 1., color);
+            draw_line(x1, low, x2, low, 1., color);
+            draw_line(x1, high, x1, low, 1., color);
+            let text = value.to_string();
+            let dimensions = measure_text(&text, None, 16, 1.);
+            if dimensions.width + 6. < x2 - x1 {
+                draw_text(&text, x1 + 3., middle + dimensions.height / 2., 16., color);
+            }
         }
+    }
+}
 
-        pub(crate) fn remove_connection(&mut self, input: (usize, usize), output: (usize, usize)) {
-            self.sim
-                .remove_connection(output.0, output.1, input.0, input.1);
+/// Name of a recorded pin shown next to its trace.
+fn probe_label(simulation: &BoardSimulation, probe: Probe) -> String {
+    let name = simulation.gate_name(probe.gate()).unwrap_or("?");
+    match probe {
+        Probe::Input(input) => format!("{name} #{} in {}", input.gate, input.index),
+        Probe::Output(output) => format!("{name} #{} out {}", output.gate, output.index),
+    }
+}
+
+/// Draws the recorded traces in a panel at the bottom of the window and
+/// handles the mouse over it, returns a trace the user asked to remove.
+fn draw_waveforms(simulation: &BoardSimulation, waves: &mut WaveView) -> Option<Probe> {
+    let waveforms = simulation.waveforms();
+    let traces: Vec<(Probe, &Trace)> = waveforms.traces().collect();
+    let height = WaveView::height(traces.len());
+    if height == 0. {
+        return None;
+    }
 
-            self.connections.retain(
-                |((output_gate_id, output_id, _), (input_gate_id, input_id, _))| {
-                    ((*output_gate_id, *output_id), (*input_gate_id, *input_id)) != (output, input)
-                },
-            )
+    let top = screen_height() - height;
+    let (mouse_x, mouse_y) = mouse_position();
+    let over = mouse_y > top;
+    let latest = waveforms.last_time().unwrap_or(simulation.time());
+
+    // the wheel zooms around the right edge, with shift it scrolls in time
+    let (_, wheel) = mouse_wheel();
+    let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+    if over && wheel != 0. {
+        if shift {
+            let step = ((screen_width() - TRACE_LABEL_WIDTH) / waves.scale / 10.).max(1.) as u64;
+            let end = waves.end.unwrap_or(latest);
+            let end = if wheel > 0. {
+                end.saturating_sub(step)
+            } else {
+                end + step
+            };
+            waves.end = (end < latest).then_some(end);
+        } else {
+            let step = if wheel > 0. {
+                WaveView::ZOOM_STEP
+            } else {
+                1. / WaveView::ZOOM_STEP
+            };
+            waves.scale = (waves.scale * step).clamp(WaveView::MIN_SCALE, WaveView::MAX_SCALE);
         }
+    }
 
-        pub(crate) fn simulate(&mut self) {
-            self.sim.simulate()
+    let end = waves.end.unwrap_or(latest);
+    let first = waves.time_at(TRACE_LABEL_WIDTH, end);
+    let over_traces = over && mouse_x > TRACE_LABEL_WIDTH;
+    for (cursor, button) in [MouseButton::Left, MouseButton::Right]
+        .into_iter()
+        .enumerate()
+    {
+        if over_traces && is_mouse_button_pressed(button) {
+            waves.cursors[cursor] = Some(waves.time_at(mouse_x, end));
         }
+    }
 
-        pub(crate) fn gate_iter_mut(
-            &mut self,
-        ) -> impl Iterator<Item = (usize, &mut Vec2, &str, (&[bool], &[bool]))> + '_ {
-            self.gates.iter_mut().map(|(id, pos)| {
-                let name = self.sim.get_gate_name(*id);
-                let state = self.sim.get_gate_state(*id);
-                (*id, pos, name, state)
-            })
+    draw_rectangle(0., top, screen_width(), height, BLACK);
+    draw_line(0., top, screen_width(), top, 1., GRAY);
+    let mut title = format!("Waveforms, t = {first}..{end}");
+    let cursors = waves
+        .cursors
+        .map(|cursor| cursor.filter(|time| *time <= latest));
+    for (name, cursor) in ["A", "B"].into_iter().zip(cursors) {
+        if let Some(time) = cursor {
+            title += &format!(", {name} = {time}");
         }
+    }
+    if let [Some(a), Some(b)] = cursors {
+        title += &format!(", B - A = {}", b as i64 - a as i64);
+    }
+    draw_text(&title, 5., top + 15., 18., WHITE);
 
-        pub(crate) fn connection_iter(
-            &self,
-        ) -> impl Iterator<Item = (((usize, usize, Vec2), bool), ((usize, usize, Vec2), bool))> + '_
+    let mut to_remove = None;
+    let rows = ((height - WAVE_HEADER) / TRACE_HEIGHT) as usize;
+    for (row, (probe, trace)) in traces.into_iter().take(rows).enumerate() {
+        let row_top = top + WAVE_HEADER + row as f32 * TRACE_HEIGHT;
+        draw_line(0., row_top, screen_width(), row_top, 1., DARKGRAY);
+
+        // the value at the first cursor, or the latest one
+        let time = cursors[0].unwrap_or(latest);
+        let value = trace
+            .value_at(time)
+            .map_or_else(|| "-".to_string(), |value| value.to_string());
+        let label = format!("{} = {value}", probe_label(simulation, probe));
+        draw_trace(trace, waves, (first, end), row_top);
+        draw_rectangle(
+            0.,
+            row_top + 1.,
+            TRACE_LABEL_WIDTH,
+            TRACE_HEIGHT - 1.,
+            BLACK,
+        );
+        draw_text(&label, 5., row_top + TRACE_HEIGHT / 2. + 5., 16., WHITE);
+        let over_label = over && mouse_x < TRACE_LABEL_WIDTH;
+        if over_label
+            && mouse_y > row_top
+            && mouse_y < row_top + TRACE_HEIGHT
+            && is_mouse_button_pressed(MouseButton::Right)
         {
-            self.connections.iter().map(
-                |(
-                    (output_gate_id, output_id, output_offset),
-                    (input_gate_id, input_id, input_offset),
-                )| {
-                    let output_state = self.sim.get_gate_state(*output_gate_id).1[*output_id];
-                    let input_state = self.sim.get_gate_state(*input_gate_id).0[*input_id];
-                    let output_pos = self.gates[output_gate_id] + *output_offset;
-                    let input_pos = self.gates[input_gate_id] + *input_offset;
-
-                    (
-                        ((*output_gate_id, *output_id, output_pos), output_state),
-                        ((*input_gate_id, *input_id, input_pos), input_state),
-                    )
-                },
-            )
+            to_remove = Some(probe);
         }
+    }
+    draw_line(
+        TRACE_LABEL_WIDTH,
+        top,
+        TRACE_LABEL_WIDTH,
+        screen_height(),
+        1.,
+        GRAY,
+    );
 
-        pub(crate) fn gate_pos(&self, gate_id: usize) -> Vec2 {
-            self.gates[&gate_id]
+    for (cursor, color) in cursors.into_iter().zip([YELLOW, SKYBLUE]) {
+        if let Some(time) = cursor.filter(|time| (first..=end).contains(time)) {
+            let x = waves.x(time, end);
+            draw_line(x, top + WAVE_HEADER, x, screen_height(), 1., color);
         }
     }
+
+    to_remove
 }
 
-#[macroquad::main("logic-sim")]
-async fn main() {
-    let mut simulation = BoardSimulation::new();
+async fn window_main() {
+    // a board file can be passed as the first argument
+    let mut file_path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "board.lsim".to_string());
+    let mut file_status = String::new();
+    let mut simulation = match load_board(&file_path) {
+        Ok(simulation) => {
+            file_path = save_path(&file_path);
+            simulation
+        }
+        Err(error) => {
+            if std::env::args().nth(1).is_some() {
+                file_status = format!("cannot load {file_path}: {error}");
+            }
+            BoardSimulation::new()
+        }
+    };
 
-    let mut dragging: Option<(usize, Vec2)> = None;
-    let mut selected_input: Option<(usize, usize, Vec2)> = None;
-    let mut selected_output: Option<(usize, usize, Vec2)> = None;
-    let mut to_remove: Option<usize> = None;
-    let mut connection_to_remove: Option<((usize, usize), (usize, usize))> = None;
+    let mut dragging: Option<Drag> = None;
+    let mut to_drag: Option<(GateId, Vec2)> = None;
+    let mut selection: BTreeSet<GateId> = BTreeSet::new();
+    // corner of the box being dragged to select gates
+    let mut selecting: Option<Vec2> = None;
+    // copied gates in the format of board files
+    let mut clipboard = String::new();
+    // selected pins with their positions relative to their gates
+    let mut selected_input: Option<(InputPin, Vec2)> = None;
+    let mut selected_output: Option<(OutputPin, Vec2)> = None;
+    let mut to_remove: Option<GateId> = None;
+    let mut to_press: Option<GateId> = None;
+    let mut pressed: Option<GateId> = None;
+    let mut connection_to_remove: Option<ConnectionId> = None;
+    let mut view = View::new();
+    // last position of the mouse on the screen while panning
+    let mut panning: Option<Vec2> = None;
+    // bend point of a wire being dragged, by its index
+    let mut dragging_waypoint: Option<(ConnectionId, usize)> = None;
+    let mut waypoint_to_add: Option<(ConnectionId, Vec2)> = None;
+    let mut waypoint_to_remove: Option<(ConnectionId, usize)> = None;
+    let mut to_record: Option<Probe> = None;
+    let mut waves = WaveView::new();
 
     let blackish = Color::from_rgba(0x1e, 0x1e, 0x1e, 0xff);
     let mut last_update = get_time();
     let mut frequency = 10f32;
     let mut elapsed_remainder = 0f64;
+    let mut event_driven = simulation.mode() == SimulationMode::Event;
+    let mut bus_width_index = 2;
+    let mut clock_period = 10f32;
+    let mut clock_duty_cycle = 50f32;
+    let mut clock_phase = 0f32;
+    let mut circuit_name = String::new();
+    let mut grid = false;
+    let mut grid_divisions = 1f32;
 
     let skin = {
         let label_style = root_ui().style_builder().text_color(WHITE).build();
@@ -231,8 +662,14 @@ async fn main() {
     root_ui().push_skin(&skin);
 
     loop {
-        if is_mouse_button_released(MouseButton::Left) && dragging.is_some() {
-            dragging = None;
+        let grid_pitch = GRID_PITCH / grid_divisions.round();
+        let control = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+
+        if is_mouse_button_released(MouseButton::Left) {
+            if let Some((_, _, from)) = dragging.take() {
+                let _ = simulation.finish_move(&from);
+            }
         }
 
         if is_mouse_button_released(MouseButton::Right) {
@@ -240,13 +677,36 @@ async fn main() {
             selected_output = None;
         }
 
-        if let (Some(input), Some(output)) = (selected_input, selected_output) {
-            simulation.add_connection(input, output);
+        if let (Some((input, _)), Some((output, _))) = (selected_input, selected_output) {
+            // pins of different widths are left unconnected
+            let _ = simulation.add_connection(output, input);
             selected_input = None;
             selected_output = None;
         }
 
+        let mouse_screen: Vec2 = mouse_position().into();
+        // the waveform panel takes the mouse from the board below it
+        let traces = simulation.waveforms().traces().count();
+        let over_waves = mouse_screen.y > screen_height() - WaveView::height(traces);
+        let board_pressed = |button| !over_waves && is_mouse_button_pressed(button);
+        let (_, wheel) = mouse_wheel();
+        if wheel != 0. && !over_waves {
+            view.zoom_at(mouse_screen, wheel > 0.);
+        }
+        if board_pressed(MouseButton::Middle) {
+            panning = Some(mouse_screen);
+        }
+        if let Some(last) = panning {
+            view.pan(mouse_screen - last);
+            panning = is_mouse_button_down(MouseButton::Middle).then_some(mouse_screen);
+        }
+        let mouse = view.mouse();
+
         clear_background(blackish);
+        set_camera(&view.camera());
+        if grid {
+            draw_grid(&view, grid_pitch);
+        }
 
         let period = (1.0 / frequency) as f64;
         let elapsed = get_time() - last_update;
@@ -262,135 +722,727 @@ async fn main() {
             }
         }
 
-        for (gate_id, gate_pos, gate_name, gate_state) in simulation.gate_iter_mut() {
-            if let Some((dragging_id, drag_pos_offset)) = dragging {
-                if dragging_id == gate_id {
-                    let pos: Vec2 = mouse_position().into();
-                    *gate_pos = pos - drag_pos_offset;
-                }
+        // the gate dragged by the mouse is snapped to the grid and lined up
+        // with others, the other dragged gates keep their distance to it
+        let dragged = dragging
+            .as_ref()
+            .and_then(|(gate_id, drag_pos_offset, from)| {
+                let pitch = grid.then_some(grid_pitch);
+                let tolerance = ALIGN_TOLERANCE / view.zoom;
+                let moving = from.iter().map(|(id, _)| *id).collect();
+                let (pos, guides) = simulation
+                    .align_gate(
+                        *gate_id,
+                        mouse - *drag_pos_offset,
+                        &moving,
+                        pitch,
+                        tolerance,
+                    )
+                    .ok()?;
+                let start = from.iter().find(|(id, _)| id == gate_id)?.1;
+                let positions: BTreeMap<GateId, Vec2> = from
+                    .iter()
+                    .map(|(id, from)| (*id, *from + pos - start))
+                    .collect();
+                Some((positions, guides))
+            });
+        if let Some((_, guides)) = &dragged {
+            let (min, max) = view.visible();
+            let thickness = 1. / view.zoom;
+            if let Some(x) = guides.x {
+                draw_line(x, min.y, x, max.y, thickness, SKYBLUE);
             }
+            if let Some(y) = guides.y {
+                draw_line(min.x, y, max.x, y, thickness, SKYBLUE);
+            }
+        }
 
-            let (inputs, outputs) = gate_state;
-            if let Some(mouse_hover) = draw_gate(gate_name, gate_pos.x, gate_pos.y, inputs, outputs)
+        let mut hovered_gate: Option<GateId> = None;
+        let mut hovered_input: Option<InputPin> = None;
+        // wires under gates are not hovered
+        let mut over_gate = false;
+        for (gate_id, gate_pos, gate_name, gate_state, interactive) in simulation.gate_iter_mut() {
+            if let Some(pos) = dragged
+                .as_ref()
+                .and_then(|(positions, _)| positions.get(&gate_id))
             {
+                *gate_pos = *pos;
+            }
+
+            let (inputs, outputs) = gate_state;
+            if selection.contains(&gate_id) {
+                let size = gate_size(inputs.len(), outputs.len());
+                let margin = 4.;
+                draw_rectangle_lines(
+                    gate_pos.x - margin,
+                    gate_pos.y - margin,
+                    size + 2. * margin,
+                    size + 2. * margin,
+                    2.,
+                    YELLOW,
+                );
+            }
+            if let Some(mouse_hover) = draw_gate(
+                gate_name,
+                gate_pos.x,
+                gate_pos.y,
+                inputs,
+                outputs,
+                interactive,
+                mouse,
+            ) {
+                over_gate = true;
                 match mouse_hover {
                     GateMouseHover::Input(input_id, input_pos) => {
-                        if is_mouse_button_pressed(MouseButton::Left) {
-                            selected_input = Some((gate_id, input_id, input_pos - *gate_pos));
+                        hovered_input = Some(gate_id.input(input_id));
+
+                        if board_pressed(MouseButton::Left) {
+                            if control {
+                                to_record = Some(Probe::Input(gate_id.input(input_id)));
+                            } else {
+                                selected_input =
+                                    Some((gate_id.input(input_id), input_pos - *gate_pos));
+                            }
                         }
                     }
                     GateMouseHover::Output(output_id, output_pos) => {
-                        if is_mouse_button_pressed(MouseButton::Left) {
-                            selected_output = Some((gate_id, output_id, output_pos - *gate_pos));
+                        if board_pressed(MouseButton::Left) {
+                            if control {
+                                to_record = Some(Probe::Output(gate_id.output(output_id)));
+                            } else {
+                                selected_output =
+                                    Some((gate_id.output(output_id), output_pos - *gate_pos));
+                            }
                         }
                     }
                     GateMouseHover::Gate(drag_pos) => {
-                        if dragging.is_none() {
-                            if is_mouse_button_pressed(MouseButton::Left) {
-                                let offset = drag_pos - *gate_pos;
-                                dragging = Some((gate_id, offset));
+                        hovered_gate = Some(gate_id);
+
+                        if dragging.is_none() && board_pressed(MouseButton::Left) {
+                            // shift-click adds or removes the gate, clicking
+                            // a gate outside the selection selects it alone
+                            if shift {
+                                if !selection.remove(&gate_id) {
+                                    selection.insert(gate_id);
+                                }
+                            } else if !selection.contains(&gate_id) {
+                                selection = BTreeSet::from([gate_id]);
+                            }
+                            if selection.contains(&gate_id) {
+                                to_drag = Some((gate_id, drag_pos - *gate_pos));
                             }
                         }
 
-                        if is_mouse_button_pressed(MouseButton::Right) {
+                        if board_pressed(MouseButton::Right) {
                             to_remove = Some(gate_id);
                         }
                     }
+                    GateMouseHover::Control => {
+                        if board_pressed(MouseButton::Left) {
+                            to_press = Some(gate_id);
+                        }
+                    }
                 }
             }
         }
 
-        for (output, input) in simulation.connection_iter() {
-            let ((output_gate_id, output_id, output_pos), output_active) = output;
-            let ((input_gate_id, input_id, input_pos), _) = input;
-
-            let opos = Vec2::new(output_pos.x, output_pos.y);
-            let ipos = Vec2::new(input_pos.x, input_pos.y);
-            let mpos: Vec2 = mouse_position().into();
+        if let Some((gate_id, offset)) = to_drag.take() {
+            let from = selection
+                .iter()
+                .filter_map(|id| Some((*id, simulation.gate_pos(*id).ok()?)))
+                .collect();
+            dragging = Some((gate_id, offset, from));
+        }
 
-            let dm = mpos - opos;
-            let d1 = ipos - opos;
-            let cross = dm.perp_dot(d1);
+        simulation.route_wires();
+        let mut over_wire = false;
+        for (connection, route, waypoints, output_state) in simulation.connection_iter() {
+            let hovered_waypoint = waypoints
+                .iter()
+                .position(|point| point.distance(mouse) < WAYPOINT_SIZE)
+                .filter(|_| !over_gate);
+            let mouse_over_line =
+                !over_gate && routing::distance(route, mouse) < WIRE_TOLERANCE / view.zoom;
+            over_wire |= mouse_over_line || hovered_waypoint.is_some();
+            let idle = dragging.is_none()
+                && dragging_waypoint.is_none()
+                && selected_input.is_none()
+                && selected_output.is_none();
 
-            let is_between = if d1.x.abs() > d1.y.abs() {
-                if d1.x > 0.0 {
-                    opos.x <= mpos.x && mpos.x <= ipos.x
-                } else {
-                    ipos.x <= mpos.x && mpos.x <= opos.x
+            if let Some(index) = hovered_waypoint {
+                if board_pressed(MouseButton::Right) {
+                    waypoint_to_remove = Some((connection, index));
+                } else if idle && board_pressed(MouseButton::Left) {
+                    dragging_waypoint = Some((connection, index));
                 }
-            } else {
-                if d1.y > 0.0 {
-                    opos.y <= mpos.y && mpos.y <= ipos.y
-                } else {
-                    ipos.y <= mpos.y && mpos.y <= opos.y
+            } else if mouse_over_line {
+                if board_pressed(MouseButton::Right) {
+                    connection_to_remove = Some(connection);
+                } else if control && board_pressed(MouseButton::Left) {
+                    to_record = simulation
+                        .connection_output(connection)
+                        .ok()
+                        .map(Probe::Output);
+                } else if idle && board_pressed(MouseButton::Left) {
+                    waypoint_to_add = Some((connection, mouse));
                 }
+            }
+
+            let thickness = if output_state.width() > 1 { 4. } else { 2. };
+            let thickness = if mouse_over_line {
+                thickness * 2.
+            } else {
+                thickness
             };
+            let color = bus_wire_color(output_state);
+            for segment in route.windows(2) {
+                let (a, b) = (segment[0], segment[1]);
+                draw_line(a.x, a.y, b.x, b.y, thickness, color);
+                // fills the corners of thick wires
+                draw_circle(b.x, b.y, thickness / 2., color);
+            }
 
-            let mouse_over_line = is_between && cross.abs() < 1000.;
+            for (index, point) in waypoints.iter().enumerate() {
+                let corner = *point - Vec2::splat(WAYPOINT_SIZE / 2.);
+                draw_rectangle(corner.x, corner.y, WAYPOINT_SIZE, WAYPOINT_SIZE, color);
+                if hovered_waypoint == Some(index) {
+                    draw_rectangle_lines(
+                        corner.x,
+                        corner.y,
+                        WAYPOINT_SIZE,
+                        WAYPOINT_SIZE,
+                        2.,
+                        WHITE,
+                    );
+                }
+            }
+
+            if output_state.width() > 1 && route.len() >= 2 {
+                let half = route.len() / 2;
+                let middle = (route[half - 1] + route[half]) / 2.;
+                draw_text(
+                    &output_state.to_string(),
+                    middle.x + 5.,
+                    middle.y - 5.,
+                    20.,
+                    color,
+                );
+            }
+        }
+
+        // dragging on empty space selects the gates in a box
+        let idle = dragging.is_none()
+            && dragging_waypoint.is_none()
+            && selected_input.is_none()
+            && selected_output.is_none();
+        if idle && !over_gate && !over_wire && board_pressed(MouseButton::Left) {
+            selecting = Some(mouse);
+        }
+        if let Some(corner) = selecting {
+            let area = Rect::new(corner.x, corner.y, 0., 0.)
+                .combine_with(Rect::new(mouse.x, mouse.y, 0., 0.));
+            draw_rectangle_lines(area.x, area.y, area.w, area.h, 1. / view.zoom, YELLOW);
+            if is_mouse_button_released(MouseButton::Left) {
+                let boxed = simulation.gates_in(area);
+                if shift {
+                    selection.extend(boxed);
+                } else {
+                    selection = boxed;
+                }
+                selecting = None;
+            }
+        }
 
-            if mouse_over_line {
-                if is_mouse_button_pressed(MouseButton::Right) {
-                    connection_to_remove =
-                        Some(((input_gate_id, input_id), (output_gate_id, output_id)));
+        let snap_to_grid = |pos: Vec2| if grid { snap(pos, grid_pitch) } else { pos };
+        if let Some((connection, pos)) = waypoint_to_add.take() {
+            if let Ok(index) = simulation.add_waypoint(connection, snap_to_grid(pos)) {
+                dragging_waypoint = Some((connection, index));
+            }
+        }
+        if let Some((connection, index)) = waypoint_to_remove.take() {
+            if let Ok(waypoints) = simulation.waypoints(connection) {
+                let mut waypoints = waypoints.to_vec();
+                waypoints.remove(index);
+                let _ = simulation.set_waypoints(connection, waypoints);
+            }
+        }
+        if is_mouse_button_released(MouseButton::Left) {
+            dragging_waypoint = None;
+        }
+        if let Some((connection, index)) = dragging_waypoint {
+            let pos = snap_to_grid(mouse);
+            if let Ok(waypoints) = simulation.waypoints(connection) {
+                if waypoints.get(index).is_some_and(|point| *point != pos) {
+                    let mut waypoints = waypoints.to_vec();
+                    waypoints[index] = pos;
+                    let _ = simulation.set_waypoints(connection, waypoints);
                 }
             }
+        }
+
+        // hints and the panel are drawn on the screen
+        set_default_camera();
+
+        // ctrl-clicked pins and wires are added to the waveforms, or removed
+        // when shown already
+        if let Some(probe) = to_record.take() {
+            let _ = simulation.toggle_recording(probe);
+        }
+        if let Some(probe) = draw_waveforms(&simulation, &mut waves) {
+            simulation.stop_recording(probe);
+        }
+
+        if let (true, Some(gate_id)) = (event_driven, hovered_gate) {
+            let mut delay = simulation.gate_delay(gate_id).unwrap_or(1);
+            if is_key_pressed(KeyCode::Equal) || is_key_pressed(KeyCode::KpAdd) {
+                delay += 1;
+            }
+            if is_key_pressed(KeyCode::Minus) || is_key_pressed(KeyCode::KpSubtract) {
+                delay = delay.saturating_sub(1).max(1);
+            }
+            let _ = simulation.set_gate_delay(gate_id, delay);
+
+            let (mouse_x, mouse_y) = mouse_position();
+            draw_text(
+                &format!("delay: {delay} (+/-)"),
+                mouse_x + 15.,
+                mouse_y,
+                20.,
+                WHITE,
+            );
+        }
+
+        if let Some(input) = hovered_input {
+            let mut resolution = simulation.resolution(input).unwrap_or_default();
+            if is_key_pressed(KeyCode::R) {
+                let index = Resolution::ALL
+                    .iter()
+                    .position(|r| *r == resolution)
+                    .unwrap();
+                resolution = Resolution::ALL[(index + 1) % Resolution::ALL.len()];
+                let _ = simulation.set_resolution(input, resolution);
+            }
 
-            draw_line(
-                output_pos.x,
-                output_pos.y,
-                input_pos.x,
-                input_pos.y,
-                if mouse_over_line { 4. } else { 2. },
-                if output_active { RED } else { WHITE },
+            let (mouse_x, mouse_y) = mouse_position();
+            draw_text(
+                &format!("{} (R)", resolution.name()),
+                mouse_x + 15.,
+                mouse_y,
+                20.,
+                WHITE,
             );
         }
 
+        if let Some(gate_id) = to_press.take() {
+            if simulation.press_gate(gate_id).is_ok() {
+                pressed = Some(gate_id);
+            }
+        }
+
+        if is_mouse_button_released(MouseButton::Left) {
+            if let Some(gate_id) = pressed.take() {
+                let _ = simulation.release_gate(gate_id);
+            }
+        }
+
         if let Some(gate_id) = to_remove.take() {
-            simulation.remove_gate(gate_id);
+            if pressed == Some(gate_id) {
+                pressed = None;
+            }
+            selection.remove(&gate_id);
+            let _ = simulation.remove_gate(gate_id);
         }
 
-        if let Some((input, output)) = connection_to_remove.take() {
-            simulation.remove_connection(input, output);
+        if is_key_pressed(KeyCode::Delete) && dragging.is_none() {
+            if pressed.is_some_and(|gate_id| selection.contains(&gate_id)) {
+                pressed = None;
+            }
+            let _ = simulation.remove_gates(&selection);
+            selection.clear();
         }
 
-        match (selected_input, selected_output) {
-            (Some((gate_id, _, offset)), None) => {
-                let (mouse_x, mouse_y) = mouse_position();
-                let gate_pos = simulation.gate_pos(gate_id);
-                let pos = gate_pos + offset;
-                draw_line(pos.x, pos.y, mouse_x, mouse_y, 2., WHITE);
+        if control && is_key_pressed(KeyCode::C) && !selection.is_empty() {
+            clipboard = simulation.copy(&selection).to_string();
+        }
+
+        // pasted gates are placed with their top left corner at the mouse,
+        // duplicated gates next to the originals
+        let to_paste = if control && is_key_pressed(KeyCode::V) && dragging.is_none() {
+            BoardFile::parse(&clipboard).ok().map(|file| {
+                let corner = file
+                    .gates
+                    .iter()
+                    .map(|gate| Vec2::new(gate.pos.0, gate.pos.1))
+                    .reduce(Vec2::min)
+                    .unwrap_or_default();
+                (file, snap_to_grid(mouse - corner))
+            })
+        } else if control && is_key_pressed(KeyCode::D) && dragging.is_none() {
+            let step = if grid { grid_pitch } else { 20. };
+            Some((simulation.copy(&selection), Vec2::splat(step)))
+        } else {
+            None
+        };
+        if let Some((file, offset)) = to_paste {
+            match simulation.paste(&file, offset) {
+                Ok(pasted) => selection = pasted.into_iter().collect(),
+                Err(error) => file_status = format!("cannot paste: {error}"),
             }
-            (None, Some((gate_id, _, offset))) => {
+        }
+
+        if let Some(connection) = connection_to_remove.take() {
+            let _ = simulation.remove_connection(connection);
+        }
+
+        if control && is_key_pressed(KeyCode::Z) {
+            let (action, edited) = if shift {
+                ("redo", simulation.redo())
+            } else {
+                ("undo", simulation.undo())
+            };
+            match edited {
+                Ok(_) => {
+                    // undone and redone gates get new ids
+                    dragging = None;
+                    dragging_waypoint = None;
+                    selection.clear();
+                    selected_input = None;
+                    selected_output = None;
+                    if let Some(gate_id) = pressed.take() {
+                        let _ = simulation.release_gate(gate_id);
+                    }
+                }
+                Err(error) => file_status = format!("cannot {action}: {error}"),
+            }
+        }
+
+        let selected_pin = match (selected_input, selected_output) {
+            (Some((input, offset)), None) => Some((input.gate, offset)),
+            (None, Some((output, offset))) => Some((output.gate, offset)),
+            _ => None,
+        };
+        if let Some((gate_id, offset)) = selected_pin {
+            if let Ok(gate_pos) = simulation.gate_pos(gate_id) {
                 let (mouse_x, mouse_y) = mouse_position();
-                let gate_pos = simulation.gate_pos(gate_id);
-                let pos = gate_pos + offset;
+                let pos = view.camera().world_to_screen(gate_pos + offset);
                 draw_line(pos.x, pos.y, mouse_x, mouse_y, 2., WHITE);
             }
-            _ => {}
         }
 
+        // gates of a loaded or rebuilt board have new ids
+        let mut board_replaced = false;
+
         {
             root_ui().slider(hash!(), "Frequency (Hz)", 1f32..100f32, &mut frequency);
+            root_ui().label(None, &format!("Time: {}", simulation.time()));
+            if root_ui().button(None, "Reset") {
+                simulation.reset();
+                waves = WaveView::new();
+            }
+            root_ui().same_line(0.);
+            if root_ui().button(None, "Reset view") {
+                view = View::new();
+            }
+            root_ui().checkbox(hash!(), "Event-driven", &mut event_driven);
+            root_ui().checkbox(hash!(), "Grid", &mut grid);
+            root_ui().slider(hash!(), "Grid divisions", 1f32..4f32, &mut grid_divisions);
+            let mut history_depth = simulation.history_depth() as f32;
+            root_ui().slider(hash!(), "Undo depth", 0f32..500f32, &mut history_depth);
+            simulation.set_history_depth(history_depth as usize);
+            let mut trace_length = simulation.waveforms().capacity() as f32;
+            root_ui().slider(hash!(), "Trace length", 16f32..4096f32, &mut trace_length);
+            simulation.set_waveform_capacity(trace_length as usize);
+
+            root_ui().input_text(hash!(), "File", &mut file_path);
+            if root_ui().button(None, "Save") {
+                file_status = match save_board(&simulation, &file_path) {
+                    Ok(()) => format!("saved {file_path}"),
+                    Err(error) => format!("cannot save {file_path}: {error}"),
+                };
+            }
+            root_ui().same_line(0.);
+            if root_ui().button(None, "Load") {
+                file_status = match load_board(&file_path) {
+                    Ok(loaded) => {
+                        simulation = loaded;
+                        event_driven = simulation.mode() == SimulationMode::Event;
+                        board_replaced = true;
+                        let loaded = format!("loaded {file_path}");
+                        file_path = save_path(&file_path);
+                        loaded
+                    }
+                    Err(error) => format!("cannot load {file_path}: {error}"),
+                };
+            }
+            root_ui().same_line(0.);
+            if root_ui().button(None, "Export VCD") {
+                // the dump is written next to the board file
+                let path = std::path::Path::new(&file_path).with_extension("vcd");
+                let written = simulation
+                    .to_vcd()
+                    .map_err(|error| error.to_string())
+                    .and_then(|dump| {
+                        std::fs::write(&path, dump).map_err(|error| error.to_string())
+                    });
+                file_status = match written {
+                    Ok(()) => format!("exported {}", path.display()),
+                    Err(error) => format!("cannot export {}: {error}", path.display()),
+                };
+            }
+            root_ui().same_line(0.);
+            if root_ui().button(None, "Export Verilog") {
+                // the module is named after the board file
+                let path = std::path::Path::new(&file_path).with_extension("v");
+                let module = path
+                    .file_stem()
+                    .map_or("board".into(), |stem| stem.to_string_lossy());
+                let written = verilog::export(&simulation.to_file(), &module)
+                    .map_err(|error| error.to_string())
+                    .and_then(|verilog| {
+                        std::fs::write(&path, verilog).map_err(|error| error.to_string())
+                    });
+                file_status = match written {
+                    Ok(()) => format!("exported {}", path.display()),
+                    Err(error) => format!("cannot export {}: {error}", path.display()),
+                };
+            }
+            if !file_status.is_empty() {
+                root_ui().label(None, &file_status);
+            }
+
+            let resolution_names = Resolution::ALL.map(Resolution::name);
+            let mut resolution_index = Resolution::ALL
+                .iter()
+                .position(|r| *r == simulation.default_resolution())
+                .unwrap();
+            root_ui().combo_box(hash!(), "Nets", &resolution_names, &mut resolution_index);
+            simulation.set_default_resolution(Resolution::ALL[resolution_index]);
+
+            for diagnostic in simulation.diagnostics() {
+                let problem = match diagnostic.conflict {
+                    Conflict::MultipleDrivers => "multiple drivers",
+                    Conflict::Contention => "contention",
+                };
+                root_ui().label(
+                    None,
+                    &format!(
+                        "{problem} at {} #{} input {}",
+                        simulation.gate_name(diagnostic.input.gate).unwrap_or("?"),
+                        diagnostic.input.gate,
+                        diagnostic.input.index
+                    ),
+                );
+            }
+
+            simulation.set_mode(if event_driven {
+                SimulationMode::Event
+            } else {
+                SimulationMode::Tick
+            });
             root_ui().label(None, "Add Gate:");
+            // new gates are placed in the middle of the view
+            let new_gate_pos = if grid {
+                snap(view.center, grid_pitch)
+            } else {
+                view.center
+            };
 
             fn add_gate_btn<const INPUTS: usize, const OUTPUTS: usize>(
                 gate: impl Gate<INPUTS, OUTPUTS> + 'static,
                 simulation: &mut BoardSimulation,
+                pos: Vec2,
             ) {
-                let screen_middle = Vec2::new(screen_width() / 2., screen_height() / 2.);
                 if root_ui().button(None, format!("{:<5}", gate.name())) {
-                    simulation.add_gate(gate, screen_middle);
+                    simulation.add_gate(gate, pos);
                 }
             }
 
-            add_gate_btn(And, &mut simulation);
-            add_gate_btn(Nand, &mut simulation);
-            add_gate_btn(Or, &mut simulation);
-            add_gate_btn(Nor, &mut simulation);
-            add_gate_btn(Xor, &mut simulation);
-            add_gate_btn(Xnor, &mut simulation);
-            add_gate_btn(Yes, &mut simulation);
-            add_gate_btn(Not, &mut simulation);
+            add_gate_btn(And, &mut simulation, new_gate_pos);
+            add_gate_btn(Nand, &mut simulation, new_gate_pos);
+            add_gate_btn(Or, &mut simulation, new_gate_pos);
+            add_gate_btn(Nor, &mut simulation, new_gate_pos);
+            add_gate_btn(Xor, &mut simulation, new_gate_pos);
+            add_gate_btn(Xnor, &mut simulation, new_gate_pos);
+            add_gate_btn(Yes, &mut simulation, new_gate_pos);
+            add_gate_btn(Not, &mut simulation, new_gate_pos);
+            add_gate_btn(High, &mut simulation, new_gate_pos);
+            add_gate_btn(Low, &mut simulation, new_gate_pos);
+            add_gate_btn(Switch::default(), &mut simulation, new_gate_pos);
+            add_gate_btn(Button::default(), &mut simulation, new_gate_pos);
+            add_gate_btn(Led, &mut simulation, new_gate_pos);
+            add_gate_btn(SevenSegment, &mut simulation, new_gate_pos);
+            add_gate_btn(HexDisplay, &mut simulation, new_gate_pos);
+            add_gate_btn(SrLatch::default(), &mut simulation, new_gate_pos);
+            add_gate_btn(DFlipFlop::default(), &mut simulation, new_gate_pos);
+            add_gate_btn(JkFlipFlop::default(), &mut simulation, new_gate_pos);
+            add_gate_btn(TFlipFlop::default(), &mut simulation, new_gate_pos);
+            add_gate_btn(InputPort::default(), &mut simulation, new_gate_pos);
+            add_gate_btn(OutputPort::default(), &mut simulation, new_gate_pos);
+
+            root_ui().label(None, "Subcircuits:");
+            let mut flatten = simulation.subcircuit_mode() == SubcircuitMode::Flattened;
+            root_ui().checkbox(hash!(), "Flatten", &mut flatten);
+            let subcircuit_mode = if flatten {
+                SubcircuitMode::Flattened
+            } else {
+                SubcircuitMode::Nested
+            };
+            if subcircuit_mode != simulation.subcircuit_mode() {
+                simulation.set_subcircuit_mode(subcircuit_mode);
+                board_replaced = true;
+            }
+
+            let names: Vec<String> = simulation.circuit_names().map(String::from).collect();
+            for name in names {
+                if root_ui().button(None, format!("{name:<5}")) {
+                    if let Err(error) = simulation.add_circuit(&name, new_gate_pos) {
+                        file_status = format!("cannot add {name}: {error}");
+                    }
+                }
+            }
+
+            root_ui().input_text(hash!(), "Name", &mut circuit_name);
+            if root_ui().button(None, "Package board") {
+                file_status = match simulation.package(&circuit_name) {
+                    Ok(()) => format!("packaged board as {circuit_name}"),
+                    Err(error) => format!("cannot package board: {error}"),
+                };
+                board_replaced = true;
+            }
+            root_ui().same_line(0.);
+            if root_ui().button(None, "Package selection") {
+                file_status = match simulation.package_selection(&circuit_name, &selection) {
+                    Ok(instance) => {
+                        selection = BTreeSet::from([instance]);
+                        format!("packaged selection as {circuit_name}")
+                    }
+                    Err(error) => format!("cannot package selection: {error}"),
+                };
+            }
+            root_ui().same_line(0.);
+            if root_ui().button(None, "Import file") {
+                let imported = read_board_file(&file_path).and_then(|file| {
+                    simulation
+                        .import(&circuit_name, file)
+                        .map_err(|error| error.to_string())
+                });
+                file_status = match imported {
+                    Ok(()) => format!("imported {file_path} as {circuit_name}"),
+                    Err(error) => format!("cannot import {file_path}: {error}"),
+                };
+                board_replaced = true;
+            }
+
+            root_ui().label(None, "Add Clock:");
+            root_ui().slider(hash!(), "Period", 2f32..100f32, &mut clock_period);
+            root_ui().slider(hash!(), "Duty (%)", 0f32..100f32, &mut clock_duty_cycle);
+            root_ui().slider(hash!(), "Phase", 0f32..100f32, &mut clock_phase);
+            add_gate_btn(
+                Clock::new(
+                    clock_period as u64,
+                    clock_duty_cycle as u8,
+                    clock_phase as u64,
+                ),
+                &mut simulation,
+                new_gate_pos,
+            );
+
+            root_ui().label(None, "Add Bus Gate:");
+            root_ui().combo_box(
+                hash!(),
+                "Width",
+                &BUS_WIDTHS
+                    .map(|width| format!("{width}"))
+                    .each_ref()
+                    .map(String::as_str),
+                &mut bus_width_index,
+            );
+            let width = BUS_WIDTHS[bus_width_index];
+
+            fn add_bus_gate_btn<const INPUTS: usize, const OUTPUTS: usize>(
+                label: &str,
+                gate: impl Gate<INPUTS, OUTPUTS> + 'static,
+                simulation: &mut BoardSimulation,
+                pos: Vec2,
+            ) {
+                if root_ui().button(None, format!("{label:<7}")) {
+                    simulation.add_gate(gate, pos);
+                }
+            }
+
+            add_bus_gate_btn(
+                &format!("AND{width}"),
+                Wide::new(And, width),
+                &mut simulation,
+                new_gate_pos,
+            );
+            add_bus_gate_btn(
+                &format!("OR{width}"),
+                Wide::new(Or, width),
+                &mut simulation,
+                new_gate_pos,
+            );
+            add_bus_gate_btn(
+                &format!("XOR{width}"),
+                Wide::new(Xor, width),
+                &mut simulation,
+                new_gate_pos,
+            );
+            add_bus_gate_btn(
+                &format!("NOT{width}"),
+                Wide::new(Not, width),
+                &mut simulation,
+                new_gate_pos,
+            );
+            add_bus_gate_btn(
+                &format!("IN{width}"),
+                InputPort::new(width),
+                &mut simulation,
+                new_gate_pos,
+            );
+            add_bus_gate_btn(
+                &format!("OUT{width}"),
+                OutputPort::new(width),
+                &mut simulation,
+                new_gate_pos,
+            );
+
+            let split = format!("SPLIT{width}");
+            let merge = format!("MERGE{width}");
+            match width {
+                2 => {
+                    add_bus_gate_btn(&split, Splitter::<2>::bits(), &mut simulation, new_gate_pos);
+                    add_bus_gate_btn(&merge, Merger::<2>::bits(), &mut simulation, new_gate_pos);
+                }
+                4 => {
+                    add_bus_gate_btn(&split, Splitter::<4>::bits(), &mut simulation, new_gate_pos);
+                    add_bus_gate_btn(&merge, Merger::<4>::bits(), &mut simulation, new_gate_pos);
+                }
+                8 => {
+                    add_bus_gate_btn(&split, Splitter::<8>::bits(), &mut simulation, new_gate_pos);
+                    add_bus_gate_btn(&merge, Merger::<8>::bits(), &mut simulation, new_gate_pos);
+                }
+                _ => {
+                    add_bus_gate_btn(
+                        &split,
+                        Splitter::<16>::bits(),
+                        &mut simulation,
+                        new_gate_pos,
+                    );
+                    add_bus_gate_btn(&merge, Merger::<16>::bits(), &mut simulation, new_gate_pos);
+                }
+            }
+        }
+
+        if board_replaced {
+            dragging = None;
+            dragging_waypoint = None;
+            selection.clear();
+            selecting = None;
+            selected_input = None;
+            selected_output = None;
+            pressed = None;
+            waves = WaveView::new();
         }
 
         next_frame().await
@@ -0,0 +1,167 @@
+use crate::{bus::Bus, signal::Signal};
+
+/// Policy that determines the value of a net, that is of an input pin together
+/// with all outputs driving it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Resolution {
+    /// Only a single driver is allowed, multiple drivers make the net unknown.
+    Exclusive,
+    /// The net is high if any of the drivers is high.
+    WiredOr,
+    /// The net is low if any of the drivers is low.
+    WiredAnd,
+    /// Drivers in high impedance are ignored, the remaining drivers have to
+    /// agree on the value, otherwise the net is unknown.
+    #[default]
+    TriState,
+}
+
+/// Problem detected while resolving the value of a net.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conflict {
+    /// More than one output drives a net that allows only a single driver.
+    MultipleDrivers,
+    /// Drivers of the net drive opposite values at the same time.
+    Contention,
+}
+
+impl Resolution {
+    pub const ALL: [Resolution; 4] = [
+        Resolution::Exclusive,
+        Resolution::WiredOr,
+        Resolution::WiredAnd,
+        Resolution::TriState,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Resolution::Exclusive => "exclusive",
+            Resolution::WiredOr => "wired-or",
+            Resolution::WiredAnd => "wired-and",
+            Resolution::TriState => "tri-state",
+        }
+    }
+
+    /// Policy with given name, as returned by [`Resolution::name`].
+    pub fn from_name(name: &str) -> Option<Resolution> {
+        Resolution::ALL
+            .into_iter()
+            .find(|resolution| resolution.name() == name)
+    }
+
+    /// Computes the value of a net of given width from the values of all of
+    /// its drivers, bit by bit, a net without drivers is in high impedance.
+    pub fn resolve(self, width: usize, drivers: &[Bus]) -> (Bus, Option<Conflict>) {
+        let driven = drivers
+            .iter()
+            .any(|driver| driver.bits().any(|bit| bit != Signal::HighZ));
+        if self == Resolution::Exclusive && driven && drivers.len() > 1 {
+            return (
+                Bus::new(width, Signal::Unknown),
+                Some(Conflict::MultipleDrivers),
+            );
+        }
+
+        let mut conflict = None;
+        let bus = Bus::from_bits((0..width).map(|index| {
+            let (signal, bit_conflict) =
+                self.resolve_bit(drivers.iter().map(|driver| driver.bit(index)));
+            conflict = conflict.or(bit_conflict);
+            signal
+        }));
+
+        (bus, conflict)
+    }
+
+    fn resolve_bit(self, drivers: impl Iterator<Item = Signal>) -> (Signal, Option<Conflict>) {
+        let mut driven = drivers.filter(|signal| *signal != Signal::HighZ);
+
+        let Some(first) = driven.next() else {
+            return (Signal::HighZ, None);
+        };
+
+        match self {
+            Resolution::Exclusive => (first, None),
+            Resolution::WiredOr => (driven.fold(first, |net, signal| net | signal), None),
+            Resolution::WiredAnd => (driven.fold(first, |net, signal| net & signal), None),
+            Resolution::TriState => {
+                let mut net = first;
+                let mut conflict = None;
+                for signal in driven {
+                    if signal == net {
+                        continue;
+                    }
+
+                    // an unknown driver makes the net unknown, but only two
+                    // known opposite values are a real contention
+                    if net != Signal::Unknown && signal != Signal::Unknown {
+                        conflict = Some(Conflict::Contention);
+                    }
+                    net = Signal::Unknown;
+                }
+                (net, conflict)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const Y: Signal = Signal::High;
+    const N: Signal = Signal::Low;
+    const X: Signal = Signal::Unknown;
+    const Z: Signal = Signal::HighZ;
+
+    fn resolve(resolution: Resolution, drivers: &[Signal]) -> (Signal, Option<Conflict>) {
+        let drivers: Vec<Bus> = drivers.iter().copied().map(Bus::from).collect();
+        let (bus, conflict) = resolution.resolve(1, &drivers);
+        (bus.bit(0), conflict)
+    }
+
+    #[test]
+    fn undriven_net_is_high_impedance() {
+        for resolution in Resolution::ALL {
+            assert_eq!(resolve(resolution, &[]), (Z, None));
+            assert_eq!(resolve(resolution, &[Z, Z]), (Z, None));
+        }
+    }
+
+    #[test]
+    fn exclusive() {
+        assert_eq!(resolve(Resolution::Exclusive, &[Y]), (Y, None));
+        assert_eq!(
+            resolve(Resolution::Exclusive, &[N, N]),
+            (X, Some(Conflict::MultipleDrivers))
+        );
+    }
+
+    #[test]
+    fn wired() {
+        assert_eq!(resolve(Resolution::WiredOr, &[N, Y, Z]), (Y, None));
+        assert_eq!(resolve(Resolution::WiredOr, &[N, N]), (N, None));
+        assert_eq!(resolve(Resolution::WiredAnd, &[N, Y, Z]), (N, None));
+        assert_eq!(resolve(Resolution::WiredAnd, &[Y, Y]), (Y, None));
+    }
+
+    #[test]
+    fn bus_is_resolved_bit_by_bit() {
+        let drivers = [Bus::from_bits([Y, Z, Z]), Bus::from_bits([Z, N, Y])];
+        assert_eq!(
+            Resolution::TriState.resolve(3, &drivers),
+            (Bus::from_bits([Y, N, Y]), None)
+        );
+    }
+
+    #[test]
+    fn tri_state() {
+        assert_eq!(resolve(Resolution::TriState, &[Z, Y, Z]), (Y, None));
+        assert_eq!(resolve(Resolution::TriState, &[N, N]), (N, None));
+        assert_eq!(resolve(Resolution::TriState, &[N, X]), (X, None));
+        assert_eq!(
+            resolve(Resolution::TriState, &[N, Y]),
+            (X, Some(Conflict::Contention))
+        );
+    }
+}
@@ -0,0 +1,1301 @@
+//! Import of gate-level netlists written by Yosys, as BLIF (`write_blif`) or
+//! JSON (`write_json`) files, as boards.
+//!
+//! Cells of the internal gate library of Yosys map to logic gates, D
+//! flip-flops and the SR latch, other combinational cells map to lookup
+//! tables. Enables and synchronous resets of flip-flops become a lookup table
+//! in front of the D input, latches are not supported. `$lut` cells and the
+//! covers of BLIF `.names` lines map to lookup tables, or to logic gates when
+//! their table is the one of a logic gate. Designs have to be flattened and mapped to gates first,
+//! e.g. by `synth -flatten`.
+//!
+//! - Every bit of a port of the top module becomes a 1-bit `IN` or `OUT` port
+//!   of the board, in the order of the ports. The inputs are toggled by
+//!   clicking them, as nothing drives the ports of the board.
+//! - Constant bits are driven by `HIGH` and `LOW` gates, undefined bits are
+//!   left unconnected.
+//! - BLIF latches clocked on an edge become D flip-flops, level-sensitive
+//!   latches are not supported.
+//!
+//! Gates are laid out in columns by their distance from the inputs, with the
+//! inputs in the first column and the outputs in the last one. Gates of a
+//! column are ordered by the positions of the gates driving them, to keep
+//! wires short.
+
+use std::{collections::BTreeMap, path::Path};
+
+use crate::board_file::{BoardFile, FileError, GateEntry, WireEntry};
+
+/// Horizontal distance between columns of gates.
+const COLUMN_WIDTH: f32 = 150.;
+/// Vertical space between gates of a column.
+const ROW_SPACING: f32 = 30.;
+/// Height taken by each pin of a gate, as gates are drawn on the board.
+const PIN_HEIGHT: f32 = 25.;
+
+/// Whether a file is a netlist to import rather than a board file, judging by
+/// its extension.
+pub fn is_netlist(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("blif") || extension.eq_ignore_ascii_case("json")
+    })
+}
+
+/// Reads a BLIF or Yosys JSON netlist as a board, JSON files are told apart
+/// by their contents.
+pub fn import(text: &str) -> Result<BoardFile, FileError> {
+    let netlist = if text.trim_start().starts_with('{') {
+        parse_json(text)?
+    } else {
+        parse_blif(text)?
+    };
+    Ok(netlist.to_board())
+}
+
+/// Net of a netlist, nets are numbered in the order they are named.
+type Net = usize;
+
+/// Gate of a netlist with the nets of its pins, unconnected pins have no net.
+struct Cell {
+    kind: &'static str,
+    params: Vec<u64>,
+    inputs: Vec<Option<Net>>,
+    outputs: Vec<Option<Net>>,
+}
+
+#[derive(Default)]
+struct Netlist {
+    names: BTreeMap<String, Net>,
+    count: usize,
+    /// Nets of the bits of the input ports
+    inputs: Vec<Net>,
+    /// Nets of the bits of the output ports
+    outputs: Vec<Option<Net>>,
+    cells: Vec<Cell>,
+    /// Nets driven by the low and the high constant, once used
+    constants: [Option<Net>; 2],
+}
+
+impl Netlist {
+    /// Net with given name, nets are created when first named.
+    fn net(&mut self, name: &str) -> Net {
+        if let Some(net) = self.names.get(name) {
+            return *net;
+        }
+        let net = self.fresh();
+        self.names.insert(name.to_string(), net);
+        net
+    }
+
+    /// New net without a name.
+    fn fresh(&mut self) -> Net {
+        self.count += 1;
+        self.count - 1
+    }
+
+    /// Net driven by a constant.
+    fn constant(&mut self, value: bool) -> Net {
+        if let Some(net) = self.constants[value as usize] {
+            return net;
+        }
+        let net = self.fresh();
+        let kind = if value { "HIGH" } else { "LOW" };
+        self.add(kind, Vec::new(), Vec::new(), vec![Some(net)]);
+        self.constants[value as usize] = Some(net);
+        net
+    }
+
+    fn add(
+        &mut self,
+        kind: &'static str,
+        params: Vec<u64>,
+        inputs: Vec<Option<Net>>,
+        outputs: Vec<Option<Net>>,
+    ) {
+        self.cells.push(Cell {
+            kind,
+            params,
+            inputs,
+            outputs,
+        });
+    }
+
+    /// Adds a gate computing given table of the inputs.
+    fn add_table(
+        &mut self,
+        table: u64,
+        inputs: Vec<Option<Net>>,
+        output: Option<Net>,
+    ) -> Result<(), FileError> {
+        let (kind, params) = table_gate(inputs.len(), table)?;
+        self.add(kind, params, inputs, vec![output]);
+        Ok(())
+    }
+
+    /// Adds a cell of the internal gate library of Yosys with the nets of its
+    /// pins, keyed by the names of the pins.
+    fn add_library_cell(
+        &mut self,
+        kind: &str,
+        pins: &BTreeMap<String, Option<Net>>,
+    ) -> Result<(), FileError> {
+        if let Some(flip_flop) = flip_flop(kind) {
+            return self.add_flip_flop(&flip_flop, pins);
+        }
+        if let Some(selects) = wide_mux(kind) {
+            let pin = |name: &str| pins.get(name).copied().flatten();
+            let data: Vec<Option<Net>> = (b'A'..)
+                .take(1 << selects)
+                .map(|name| pin(&(name as char).to_string()))
+                .collect();
+            let selects: Vec<Option<Net>> = ["S", "T", "U", "V"][..selects]
+                .iter()
+                .map(|name| pin(name))
+                .collect();
+            self.add_mux(&data, &selects, pin("Y"));
+            return Ok(());
+        }
+        let (gate, params, input_pins, output_pins) = library_cell(kind).ok_or_else(|| {
+            FileError(format!(
+                "unsupported cell type {kind}, the design has to be mapped to gates, \
+                 e.g. by synth -flatten"
+            ))
+        })?;
+
+        let inputs = input_pins
+            .iter()
+            .map(|pin| self.library_pin(pin, pins))
+            .collect();
+        let outputs = output_pins
+            .iter()
+            .map(|pin| pins.get(*pin).copied().flatten())
+            .collect();
+        self.add(gate, params.to_vec(), inputs, outputs);
+        Ok(())
+    }
+
+    /// Net of an input pin of a library cell, pins starting with `!` are
+    /// inverted by a `NOT` gate.
+    fn library_pin(&mut self, pin: &str, pins: &BTreeMap<String, Option<Net>>) -> Option<Net> {
+        match pin.strip_prefix('!') {
+            Some(pin) => {
+                let net = pins.get(pin).copied().flatten();
+                let inverted = self.fresh();
+                self.add("NOT", Vec::new(), vec![net], vec![Some(inverted)]);
+                Some(inverted)
+            }
+            None => pins.get(pin).copied().flatten(),
+        }
+    }
+
+    /// Adds a D flip-flop for a flip-flop cell, enables and synchronous resets
+    /// are a table computing the next value of the flip-flop from its D input
+    /// and its current value.
+    fn add_flip_flop(
+        &mut self,
+        flip_flop: &FlipFlop,
+        pins: &BTreeMap<String, Option<Net>>,
+    ) -> Result<(), FileError> {
+        let pin = |name: &str| pins.get(name).copied().flatten();
+        let mut d = pin("D");
+        let mut q = pin("Q");
+        if flip_flop.enable.is_some() || flip_flop.sync_reset.is_some() {
+            let current = *q.get_or_insert_with(|| self.fresh());
+            let mut inputs = vec![d, Some(current)];
+            let enable = flip_flop.enable.map(|polarity| {
+                inputs.push(pin("E"));
+                (inputs.len() - 1, polarity)
+            });
+            let reset = flip_flop.sync_reset.map(|(polarity, value)| {
+                inputs.push(pin("R"));
+                (inputs.len() - 1, polarity, value)
+            });
+            let next = |index: usize| {
+                let bit = |input: usize| index >> input & 1 == 1;
+                let enabled = enable.is_none_or(|(input, polarity)| bit(input) == polarity);
+                match reset {
+                    Some((input, polarity, value))
+                        if bit(input) == polarity && (enabled || !flip_flop.reset_when_enabled) =>
+                    {
+                        value
+                    }
+                    _ if enabled => bit(0),
+                    _ => bit(1),
+                }
+            };
+            let table = (0..1 << inputs.len())
+                .filter(|index| next(*index))
+                .fold(0, |table, index| table | 1 << index);
+            let next = self.fresh();
+            self.add_table(table, inputs, Some(next))?;
+            d = Some(next);
+        }
+
+        let [clock, set, reset] = flip_flop.pins.map(|pin| self.library_pin(pin, pins));
+        self.add("DFF", Vec::new(), vec![d, clock, set, reset], vec![q, None]);
+        Ok(())
+    }
+
+    /// Adds a multiplexer of the data inputs as a tree of multiplexers of two
+    /// inputs, the last select input chooses between the halves of the data
+    /// inputs.
+    fn add_mux(&mut self, data: &[Option<Net>], selects: &[Option<Net>], output: Option<Net>) {
+        let (select, selects) = selects.split_last().expect("no select input");
+        let (low, high) = data.split_at(data.len() / 2);
+        let mut inputs = Vec::with_capacity(3);
+        for half in [low, high] {
+            match half {
+                [net] => inputs.push(*net),
+                _ => {
+                    let net = self.fresh();
+                    self.add_mux(half, selects, Some(net));
+                    inputs.push(Some(net));
+                }
+            }
+        }
+        inputs.push(*select);
+        self.add("LUT", vec![3, 0xca], inputs, vec![output]);
+    }
+
+    /// Board with the ports and cells laid out in columns.
+    fn to_board(&self) -> BoardFile {
+        // gates are the input ports, the cells and the output ports, with
+        // their index as the id
+        let first_cell = self.inputs.len();
+        let first_output = first_cell + self.cells.len();
+        let count = first_output + self.outputs.len();
+        let gate_inputs = |gate: usize| -> Vec<Option<Net>> {
+            if gate < first_cell {
+                Vec::new()
+            } else if gate < first_output {
+                self.cells[gate - first_cell].inputs.clone()
+            } else {
+                vec![self.outputs[gate - first_output]]
+            }
+        };
+        let pins = |gate: usize| -> usize {
+            if gate < first_cell || gate >= first_output {
+                1
+            } else {
+                let cell = &self.cells[gate - first_cell];
+                cell.inputs.len().max(cell.outputs.len()).max(1)
+            }
+        };
+
+        let mut drivers: BTreeMap<Net, Vec<(usize, usize)>> = BTreeMap::new();
+        for (index, net) in self.inputs.iter().enumerate() {
+            drivers.entry(*net).or_default().push((index, 0));
+        }
+        for (index, cell) in self.cells.iter().enumerate() {
+            for (output, net) in cell.outputs.iter().enumerate() {
+                if let Some(net) = net {
+                    drivers
+                        .entry(*net)
+                        .or_default()
+                        .push((first_cell + index, output));
+                }
+            }
+        }
+        let driving = |gate: usize| -> Vec<usize> {
+            gate_inputs(gate)
+                .iter()
+                .flatten()
+                .flat_map(|net| drivers.get(net).into_iter().flatten())
+                .map(|(driver, _)| *driver)
+                .collect()
+        };
+
+        // cells go one column after the latest of their drivers, wires back
+        // to a gate whose column is being found close a loop and are skipped
+        let mut columns: Vec<Option<usize>> = vec![None; count];
+        let mut visiting = vec![false; count];
+        columns[..first_cell].fill(Some(0));
+        for start in first_cell..first_output {
+            if columns[start].is_some() {
+                continue;
+            }
+            let mut stack = vec![(start, driving(start), 0)];
+            visiting[start] = true;
+            while let Some((gate, gate_drivers, next)) = stack.last_mut() {
+                if let Some(driver) = gate_drivers.get(*next).copied() {
+                    *next += 1;
+                    if columns[driver].is_none() && !visiting[driver] {
+                        visiting[driver] = true;
+                        stack.push((driver, driving(driver), 0));
+                    }
+                    continue;
+                }
+                let column = gate_drivers
+                    .iter()
+                    .filter_map(|driver| columns[*driver])
+                    .max()
+                    .map_or(1, |column| column + 1);
+                columns[*gate] = Some(column);
+                visiting[*gate] = false;
+                stack.pop();
+            }
+        }
+        let last = columns
+            .iter()
+            .flatten()
+            .max()
+            .map_or(1, |column| column + 1);
+        for column in &mut columns[first_output..] {
+            *column = Some(last);
+        }
+
+        let mut layout: Vec<Vec<usize>> = vec![Vec::new(); last + 1];
+        for (gate, column) in columns.iter().enumerate() {
+            layout[column.unwrap()].push(gate);
+        }
+        let mut centers = vec![0.; count];
+        let mut pos = vec![(0., 0.); count];
+        for (column, gates) in layout.iter_mut().enumerate() {
+            // ports keep their order, other gates are sorted by the centers
+            // of their drivers in earlier columns
+            if column != 0 && column != last {
+                let barycenter = |gate: usize| {
+                    let placed: Vec<f32> = driving(gate)
+                        .iter()
+                        .filter(|driver| columns[**driver] < Some(column))
+                        .map(|driver| centers[*driver])
+                        .collect();
+                    match placed.len() {
+                        0 => f32::MAX,
+                        len => placed.iter().sum::<f32>() / len as f32,
+                    }
+                };
+                let keys: BTreeMap<usize, f32> = gates
+                    .iter()
+                    .map(|gate| (*gate, barycenter(*gate)))
+                    .collect();
+                gates.sort_by(|a, b| keys[a].total_cmp(&keys[b]));
+            }
+
+            let mut y = 0.;
+            for gate in gates {
+                let height = pins(*gate) as f32 * PIN_HEIGHT;
+                pos[*gate] = (column as f32 * COLUMN_WIDTH, y);
+                centers[*gate] = y + height / 2.;
+                y += height + ROW_SPACING;
+            }
+        }
+
+        let mut board = BoardFile::default();
+        for (gate, pos) in pos.into_iter().enumerate() {
+            let (kind, params) = if gate < first_cell {
+                ("IN", vec![1])
+            } else if gate < first_output {
+                let cell = &self.cells[gate - first_cell];
+                (cell.kind, cell.params.clone())
+            } else {
+                ("OUT", vec![1])
+            };
+            board.gates.push(GateEntry {
+                id: gate,
+                kind: kind.to_string(),
+                pos,
+                delay: 1,
+                params,
+            });
+
+            for (input, net) in gate_inputs(gate).iter().enumerate() {
+                for output in net
+                    .iter()
+                    .flat_map(|net| drivers.get(net).into_iter().flatten())
+                {
+                    board.wires.push(WireEntry {
+                        output: *output,
+                        input: (gate, input),
+                        waypoints: Vec::new(),
+                    });
+                }
+            }
+        }
+        board
+    }
+}
+
+/// Kind and parameters of a gate with the pins of a cell connected to the
+/// inputs and outputs of the gate.
+type LibraryCell = (
+    &'static str,
+    &'static [u64],
+    &'static [&'static str],
+    &'static [&'static str],
+);
+
+/// Gate for a cell of the internal gate library of Yosys. Pins starting with
+/// `!` are inverted, empty ones are left unconnected.
+fn library_cell(kind: &str) -> Option<LibraryCell> {
+    const Y: &[&str] = &["Y"];
+    const Q: &[&str] = &["Q", ""];
+    Some(match kind {
+        "$_BUF_" => ("YES", &[], &["A"], Y),
+        "$_NOT_" => ("NOT", &[], &["A"], Y),
+        "$_AND_" => ("AND", &[], &["A", "B"], Y),
+        "$_NAND_" => ("NAND", &[], &["A", "B"], Y),
+        "$_OR_" => ("OR", &[], &["A", "B"], Y),
+        "$_NOR_" => ("NOR", &[], &["A", "B"], Y),
+        "$_XOR_" => ("XOR", &[], &["A", "B"], Y),
+        "$_XNOR_" => ("XNOR", &[], &["A", "B"], Y),
+        // A and not B, A or not B, B when S is high or else A and its inverse
+        "$_ANDNOT_" => ("LUT", &[2, 0b0010], &["A", "B"], Y),
+        "$_ORNOT_" => ("LUT", &[2, 0b1011], &["A", "B"], Y),
+        "$_MUX_" => ("LUT", &[3, 0xca], &["A", "B", "S"], Y),
+        "$_NMUX_" => ("LUT", &[3, 0x35], &["A", "B", "S"], Y),
+        // inverted A and B or C, inverted A or B and C, and the same of pairs
+        "$_AOI3_" => ("LUT", &[3, 0x07], &["A", "B", "C"], Y),
+        "$_OAI3_" => ("LUT", &[3, 0x1f], &["A", "B", "C"], Y),
+        "$_AOI4_" => ("LUT", &[4, 0x0777], &["A", "B", "C", "D"], Y),
+        "$_OAI4_" => ("LUT", &[4, 0x111f], &["A", "B", "C", "D"], Y),
+        "$_SR_PP_" => ("SR", &[], &["S", "R"], Q),
+        "$_SR_PN_" => ("SR", &[], &["S", "!R"], Q),
+        "$_SR_NP_" => ("SR", &[], &["!S", "R"], Q),
+        "$_SR_NN_" => ("SR", &[], &["!S", "!R"], Q),
+        _ => return None,
+    })
+}
+
+/// Number of select inputs of a multiplexer of more than two inputs, whose
+/// data inputs are named from `A` and select inputs from `S`.
+fn wide_mux(kind: &str) -> Option<usize> {
+    match kind {
+        "$_MUX4_" => Some(2),
+        "$_MUX8_" => Some(3),
+        "$_MUX16_" => Some(4),
+        _ => None,
+    }
+}
+
+/// Flip-flop cell of the internal gate library of Yosys.
+struct FlipFlop {
+    /// Pins of the clock, set and reset inputs of the D flip-flop, as in
+    /// [`library_cell`]
+    pins: [&'static str; 3],
+    /// Polarity of the enable input
+    enable: Option<bool>,
+    /// Polarity of the synchronous reset input and the value it resets to
+    sync_reset: Option<(bool, bool)>,
+    /// Whether the synchronous reset only applies when enabled
+    reset_when_enabled: bool,
+}
+
+/// Flip-flop for a cell whose name is the kind of flip-flop followed by the
+/// polarities of its pins and the values they reset to, e.g. `$_DFFE_PN0P_`
+/// for a flip-flop on the rising edge of the clock reset to 0 by a low `R`
+/// and enabled by a high `E`.
+fn flip_flop(kind: &str) -> Option<FlipFlop> {
+    let (name, flags) = kind
+        .strip_prefix("$_")?
+        .strip_suffix('_')?
+        .split_once('_')?;
+    let pin = |pin: &'static str, flag: u8| match (pin, flag) {
+        (_, b'P') => Some(pin),
+        ("C", b'N') => Some("!C"),
+        ("S", b'N') => Some("!S"),
+        ("R", b'N') => Some("!R"),
+        _ => None,
+    };
+    let polarity = |flag: u8| match flag {
+        b'P' => Some(true),
+        b'N' => Some(false),
+        _ => None,
+    };
+    let value = |flag: u8| match flag {
+        b'0' => Some(false),
+        b'1' => Some(true),
+        _ => None,
+    };
+    // asynchronous reset to 0 or 1, given the pin and the value flag
+    let reset = |pin: &'static str, flag: u8| match flag {
+        b'0' => Some(["", pin]),
+        b'1' => Some([pin, ""]),
+        _ => None,
+    };
+
+    let mut flip_flop = FlipFlop {
+        pins: ["", "", ""],
+        enable: None,
+        sync_reset: None,
+        reset_when_enabled: false,
+    };
+    let clock = |flag: u8| pin("C", flag);
+    match (name, flags.as_bytes()) {
+        ("DFF", &[c]) => flip_flop.pins[0] = clock(c)?,
+        ("DFF", &[c, r, v]) => {
+            let [set, reset] = reset(pin("R", r)?, v)?;
+            flip_flop.pins = [clock(c)?, set, reset];
+        }
+        ("DFFE", &[c, e]) => {
+            flip_flop.pins[0] = clock(c)?;
+            flip_flop.enable = Some(polarity(e)?);
+        }
+        ("DFFE", &[c, r, v, e]) => {
+            let [set, reset] = reset(pin("R", r)?, v)?;
+            flip_flop.pins = [clock(c)?, set, reset];
+            flip_flop.enable = Some(polarity(e)?);
+        }
+        ("DFFSR", &[c, s, r]) => flip_flop.pins = [clock(c)?, pin("S", s)?, pin("R", r)?],
+        ("DFFSRE", &[c, s, r, e]) => {
+            flip_flop.pins = [clock(c)?, pin("S", s)?, pin("R", r)?];
+            flip_flop.enable = Some(polarity(e)?);
+        }
+        ("SDFF", &[c, r, v]) => {
+            flip_flop.pins[0] = clock(c)?;
+            flip_flop.sync_reset = Some((polarity(r)?, value(v)?));
+        }
+        ("SDFFE" | "SDFFCE", &[c, r, v, e]) => {
+            flip_flop.pins[0] = clock(c)?;
+            flip_flop.sync_reset = Some((polarity(r)?, value(v)?));
+            flip_flop.enable = Some(polarity(e)?);
+            flip_flop.reset_when_enabled = name == "SDFFCE";
+        }
+        _ => return None,
+    }
+    Some(flip_flop)
+}
+
+/// Gate computing given table of given number of inputs, as described by
+/// [`Lut`](crate::gates::Lut). Tables of logic gates and constants give those
+/// gates, other tables give lookup tables.
+fn table_gate(inputs: usize, table: u64) -> Result<(&'static str, Vec<u64>), FileError> {
+    if inputs > 6 {
+        return Err(FileError(format!(
+            "functions of {inputs} inputs are not supported, at most 6"
+        )));
+    }
+    let kind = match (inputs, table & mask(inputs)) {
+        (0, 0) => "LOW",
+        (0, _) => "HIGH",
+        (1, 0b10) => "YES",
+        (1, 0b01) => "NOT",
+        (2, 0b1000) => "AND",
+        (2, 0b0111) => "NAND",
+        (2, 0b1110) => "OR",
+        (2, 0b0001) => "NOR",
+        (2, 0b0110) => "XOR",
+        (2, 0b1001) => "XNOR",
+        (_, table) => return Ok(("LUT", vec![inputs as u64, table])),
+    };
+    Ok((kind, Vec::new()))
+}
+
+/// Bits of the table of a function of given number of inputs.
+fn mask(inputs: usize) -> u64 {
+    u64::MAX.checked_shr(64 - (1 << inputs)).unwrap_or(0)
+}
+
+fn parse_blif(text: &str) -> Result<Netlist, FileError> {
+    // lines ending with a backslash continue on the next line
+    let mut lines: Vec<(usize, Vec<&str>)> = Vec::new();
+    let mut continued = false;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim_end();
+        let (line, continues) = match line.strip_suffix('\\') {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        match lines.last_mut() {
+            Some((_, tokens)) if continued => tokens.extend(line.split_whitespace()),
+            _ => lines.push((index + 1, line.split_whitespace().collect())),
+        }
+        continued = continues;
+    }
+    lines.retain(|(_, tokens)| !tokens.is_empty());
+
+    let mut netlist = Netlist::default();
+    let mut lines = lines.into_iter().peekable();
+    while let Some((number, tokens)) = lines.next() {
+        let error = |message: String| FileError(format!("line {number}: {message}"));
+        match tokens[..] {
+            [".model", ..] => {}
+            // only the first model is read, as the top module
+            [".end", ..] => break,
+            [".inputs", ref names @ ..] => {
+                for name in names {
+                    let net = netlist.net(name);
+                    netlist.inputs.push(net);
+                }
+            }
+            [".outputs", ref names @ ..] => {
+                for name in names {
+                    let net = netlist.net(name);
+                    netlist.outputs.push(Some(net));
+                }
+            }
+            [".names", ref names @ ..] if !names.is_empty() => {
+                let (output, inputs) = names.split_last().unwrap();
+                let mut cover = Vec::new();
+                while let Some((_, row)) = lines.next_if(|(_, row)| !row[0].starts_with('.')) {
+                    cover.push(row);
+                }
+                let table = cover_table(inputs.len(), &cover).map_err(error)?;
+                let inputs = inputs.iter().map(|name| Some(netlist.net(name))).collect();
+                let output = netlist.net(output);
+                netlist
+                    .add_table(table, inputs, Some(output))
+                    .map_err(|FileError(message)| error(message))?;
+            }
+            [".latch", input, output, kind, control, ..] => {
+                let cell = match kind {
+                    "re" => "$_DFF_P_",
+                    "fe" => "$_DFF_N_",
+                    _ => return Err(error(format!("unsupported latch of type {kind}"))),
+                };
+                let mut pins = BTreeMap::new();
+                pins.insert("D".to_string(), Some(netlist.net(input)));
+                pins.insert("C".to_string(), Some(netlist.net(control)));
+                pins.insert("Q".to_string(), Some(netlist.net(output)));
+                netlist.add_library_cell(cell, &pins)?;
+            }
+            [".latch", ..] => return Err(error("latches need a clock".to_string())),
+            [".subckt" | ".gate", kind, ref connections @ ..] => {
+                let mut pins = BTreeMap::new();
+                for connection in connections {
+                    let (pin, name) = connection
+                        .split_once('=')
+                        .ok_or_else(|| error(format!("invalid connection {connection}")))?;
+                    pins.insert(pin.to_string(), Some(netlist.net(name)));
+                }
+                netlist
+                    .add_library_cell(kind, &pins)
+                    .map_err(|FileError(message)| error(message))?;
+            }
+            // names and attributes of cells
+            [".cname" | ".attr" | ".param", ..] => {}
+            _ => return Err(error(format!("invalid line \"{}\"", tokens.join(" ")))),
+        }
+    }
+    Ok(netlist)
+}
+
+/// Table of a `.names` cover, rows list the values of the inputs with `-` for
+/// any value followed by the value of the output for them.
+fn cover_table(inputs: usize, cover: &[Vec<&str>]) -> Result<u64, String> {
+    if inputs > 6 {
+        return Err(format!(
+            "functions of {inputs} inputs are not supported, at most 6"
+        ));
+    }
+
+    let mut table = 0;
+    let mut value = "1";
+    for row in cover {
+        let (cube, output) = match row[..] {
+            [output] if inputs == 0 => ("", output),
+            [cube, output] if cube.len() == inputs => (cube, output),
+            _ => return Err(format!("invalid cover row \"{}\"", row.join(" "))),
+        };
+        if !matches!(output, "0" | "1") || !cube.chars().all(|bit| "01-".contains(bit)) {
+            return Err(format!("invalid cover row \"{}\"", row.join(" ")));
+        }
+        value = output;
+
+        for input in 0..1 << inputs {
+            let matches = cube.bytes().enumerate().all(|(index, bit)| match bit {
+                b'0' => input >> index & 1 == 0,
+                b'1' => input >> index & 1 == 1,
+                _ => true,
+            });
+            if matches {
+                table |= 1 << input;
+            }
+        }
+    }
+
+    // rows with a low output list where the output is low
+    Ok(if value == "0" {
+        !table & mask(inputs)
+    } else {
+        table
+    })
+}
+
+fn parse_json(text: &str) -> Result<Netlist, FileError> {
+    let mut parser = JsonParser {
+        text,
+        pos: 0,
+        depth: 0,
+    };
+    let json = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != text.len() {
+        return Err(parser.error());
+    }
+
+    let invalid = |what: &str| FileError(format!("invalid {what} in the netlist"));
+    let modules = json
+        .get("modules")
+        .and_then(Json::as_object)
+        .ok_or_else(|| invalid("modules"))?;
+    let is_top = |module: &Json| {
+        module
+            .get("attributes")
+            .and_then(|attributes| attributes.get("top"))
+            .is_some_and(|top| match top {
+                Json::Bool(value) => *value,
+                Json::Number(value) => *value != 0.,
+                Json::String(value) => value.contains('1'),
+                _ => false,
+            })
+    };
+    let module = match modules.iter().find(|(_, module)| is_top(module)) {
+        Some((_, module)) => module,
+        None if modules.len() == 1 => &modules[0].1,
+        None => {
+            return Err(FileError(
+                "no top module in the netlist, select it by hierarchy -top".to_string(),
+            ))
+        }
+    };
+
+    let mut netlist = Netlist::default();
+    let bit = |netlist: &mut Netlist, bit: &Json| match bit {
+        Json::Number(net) => Ok(Some(netlist.net(&net.to_string()))),
+        Json::String(value) => match value.as_str() {
+            "0" => Ok(Some(netlist.constant(false))),
+            "1" => Ok(Some(netlist.constant(true))),
+            "x" | "z" => Ok(None),
+            _ => Err(invalid("bit")),
+        },
+        _ => Err(invalid("bit")),
+    };
+
+    let ports = module.get("ports").and_then(Json::as_object);
+    for (name, port) in ports.into_iter().flatten() {
+        let port_bits = port
+            .get("bits")
+            .and_then(Json::as_array)
+            .ok_or_else(|| invalid("bits"))?;
+        match port.get("direction").and_then(Json::as_str) {
+            Some("input") => {
+                for port_bit in port_bits {
+                    let net = bit(&mut netlist, port_bit)?.ok_or_else(|| invalid("input bit"))?;
+                    netlist.inputs.push(net);
+                }
+            }
+            Some("output") => {
+                for port_bit in port_bits {
+                    let net = bit(&mut netlist, port_bit)?;
+                    netlist.outputs.push(net);
+                }
+            }
+            _ => {
+                return Err(FileError(format!(
+                    "port {name} is not an input or an output"
+                )))
+            }
+        }
+    }
+
+    let cells = module.get("cells").and_then(Json::as_object);
+    for (name, cell) in cells.into_iter().flatten() {
+        let kind = cell
+            .get("type")
+            .and_then(Json::as_str)
+            .ok_or_else(|| invalid("cell"))?;
+        let mut pins = BTreeMap::new();
+        let connections = cell.get("connections").and_then(Json::as_object);
+        for (pin, connected) in connections.into_iter().flatten() {
+            let mut nets = Vec::new();
+            for connected in connected.as_array().ok_or_else(|| invalid("bits"))? {
+                nets.push(bit(&mut netlist, connected)?);
+            }
+            pins.insert(pin.clone(), nets);
+        }
+
+        let in_cell = |FileError(message)| FileError(format!("cell {name}: {message}"));
+        if kind == "$lut" {
+            let table = cell
+                .get("parameters")
+                .and_then(|parameters| parameters.get("LUT"))
+                .and_then(Json::as_number)
+                .ok_or_else(|| invalid("lookup table"))?;
+            let inputs = pins.remove("A").unwrap_or_default();
+            let output = pins.remove("Y").unwrap_or_default();
+            netlist
+                .add_table(table, inputs, output.first().copied().flatten())
+                .map_err(in_cell)?;
+        } else {
+            let pins = pins
+                .into_iter()
+                .map(|(pin, nets)| (pin, nets.first().copied().flatten()))
+                .collect();
+            netlist.add_library_cell(kind, &pins).map_err(in_cell)?;
+        }
+    }
+    Ok(netlist)
+}
+
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Members in the order they are written
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        let members = self.as_object()?;
+        members
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
+    fn as_object(&self) -> Option<&[(String, Json)]> {
+        match self {
+            Json::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Value of a number, or of a string of bits written by Yosys for
+    /// parameters, most significant first.
+    fn as_number(&self) -> Option<u64> {
+        match self {
+            Json::Number(value) if *value >= 0. && value.fract() == 0. => Some(*value as u64),
+            Json::String(bits) => {
+                let bits = bits.trim_start_matches('0');
+                if bits.is_empty() {
+                    Some(0)
+                } else {
+                    u64::from_str_radix(bits, 2).ok()
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Deepest nesting of objects and arrays read, deeper files are rejected
+/// before parsing them overflows the stack. Netlists nest only a few levels.
+const MAX_JSON_DEPTH: usize = 64;
+
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+    /// Objects and arrays the parser is inside of
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self) -> FileError {
+        FileError(format!("invalid JSON at byte {}", self.pos))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), FileError> {
+        self.skip_whitespace();
+        if self.peek() != Some(byte) {
+            return Err(self.error());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Whether the next value ends a list closed by given byte, consumes the
+    /// separating comma or the closing byte.
+    fn list_ends(&mut self, close: u8) -> Result<bool, FileError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b',') => {
+                self.pos += 1;
+                Ok(false)
+            }
+            Some(byte) if byte == close => {
+                self.pos += 1;
+                Ok(true)
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, FileError> {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        let keyword = |word: &str, value| rest.starts_with(word).then_some((word.len(), value));
+        if let Some((len, value)) = keyword("null", Json::Null)
+            .or_else(|| keyword("true", Json::Bool(true)))
+            .or_else(|| keyword("false", Json::Bool(false)))
+        {
+            self.pos += len;
+            return Ok(value);
+        }
+
+        match self.peek() {
+            Some(open @ (b'{' | b'[')) => {
+                if self.depth == MAX_JSON_DEPTH {
+                    return Err(FileError(format!(
+                        "JSON nested too deeply at byte {}",
+                        self.pos
+                    )));
+                }
+                self.depth += 1;
+                self.pos += 1;
+                let value = if open == b'{' {
+                    self.object()
+                } else {
+                    self.array()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b'-' | b'0'..=b'9') => {
+                let len = rest
+                    .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+                    .unwrap_or(rest.len());
+                let number = rest[..len].parse().map_err(|_| self.error())?;
+                self.pos += len;
+                Ok(Json::Number(number))
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    /// Members of an object after its opening brace.
+    fn object(&mut self) -> Result<Json, FileError> {
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(b':')?;
+            members.push((key, self.value()?));
+            if self.list_ends(b'}')? {
+                return Ok(Json::Object(members));
+            }
+        }
+    }
+
+    /// Values of an array after its opening bracket.
+    fn array(&mut self) -> Result<Json, FileError> {
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            if self.list_ends(b']')? {
+                return Ok(Json::Array(values));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, FileError> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error());
+        }
+        self.pos += 1;
+
+        let text = self.text;
+        let mut chars = text[self.pos..].char_indices();
+        let mut value = String::new();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += offset + 1;
+                    return Ok(value);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let code: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            u32::from_str_radix(&code, 16)
+                                .ok()
+                                .filter(|_| code.len() == 4)
+                                .map(|code| char::from_u32(code).unwrap_or('\u{fffd}'))
+                                .ok_or_else(|| self.error())?
+                        }
+                        Some(c @ ('"' | '\\' | '/')) => c,
+                        _ => return Err(self.error()),
+                    };
+                    value.push(escaped);
+                }
+                c => value.push(c),
+            }
+        }
+        Err(self.error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        headless::Runner, logic_simulation::LogicSimulation, signal::Signal,
+        subcircuit::SubcircuitMode,
+    };
+
+    /// Checks the outputs of an imported board for all values of its inputs.
+    fn check(board: &BoardFile, expected: impl Fn(usize) -> Vec<u8>) {
+        let mut runner = Runner::new(board).unwrap();
+        let (inputs, _) = board.ports();
+        for value in 0..1 << inputs.len() {
+            let mut stimulus = String::new();
+            for index in 0..inputs.len() {
+                stimulus += &format!("set in{index} {}\n", value >> index & 1);
+            }
+            stimulus += "settle\n";
+            for (index, bit) in expected(value).iter().enumerate() {
+                stimulus += &format!("expect out{index} {bit}\n");
+            }
+            runner.run(&stimulus).unwrap();
+        }
+        assert_eq!(runner.failures, Vec::<String>::new());
+    }
+
+    #[test]
+    fn blif_full_adder() {
+        let blif = "# full adder
+.model adder
+.inputs a b \\
+  cin
+.outputs sum cout
+.names a b cin sum
+100 1
+010 1
+001 1
+111 1
+.names a b ab
+11 1
+.names a b t
+00 0
+11 0
+.names t cin tc
+11 1
+.names ab tc cout
+00 0
+.end
+";
+        let board = import(blif).unwrap();
+        let kinds: Vec<&str> = board.gates.iter().map(|gate| gate.kind.as_str()).collect();
+        assert_eq!(
+            kinds,
+            ["IN", "IN", "IN", "LUT", "AND", "XOR", "AND", "OR", "OUT", "OUT"]
+        );
+        // gates go one column after their drivers
+        let x: Vec<f32> = board.gates.iter().map(|gate| gate.pos.0).collect();
+        assert_eq!(x, [0., 0., 0., 150., 150., 150., 300., 450., 600., 600.]);
+
+        check(&board, |value| {
+            let sum = value.count_ones();
+            vec![sum as u8 & 1, (sum >= 2) as u8]
+        });
+    }
+
+    #[test]
+    fn yosys_json_with_flip_flop() {
+        // y = sel ? b : a, q = y delayed by the clock, c constant high
+        let json = r#"{
+  "creator": "Yosys",
+  "modules": {
+    "lib": { "ports": {}, "cells": {} },
+    "top": {
+      "attributes": { "top": "00000000000000000000000000000001" },
+      "ports": {
+        "ab": { "direction": "input", "bits": [ 2, 3 ] },
+        "sel": { "direction": "input", "bits": [ 4 ] },
+        "clk": { "direction": "input", "bits": [ 5 ] },
+        "y": { "direction": "output", "bits": [ 6 ] },
+        "q": { "direction": "output", "bits": [ 7 ] },
+        "c": { "direction": "output", "bits": [ "1", "x" ] }
+      },
+      "cells": {
+        "$mux": {
+          "type": "$_MUX_",
+          "connections": { "A": [ 2 ], "B": [ 3 ], "S": [ 4 ], "Y": [ 6 ] }
+        },
+        "$ff": {
+          "type": "$_DFF_P_",
+          "connections": { "C": [ 5 ], "D": [ 6 ], "Q": [ 7 ] }
+        },
+        "$lut\\1": {
+          "type": "$lut",
+          "parameters": { "LUT": "0110", "WIDTH": 2 },
+          "connections": { "A": [ 2, 3 ], "Y": [ 8 ] }
+        }
+      }
+    }
+  }
+}"#;
+        let board = import(json).unwrap();
+        assert!(board.gates.iter().any(|gate| gate.kind == "XOR"));
+
+        let mut runner = Runner::new(&board).unwrap();
+        runner
+            .run(
+                "set in0 1
+set in1 0
+set in2 1
+set in3 0
+settle
+expect out0 0
+expect out1 X
+expect out2 1
+expect out3 Z
+set in3 1
+settle
+expect out1 0
+set in2 0
+set in3 0
+settle
+set in3 1
+settle
+expect out0 1
+expect out1 1",
+            )
+            .unwrap();
+        assert_eq!(runner.failures, Vec::<String>::new());
+    }
+
+    #[test]
+    fn combinational_library_cells() {
+        let blif = ".model m
+.inputs a b c d e f g h s t u
+.outputs y z w
+.gate $_MUX8_ A=a B=b C=c D=d E=e F=f G=g H=h S=s T=t U=u Y=y
+.gate $_AOI4_ A=a B=b C=c D=d Y=z
+.gate $_NMUX_ A=a B=b S=s Y=w
+.end
+";
+        check(&import(blif).unwrap(), |value| {
+            let bit = |index: usize| (value >> index & 1) as u8;
+            let select = value >> 8 & 7;
+            vec![
+                bit(select),
+                1 - (bit(0) & bit(1) | bit(2) & bit(3)),
+                1 - bit(bit(8) as usize),
+            ]
+        });
+    }
+
+    #[test]
+    fn flip_flops_with_enable_and_resets() {
+        // q is enabled by a high e and reset to 0 by a low r, s is enabled
+        // by a low e and reset to 1 by a high r on the clock
+        let blif = ".model m
+.inputs d clk e r
+.outputs q s
+.gate $_DFFE_PN0P_ D=d C=clk R=r E=e Q=q
+.gate $_SDFFCE_PP1N_ D=d C=clk R=r E=e Q=s
+.end
+";
+        let mut runner = Runner::new(&import(blif).unwrap()).unwrap();
+        runner
+            .run(
+                "set in0 1
+set in1 0
+set in2 1
+set in3 1
+settle
+set in1 1
+settle
+expect out0 1
+expect out1 X
+set in0 0
+set in1 0
+set in2 0
+settle
+set in1 1
+settle
+expect out0 1
+expect out1 1
+set in1 0
+set in3 0
+settle
+expect out0 0
+set in1 1
+settle
+expect out1 0",
+            )
+            .unwrap();
+        assert_eq!(runner.failures, Vec::<String>::new());
+    }
+
+    #[test]
+    fn imported_inputs_can_be_toggled() {
+        let board = import(".model m\n.inputs a b\n.outputs y\n.names a b y\n11 1\n.end").unwrap();
+        let mut sim = LogicSimulation::new();
+        let (ids, _) = board
+            .build(&mut sim, &[], SubcircuitMode::default())
+            .unwrap();
+        let (inputs, outputs) = board.ports();
+        let settle = |sim: &mut LogicSimulation| {
+            while !sim.is_stable() {
+                sim.simulate();
+            }
+            sim.get_gate_state(ids[&outputs[0]]).unwrap().1[0].bit(0)
+        };
+
+        assert_eq!(settle(&mut sim), Signal::Unknown);
+        for input in &inputs {
+            assert!(sim.is_gate_interactive(ids[input]).unwrap());
+            sim.press_gate(ids[input]).unwrap();
+        }
+        assert_eq!(settle(&mut sim), Signal::High);
+        sim.press_gate(ids[&inputs[1]]).unwrap();
+        assert_eq!(settle(&mut sim), Signal::Low);
+    }
+
+    #[test]
+    fn unsupported_netlists() {
+        let error = |text| import(text).unwrap_err().0;
+        assert_eq!(
+            error(".model m\n.inputs a\n.latch a b ah c\n.end"),
+            "line 3: unsupported latch of type ah"
+        );
+        assert_eq!(
+            error(".model m\n.names a b\n1 1\n2 1\n"),
+            "line 2: invalid cover row \"2 1\""
+        );
+        assert!(
+            error(r#"{"modules": {"m": {"cells": {"c": {"type": "$add"}}}}}"#)
+                .starts_with("cell c: unsupported cell type $add")
+        );
+        assert_eq!(error("{\"modules\": [1,]}"), "invalid JSON at byte 15");
+        assert_eq!(
+            error(&format!("{{\"modules\": {}", "[".repeat(100_000))),
+            "JSON nested too deeply at byte 75"
+        );
+    }
+}
@@ -0,0 +1,319 @@
+//! Orthogonal routing of wires around the bodies of gates. Only available
+//! with the `gui` feature.
+//!
+//! Routes are made of horizontal and vertical segments. A wire leaves its
+//! output to the right and enters its input from the left, in between it
+//! passes through its bend points in order. Each part between two points is
+//! searched on the lines running along the edges of the gates in the way,
+//! preferring short routes with few bends.
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BinaryHeap},
+};
+
+use macroquad::prelude::{Rect, Vec2};
+
+/// Distance kept between wires and the bodies of gates.
+pub const MARGIN: f32 = 15.;
+
+/// Cost of a bend, as the length of a straight segment it is worth.
+const BEND_COST: f32 = 20.;
+
+/// Area around the part of a route in which gates are routed around.
+const SEARCH_AREA: f32 = 200.;
+
+/// Route from an output to an input through given bend points, avoiding
+/// given areas of gates. Returns the corners of the route, starting at the
+/// output and ending at the input.
+pub fn route(output: Vec2, input: Vec2, waypoints: &[Vec2], gates: &[Rect]) -> Vec<Vec2> {
+    let obstacles: Vec<Rect> = gates.iter().map(|gate| inflate(*gate)).collect();
+    let mut points = vec![output + Vec2::new(MARGIN, 0.)];
+    points.extend_from_slice(waypoints);
+    points.push(input - Vec2::new(MARGIN, 0.));
+
+    let mut route = vec![output, points[0]];
+    for part in points.windows(2) {
+        route.extend(route_part(part[0], part[1], &obstacles));
+    }
+    route.push(input);
+    simplify(route)
+}
+
+/// Whether a route passes through the area of a gate, or too close to it.
+pub fn crosses(route: &[Vec2], gate: Rect) -> bool {
+    let area = inflate(gate);
+    route
+        .windows(2)
+        .any(|segment| blocks(area, segment[0], segment[1]))
+}
+
+/// Distance from a point to the closest segment of a route.
+pub fn distance(route: &[Vec2], point: Vec2) -> f32 {
+    route
+        .windows(2)
+        .map(|segment| {
+            let (a, b) = (segment[0], segment[1]);
+            let length = (b - a).length_squared();
+            let t = if length > 0. {
+                ((point - a).dot(b - a) / length).clamp(0., 1.)
+            } else {
+                0.
+            };
+            point.distance(a + (b - a) * t)
+        })
+        .fold(f32::INFINITY, f32::min)
+}
+
+fn inflate(gate: Rect) -> Rect {
+    Rect::new(
+        gate.x - MARGIN,
+        gate.y - MARGIN,
+        gate.w + 2. * MARGIN,
+        gate.h + 2. * MARGIN,
+    )
+}
+
+/// Whether the segment goes through the inside of the area, segments along
+/// its edges are allowed.
+fn blocks(area: Rect, a: Vec2, b: Vec2) -> bool {
+    let (min, max) = (a.min(b), a.max(b));
+    min.x < area.right() && max.x > area.left() && min.y < area.bottom() && max.y > area.top()
+}
+
+/// Direction of the last segment of a partial route, bends are counted
+/// when it changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Heading {
+    None,
+    Horizontal,
+    Vertical,
+}
+
+/// Crossing of two search lines with the heading it was reached with.
+type Node = (usize, usize, Heading);
+
+struct Open {
+    /// Cost of the route so far plus the estimated remaining cost
+    estimate: f32,
+    node: Node,
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    /// Reversed, so that the heap pops the lowest estimate first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .total_cmp(&self.estimate)
+            .then(other.node.cmp(&self.node))
+    }
+}
+
+/// Routes the part between two points with an A* search, falls back to a
+/// route with two bends when the gates leave no way through. Returns the
+/// corners after the start.
+fn route_part(start: Vec2, end: Vec2, obstacles: &[Rect]) -> Vec<Vec2> {
+    if start == end {
+        return Vec::new();
+    }
+
+    // gates the points are in cannot be avoided
+    let area = Rect::new(start.x, start.y, 0., 0.).combine_with(Rect::new(end.x, end.y, 0., 0.));
+    let area = Rect::new(
+        area.x - SEARCH_AREA,
+        area.y - SEARCH_AREA,
+        area.w + 2. * SEARCH_AREA,
+        area.h + 2. * SEARCH_AREA,
+    );
+    let inside = |rect: &Rect, point: Vec2| blocks(*rect, point, point);
+    let obstacles: Vec<Rect> = obstacles
+        .iter()
+        .filter(|rect| rect.overlaps(&area) && !inside(rect, start) && !inside(rect, end))
+        .copied()
+        .collect();
+
+    // search lines along the points, the edges of the gates and around all
+    // of them
+    let bounds = obstacles.iter().fold(
+        Rect::new(start.x, start.y, 0., 0.).combine_with(Rect::new(end.x, end.y, 0., 0.)),
+        |bounds, rect| bounds.combine_with(*rect),
+    );
+    let mut xs = vec![
+        start.x,
+        end.x,
+        bounds.left() - MARGIN,
+        bounds.right() + MARGIN,
+    ];
+    let mut ys = vec![
+        start.y,
+        end.y,
+        bounds.top() - MARGIN,
+        bounds.bottom() + MARGIN,
+    ];
+    for rect in &obstacles {
+        xs.extend([rect.left(), rect.right()]);
+        ys.extend([rect.top(), rect.bottom()]);
+    }
+    for lines in [&mut xs, &mut ys] {
+        lines.sort_by(f32::total_cmp);
+        lines.dedup();
+    }
+
+    let index = |lines: &[f32], value: f32| lines.iter().position(|line| *line == value);
+    let (Some(start_x), Some(start_y), Some(end_x), Some(end_y)) = (
+        index(&xs, start.x),
+        index(&ys, start.y),
+        index(&xs, end.x),
+        index(&ys, end.y),
+    ) else {
+        return detour(start, end);
+    };
+
+    let point = |(x, y, _): Node| Vec2::new(xs[x], ys[y]);
+    let remaining = |node: Node| {
+        let delta = (end - point(node)).abs();
+        delta.x + delta.y
+    };
+
+    let first = (start_x, start_y, Heading::None);
+    let mut costs = BTreeMap::from([(first, 0f32)]);
+    let mut previous: BTreeMap<Node, Node> = BTreeMap::new();
+    let mut open = BinaryHeap::from([Open {
+        estimate: remaining(first),
+        node: first,
+    }]);
+
+    while let Some(Open { node, .. }) = open.pop() {
+        let (x, y, heading) = node;
+        if (x, y) == (end_x, end_y) {
+            let mut corners = vec![point(node)];
+            let mut node = node;
+            while let Some(before) = previous.get(&node) {
+                node = *before;
+                corners.push(point(node));
+            }
+            corners.pop();
+            corners.reverse();
+            return corners;
+        }
+
+        let cost = costs[&node];
+        let neighbours = [
+            (x.checked_sub(1).map(|x| (x, y)), Heading::Horizontal),
+            (
+                (x + 1 < xs.len()).then_some((x + 1, y)),
+                Heading::Horizontal,
+            ),
+            (y.checked_sub(1).map(|y| (x, y)), Heading::Vertical),
+            ((y + 1 < ys.len()).then_some((x, y + 1)), Heading::Vertical),
+        ];
+        for (next, next_heading) in neighbours {
+            let Some((next_x, next_y)) = next else {
+                continue;
+            };
+            let next = (next_x, next_y, next_heading);
+            let (from, to) = (point(node), point(next));
+            if obstacles.iter().any(|rect| blocks(*rect, from, to)) {
+                continue;
+            }
+
+            let bend = if heading != Heading::None && heading != next_heading {
+                BEND_COST
+            } else {
+                0.
+            };
+            let next_cost = cost + from.distance(to) + bend;
+            if costs.get(&next).is_none_or(|known| next_cost < *known) {
+                costs.insert(next, next_cost);
+                previous.insert(next, node);
+                open.push(Open {
+                    estimate: next_cost + remaining(next),
+                    node: next,
+                });
+            }
+        }
+    }
+
+    detour(start, end)
+}
+
+/// Route with a vertical segment halfway between the points.
+fn detour(start: Vec2, end: Vec2) -> Vec<Vec2> {
+    let middle = (start.x + end.x) / 2.;
+    vec![Vec2::new(middle, start.y), Vec2::new(middle, end.y), end]
+}
+
+/// Removes repeated corners and corners in the middle of straight segments.
+fn simplify(route: Vec<Vec2>) -> Vec<Vec2> {
+    let mut corners: Vec<Vec2> = Vec::with_capacity(route.len());
+    for point in route {
+        if corners.last() == Some(&point) {
+            continue;
+        }
+        if let [.., a, b] = corners[..] {
+            let straight = (a.x == b.x && b.x == point.x) || (a.y == b.y && b.y == point.y);
+            if straight {
+                corners.pop();
+            }
+        }
+        corners.push(point);
+    }
+    corners
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_orthogonal(route: &[Vec2]) -> bool {
+        route
+            .windows(2)
+            .all(|segment| segment[0].x == segment[1].x || segment[0].y == segment[1].y)
+    }
+
+    #[test]
+    fn aligned_pins_are_connected_straight() {
+        let route = route(Vec2::new(0., 10.), Vec2::new(100., 10.), &[], &[]);
+        assert_eq!(route, [Vec2::new(0., 10.), Vec2::new(100., 10.)]);
+    }
+
+    #[test]
+    fn routes_go_around_gates() {
+        let gate = Rect::new(100., -50., 50., 100.);
+        let (output, input) = (Vec2::new(0., 0.), Vec2::new(300., 20.));
+        let route = route(output, input, &[], &[gate]);
+
+        assert!(is_orthogonal(&route));
+        assert_eq!((route[0], route[route.len() - 1]), (output, input));
+        assert!(!crosses(&route, gate));
+        // the wire leaves the output to the right and enters the input from
+        // the left
+        assert_eq!(route[1].y, output.y);
+        assert_eq!(route[route.len() - 2].y, input.y);
+    }
+
+    #[test]
+    fn routes_pass_through_waypoints() {
+        let waypoints = [Vec2::new(50., 100.), Vec2::new(150., -40.)];
+        let route = route(Vec2::ZERO, Vec2::new(200., 0.), &waypoints, &[]);
+
+        assert!(is_orthogonal(&route));
+        for waypoint in waypoints {
+            assert_eq!(distance(&route, waypoint), 0.);
+        }
+    }
+}
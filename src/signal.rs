@@ -0,0 +1,98 @@
+use std::{
+    fmt,
+    ops::{BitAnd, BitOr, BitXor, Not},
+};
+
+/// Four-state value of a single pin or wire.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Signal {
+    Low,
+    High,
+    /// The value is not known, e.g. an output that was not computed yet or a
+    /// gate reading an undriven input.
+    #[default]
+    Unknown,
+    /// Nothing drives the pin.
+    HighZ,
+}
+
+impl Signal {
+    /// Value as seen by a gate input, high impedance reads as unknown.
+    pub fn driven(self) -> Signal {
+        match self {
+            Signal::HighZ => Signal::Unknown,
+            signal => signal,
+        }
+    }
+}
+
+impl From<bool> for Signal {
+    fn from(value: bool) -> Self {
+        if value {
+            Signal::High
+        } else {
+            Signal::Low
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = match self {
+            Signal::Low => '0',
+            Signal::High => '1',
+            Signal::Unknown => 'X',
+            Signal::HighZ => 'Z',
+        };
+        write!(f, "{c}")
+    }
+}
+
+impl Not for Signal {
+    type Output = Signal;
+
+    fn not(self) -> Signal {
+        match self.driven() {
+            Signal::Low => Signal::High,
+            Signal::High => Signal::Low,
+            _ => Signal::Unknown,
+        }
+    }
+}
+
+impl BitAnd for Signal {
+    type Output = Signal;
+
+    fn bitand(self, rhs: Signal) -> Signal {
+        // a single low input decides the result even if the other is unknown
+        match (self.driven(), rhs.driven()) {
+            (Signal::Low, _) | (_, Signal::Low) => Signal::Low,
+            (Signal::High, Signal::High) => Signal::High,
+            _ => Signal::Unknown,
+        }
+    }
+}
+
+impl BitOr for Signal {
+    type Output = Signal;
+
+    fn bitor(self, rhs: Signal) -> Signal {
+        // a single high input decides the result even if the other is unknown
+        match (self.driven(), rhs.driven()) {
+            (Signal::High, _) | (_, Signal::High) => Signal::High,
+            (Signal::Low, Signal::Low) => Signal::Low,
+            _ => Signal::Unknown,
+        }
+    }
+}
+
+impl BitXor for Signal {
+    type Output = Signal;
+
+    fn bitxor(self, rhs: Signal) -> Signal {
+        match (self.driven(), rhs.driven()) {
+            (Signal::Unknown, _) | (_, Signal::Unknown) => Signal::Unknown,
+            (a, b) => Signal::from(a != b),
+        }
+    }
+}
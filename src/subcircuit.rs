@@ -0,0 +1,202 @@
+use crate::{
+    board_file::{self, BoardFile, FileError},
+    id::GateId,
+    logic_simulation::LogicSimulation,
+};
+
+/// Selects how [`Circuit::instantiate`] adds subcircuits to a simulation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SubcircuitMode {
+    /// Each subcircuit runs its own simulation, advanced together with the
+    /// simulation the subcircuit is placed in.
+    #[default]
+    Nested,
+    /// Gates of the subcircuit are added directly to the simulation the
+    /// subcircuit is placed in.
+    Flattened,
+}
+
+impl SubcircuitMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            SubcircuitMode::Nested => "nested",
+            SubcircuitMode::Flattened => "flattened",
+        }
+    }
+}
+
+/// Board used as a component, its `IN` and `OUT` ports become the inputs and
+/// outputs of the component.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Circuit {
+    pub name: String,
+    pub board: BoardFile,
+}
+
+impl Circuit {
+    /// Checks that a circuit can be added to a library under given name, the
+    /// name is used as the kind of the gate in files.
+    pub fn check_name(name: &str, library: &[Circuit]) -> Result<(), FileError> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(FileError(format!("invalid circuit name \"{name}\"")));
+        }
+
+        if board_file::is_builtin(name) || library.iter().any(|circuit| circuit.name == name) {
+            return Err(FileError(format!("circuit name {name} is already used")));
+        }
+
+        Ok(())
+    }
+
+    /// Adds an instance of the circuit to the simulation, subcircuits used by
+    /// the circuit are looked up in given library. Returns the id of the gate
+    /// standing for the circuit.
+    pub fn instantiate(
+        &self,
+        sim: &mut LogicSimulation,
+        library: &[Circuit],
+        mode: SubcircuitMode,
+    ) -> Result<GateId, FileError> {
+        let error = |FileError(message)| FileError(format!("circuit {}: {message}", self.name));
+        let (inputs, outputs) = self.board.ports();
+
+        match mode {
+            SubcircuitMode::Nested => {
+                let mut inner = LogicSimulation::new();
+                inner.set_default_resolution(self.board.default_resolution);
+                let (ids, _) = self.board.build(&mut inner, library, mode).map_err(error)?;
+                let ports = |ports: Vec<usize>| ports.iter().map(|port| ids[port]).collect();
+
+                Ok(sim.add_nested(self.name.clone(), inner, ports(inputs), ports(outputs))?)
+            }
+            SubcircuitMode::Flattened => {
+                let (ids, _) = self.board.build(sim, library, mode).map_err(error)?;
+                let ports = |ports: Vec<usize>| ports.iter().map(|port| ids[port]).collect();
+
+                // nets inside the circuit keep the default policy of the
+                // circuit instead of the one of the simulation
+                for id in ids.values() {
+                    for index in 0..sim.get_gate_state(*id)?.0.len() {
+                        let input = id.input(index);
+                        if sim.resolution_override(input)?.is_none() {
+                            sim.set_resolution(input, self.board.default_resolution)?;
+                        }
+                    }
+                }
+
+                Ok(sim.add_flattened(
+                    self.name.clone(),
+                    ids.values().copied().collect(),
+                    ports(inputs),
+                    ports(outputs),
+                )?)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gates::*, signal::Signal};
+
+    const LIBRARY: &str = "logic-sim 2
+circuit HALFADD
+gate 0 IN 0 0 1 1
+gate 1 IN 0 50 1 1
+gate 2 XOR 100 0 1
+gate 3 AND 100 50 1
+gate 4 OUT 200 0 1 1
+gate 5 OUT 200 50 1 1
+wire 0.0 2.0
+wire 1.0 2.1
+wire 0.0 3.0
+wire 1.0 3.1
+wire 2.0 4.0
+wire 3.0 5.0
+end
+circuit FULLADD
+gate 0 IN 0 0 1 1
+gate 1 IN 0 50 1 1
+gate 2 IN 0 100 1 1
+gate 3 HALFADD 100 0 1
+gate 4 HALFADD 200 50 1
+gate 5 OR 300 100 1
+gate 6 OUT 400 0 1 1
+gate 7 OUT 400 100 1 1
+wire 0.0 3.0
+wire 1.0 3.1
+wire 3.0 4.0
+wire 2.0 4.1
+wire 3.1 5.0
+wire 4.1 5.1
+wire 4.0 6.0
+wire 5.0 7.0
+end
+";
+
+    /// Sums three bits with a full adder, returns the sum and carry after
+    /// each step.
+    fn full_adder_waveform(mode: SubcircuitMode, bits: [bool; 3]) -> Vec<(Signal, Signal)> {
+        let file = BoardFile::parse(LIBRARY).unwrap();
+        let mut sim = LogicSimulation::new();
+        let adder = file.circuits[1]
+            .instantiate(&mut sim, &file.circuits[..1], mode)
+            .unwrap();
+
+        for (input, bit) in bits.into_iter().enumerate() {
+            let source = if bit {
+                sim.add_gate(High)
+            } else {
+                sim.add_gate(Low)
+            };
+            sim.add_connection(source.output(0), adder.input(input))
+                .unwrap();
+        }
+
+        (0..12)
+            .map(|_| {
+                sim.simulate();
+                let outputs = sim.get_gate_state(adder).unwrap().1;
+                (outputs[0].bit(0), outputs[1].bit(0))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nested_and_flattened_circuits_agree() {
+        for bits in [
+            [false, false, true],
+            [true, false, true],
+            [true, true, true],
+        ] {
+            let nested = full_adder_waveform(SubcircuitMode::Nested, bits);
+            let flattened = full_adder_waveform(SubcircuitMode::Flattened, bits);
+            assert_eq!(nested, flattened);
+
+            let count = bits.iter().filter(|bit| **bit).count();
+            let sum = Signal::from(count % 2 == 1);
+            let carry = Signal::from(count >= 2);
+            assert_eq!(nested.last(), Some(&(sum, carry)));
+        }
+    }
+
+    #[test]
+    fn circuits_only_use_earlier_circuits() {
+        let file = BoardFile::parse(LIBRARY).unwrap();
+        let mut sim = LogicSimulation::new();
+        let error = file.circuits[1]
+            .instantiate(&mut sim, &[], SubcircuitMode::Nested)
+            .unwrap_err();
+        assert_eq!(error.0, "circuit FULLADD: unknown gate kind HALFADD");
+    }
+
+    #[test]
+    fn circuit_names() {
+        let file = BoardFile::parse(LIBRARY).unwrap();
+        assert!(Circuit::check_name("MUX", &file.circuits).is_ok());
+        assert!(Circuit::check_name("HALFADD", &file.circuits).is_err());
+        assert!(Circuit::check_name("AND", &file.circuits).is_err());
+        assert!(Circuit::check_name("HALF ADD", &file.circuits).is_err());
+    }
+}
@@ -0,0 +1,381 @@
+//! Export of recorded [`waveform`](crate::waveform) traces as Value Change
+//! Dump files, as read by waveform viewers such as GTKWave.
+//!
+//! Pins are placed in scopes following the subcircuits their gates were
+//! added for, all inside a top scope `board`. Each pin is a variable named
+//! after the name of its gate and the id of the gate as the instance label,
+//! followed by the pin, e.g. `AND_3_in1` or `HALFADD_7` for the scope of a
+//! subcircuit. Names that are not plain identifiers are escaped as in
+//! [`verilog`], e.g. `\7SEG_4`.
+//!
+//! Pins inside flattened subcircuits are passed to [`export`] like any other
+//! pin. Gates of nested subcircuits, the default
+//! [`SubcircuitMode`](crate::subcircuit::SubcircuitMode), run in their own
+//! simulations, all pins recorded in them by
+//! [`LogicSimulation::record_nested`] are dumped in the scopes of the nested
+//! subcircuits, ids of their gates are the ids in those simulations.
+//!
+//! One step of the simulation is one unit of the timescale, times a pin has
+//! no kept sample for are dumped as unknown.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{
+    bus::Bus,
+    id::GateId,
+    logic_simulation::{LogicSimulation, SimulationError},
+    signal::Signal,
+    verilog,
+    waveform::Probe,
+};
+
+/// Name of the scope holding all pins.
+const TOP_SCOPE: &str = "board";
+
+/// Scope of a subcircuit, or the top scope.
+#[derive(Default)]
+struct Scope {
+    name: String,
+    /// Indices of the variables declared directly in the scope
+    vars: Vec<usize>,
+    scopes: BTreeMap<GateId, Scope>,
+}
+
+impl Scope {
+    fn new(name: String) -> Scope {
+        Scope {
+            name,
+            ..Scope::default()
+        }
+    }
+
+    /// Whether the scope and the scopes inside it have no variables.
+    fn is_empty(&self) -> bool {
+        self.vars.is_empty() && self.scopes.values().all(Scope::is_empty)
+    }
+}
+
+struct Var<'a> {
+    /// Simulation recording the pin, that of a nested subcircuit for pins
+    /// inside it
+    sim: &'a LogicSimulation,
+    probe: Probe,
+    code: String,
+    width: usize,
+    name: String,
+}
+
+/// Writes the recorded values of given pins as a VCD file, pins that are
+/// not recorded are dumped as unknown. Pins recorded inside nested
+/// subcircuits are written as well.
+pub fn export(sim: &LogicSimulation, probes: &[Probe]) -> Result<String, SimulationError> {
+    let mut top = Scope::new(TOP_SCOPE.to_string());
+    let mut vars = Vec::with_capacity(probes.len());
+    add_vars(sim, probes, &mut top, &mut vars)?;
+
+    let mut out = String::new();
+    writeln!(out, "$version logic-sim $end").unwrap();
+    writeln!(out, "$timescale 1ns $end").unwrap();
+    write_scope(&mut out, &top, &vars);
+    writeln!(out, "$enddefinitions $end").unwrap();
+
+    let traces: Vec<_> = vars
+        .iter()
+        .filter_map(|var| var.sim.waveforms().trace(var.probe))
+        .filter(|trace| !trace.is_empty())
+        .collect();
+    let start = traces.iter().map(|trace| trace.start()).min();
+    let end = traces.iter().map(|trace| trace.end()).max();
+    let (start, end) = start.zip(end).unwrap_or((sim.time(), sim.time() + 1));
+
+    let mut last: Vec<Option<Bus>> = vec![None; vars.len()];
+    for time in start..end {
+        let mut changes = String::new();
+        for (var, last) in vars.iter().zip(&mut last) {
+            let value = var
+                .sim
+                .waveforms()
+                .trace(var.probe)
+                .and_then(|trace| trace.value_at(time))
+                .unwrap_or(Bus::new(var.width, Signal::Unknown));
+            if *last != Some(value) {
+                writeln!(changes, "{}", change(value, &var.code)).unwrap();
+                *last = Some(value);
+            }
+        }
+
+        if time == start {
+            write!(out, "#{time}\n$dumpvars\n{changes}$end\n").unwrap();
+        } else if !changes.is_empty() {
+            write!(out, "#{time}\n{changes}").unwrap();
+        }
+    }
+    // the last sample lasts for one step
+    writeln!(out, "#{end}").unwrap();
+
+    Ok(out)
+}
+
+/// Adds variables for pins of a simulation to the scope standing for it, in
+/// the scopes of the flattened subcircuits they are in, followed by the pins
+/// recorded inside its nested subcircuits.
+fn add_vars<'a>(
+    sim: &'a LogicSimulation,
+    probes: &[Probe],
+    top: &mut Scope,
+    vars: &mut Vec<Var<'a>>,
+) -> Result<(), SimulationError> {
+    for probe in probes {
+        let (width, pin) = match *probe {
+            Probe::Input(input) => {
+                let inputs = sim.get_gate_state(input.gate)?.0;
+                let bus = inputs
+                    .get(input.index)
+                    .ok_or(SimulationError::UnknownInput(input))?;
+                (bus.width(), format!("in{}", input.index))
+            }
+            Probe::Output(output) => {
+                let outputs = sim.get_gate_state(output.gate)?.1;
+                let bus = outputs
+                    .get(output.index)
+                    .ok_or(SimulationError::UnknownOutput(output))?;
+                (bus.width(), format!("out{}", output.index))
+            }
+        };
+        scope(sim, top, probe.gate())?.vars.push(vars.len());
+        vars.push(Var {
+            sim,
+            probe: *probe,
+            code: code(vars.len()),
+            width,
+            name: name(format!("{}_{pin}", label(sim, probe.gate())?)),
+        });
+    }
+
+    for (gate, inner) in sim.nested() {
+        let probes: Vec<Probe> = inner.waveforms().traces().map(|(probe, _)| probe).collect();
+        let name = name(label(sim, gate)?);
+        let scope = scope(sim, top, gate)?
+            .scopes
+            .entry(gate)
+            .or_insert_with(|| Scope::new(name));
+        add_vars(inner, &probes, scope, vars)?;
+    }
+    Ok(())
+}
+
+/// Scope for the pins of a gate, following the flattened subcircuits the
+/// gate is in.
+fn scope<'s>(
+    sim: &LogicSimulation,
+    top: &'s mut Scope,
+    gate: GateId,
+) -> Result<&'s mut Scope, SimulationError> {
+    let mut path = Vec::new();
+    let mut gate = gate;
+    while let Some(parent) = sim.get_gate_parent(gate)? {
+        path.push(parent);
+        gate = parent;
+    }
+
+    let mut scope = top;
+    for parent in path.iter().rev() {
+        let name = name(label(sim, *parent)?);
+        scope = scope
+            .scopes
+            .entry(*parent)
+            .or_insert_with(|| Scope::new(name));
+    }
+    Ok(scope)
+}
+
+/// Writes a scope with the scopes inside it, empty scopes are left out.
+fn write_scope(out: &mut String, scope: &Scope, vars: &[Var]) {
+    writeln!(out, "$scope module {} $end", scope.name).unwrap();
+    for index in &scope.vars {
+        let var = &vars[*index];
+        writeln!(
+            out,
+            "$var wire {} {} {} $end",
+            var.width, var.code, var.name
+        )
+        .unwrap();
+    }
+    for inner in scope.scopes.values().filter(|inner| !inner.is_empty()) {
+        write_scope(out, inner, vars);
+    }
+    writeln!(out, "$upscope $end").unwrap();
+}
+
+/// Name of a gate with its id as the instance label.
+fn label(sim: &LogicSimulation, gate: GateId) -> Result<String, SimulationError> {
+    Ok(format!("{}_{gate}", sim.get_gate_name(gate)?))
+}
+
+/// Name of a scope or variable, escaped like Verilog identifiers, without
+/// the space ending escaped identifiers as names end at spaces anyway.
+fn name(name: String) -> String {
+    verilog::identifier(&name).trim_end().to_string()
+}
+
+/// Short identifier of the variable with given index, made of the printable
+/// characters from `!` to `~`.
+fn code(mut index: usize) -> String {
+    const FIRST: u8 = b'!';
+    const COUNT: usize = (b'~' - FIRST + 1) as usize;
+
+    let mut code = String::new();
+    loop {
+        code.push((FIRST + (index % COUNT) as u8) as char);
+        index /= COUNT;
+        if index == 0 {
+            return code;
+        }
+        index -= 1;
+    }
+}
+
+/// Value change of a variable, bits are written most significant first.
+fn change(value: Bus, code: &str) -> String {
+    let bit = |signal: Signal| signal.to_string().to_ascii_lowercase();
+    if value.width() == 1 {
+        return format!("{}{code}", bit(value.bit(0)));
+    }
+
+    let bits: String = (0..value.width())
+        .rev()
+        .map(|index| bit(value.bit(index)))
+        .collect();
+    format!("b{bits} {code}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gates::*, logic_simulation::SimulationMode};
+
+    #[test]
+    fn pins_are_dumped_in_scopes_of_subcircuits() {
+        let mut sim = LogicSimulation::new();
+        let low = sim.add_gate(Low);
+        let input = sim.add_gate(InputPort::default());
+        let not = sim.add_gate(Not);
+        let output = sim.add_gate(OutputPort::default());
+        sim.add_connection(input.output(0), not.input(0)).unwrap();
+        sim.add_connection(not.output(0), output.input(0)).unwrap();
+        let inv = sim
+            .add_flattened(
+                "INV".to_string(),
+                vec![input, not, output],
+                vec![input],
+                vec![output],
+            )
+            .unwrap();
+        sim.add_connection(low.output(0), inv.input(0)).unwrap();
+
+        let probes = [Probe::Output(not.output(0)), Probe::Input(inv.input(0))];
+        for probe in probes {
+            sim.record(probe).unwrap();
+        }
+        for _ in 0..3 {
+            sim.simulate();
+        }
+
+        let expected = "$version logic-sim $end
+$timescale 1ns $end
+$scope module board $end
+$var wire 1 \" INV_4_in0 $end
+$scope module INV_4 $end
+$var wire 1 ! NOT_2_out0 $end
+$upscope $end
+$upscope $end
+$enddefinitions $end
+#0
+$dumpvars
+x!
+z\"
+$end
+#1
+x\"
+#2
+0\"
+#3
+1!
+#4
+";
+        assert_eq!(export(&sim, &probes).unwrap(), expected);
+    }
+
+    #[test]
+    fn nested_subcircuits_get_scopes() {
+        // the pins inside the subcircuit follow the time of the board also in
+        // the event-driven mode, where the subcircuit is not always updated
+        for mode in [SimulationMode::Tick, SimulationMode::Event] {
+            let mut inner = LogicSimulation::new();
+            inner.set_mode(mode);
+            let input = inner.add_gate(InputPort::default());
+            let not = inner.add_gate(Not);
+            let output = inner.add_gate(OutputPort::default());
+            inner.add_connection(input.output(0), not.input(0)).unwrap();
+            inner
+                .add_connection(not.output(0), output.input(0))
+                .unwrap();
+
+            let mut sim = LogicSimulation::new();
+            sim.set_mode(mode);
+            let low = sim.add_gate(Low);
+            let inv = sim
+                .add_nested("7INV".to_string(), inner, vec![input], vec![output])
+                .unwrap();
+            sim.add_connection(low.output(0), inv.input(0)).unwrap();
+            assert_eq!(
+                sim.subcircuit_ports(),
+                [(vec![inv], input), (vec![inv], output)]
+            );
+
+            let probes = [Probe::Output(inv.output(0))];
+            sim.record(probes[0]).unwrap();
+            sim.record_nested(&[inv], Probe::Output(not.output(0)))
+                .unwrap();
+            assert_eq!(
+                sim.record_nested(&[low], probes[0]),
+                Err(SimulationError::NotNested(low))
+            );
+            for _ in 0..6 {
+                sim.simulate();
+            }
+
+            // the name of the subcircuit is escaped, the pins inside it are
+            // in its scope
+            let expected = "$version logic-sim $end
+$timescale 1ns $end
+$scope module board $end
+$var wire 1 ! \\7INV_1_out0 $end
+$scope module \\7INV_1 $end
+$var wire 1 \" NOT_1_out0 $end
+$upscope $end
+$upscope $end
+$enddefinitions $end
+#0
+$dumpvars
+x!
+x\"
+$end
+#3
+1\"
+#4
+1!
+#7
+";
+            assert_eq!(export(&sim, &probes).unwrap(), expected, "{mode:?} mode");
+        }
+    }
+
+    #[test]
+    fn codes_are_printable() {
+        assert_eq!(code(0), "!");
+        assert_eq!(code(93), "~");
+        assert_eq!(code(94), "!!");
+        assert_eq!(change(Bus::from_u64(4, 5), "!"), "b0101 !");
+    }
+}
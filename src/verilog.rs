@@ -0,0 +1,538 @@
+//! Export of boards as structural Verilog.
+//!
+//! The board becomes a module with the `IN` ports as inputs `in0`, `in1`, ...
+//! and the `OUT` ports as outputs `out0`, `out1`, ..., numbered from the top
+//! as the pins of a subcircuit. Every subcircuit of the library becomes a
+//! module with the name of the circuit, instantiated for each of its gates.
+//!
+//! - Logic gates map to the gate primitives, gates on buses to arrays of
+//!   primitives, and `HIGH` and `LOW` to constants.
+//! - Splitters and mergers map to part selects and concatenations, and lookup
+//!   tables to a shift of the table by their inputs.
+//! - Flip-flops and the SR latch map to small behavioural modules written
+//!   along with the board, set and reset take effect at once and set wins
+//!   when both are active.
+//! - Switches, buttons and clocks of the board become inputs named after the
+//!   kind and the id of the gate in the file, e.g. `sw_3` or `clk_4`, and
+//!   indicators become outputs, e.g. `led_5`. Inside subcircuits switches and
+//!   buttons are never pressed and stay low, indicators are left out and
+//!   clocks cannot be exported.
+//!
+//! Other gates and wires are named after the ids of the gates in the file,
+//! e.g. `g2` and its output `g2_o0`. Inputs driven by several outputs are
+//! `wor`, `wand` or `tri` nets following their resolution policy, and
+//! unconnected inputs are in high impedance, except for set and reset of
+//! flip-flops, which are inactive. Delays of gates are not exported, wires
+//! into `IN` ports of a board are left out as the value of the port comes
+//! from outside of the module.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{
+    board_file::{BoardFile, FileError},
+    bus::Bus,
+    logic_simulation::LogicSimulation,
+    net::Resolution,
+    subcircuit::{Circuit, SubcircuitMode},
+};
+
+/// Behavioural modules of the sequential gates, with the kinds they are used
+/// for.
+const CELLS: [(&str, &str); 4] = [
+    (
+        "DFF",
+        "module logic_sim_dff (input d, input clk, input set, input reset, output reg q, output qn);
+  assign qn = ~q;
+  always @(posedge clk or posedge set or posedge reset)
+    if (set) q <= 1'b1;
+    else if (reset) q <= 1'b0;
+    else q <= d;
+endmodule
+",
+    ),
+    (
+        "JKFF",
+        "module logic_sim_jkff (input j, input k, input clk, input set, input reset, output reg q, output qn);
+  assign qn = ~q;
+  always @(posedge clk or posedge set or posedge reset)
+    if (set) q <= 1'b1;
+    else if (reset) q <= 1'b0;
+    else q <= (j & ~q) | (~k & q);
+endmodule
+",
+    ),
+    (
+        "TFF",
+        "module logic_sim_tff (input t, input clk, input set, input reset, output reg q, output qn);
+  assign qn = ~q;
+  always @(posedge clk or posedge set or posedge reset)
+    if (set) q <= 1'b1;
+    else if (reset) q <= 1'b0;
+    else q <= t ^ q;
+endmodule
+",
+    ),
+    (
+        "SR",
+        "module logic_sim_sr (input s, input r, output reg q, output qn);
+  assign qn = ~q;
+  always @(s or r)
+    if (s) q <= 1'b1;
+    else if (r) q <= 1'b0;
+endmodule
+",
+    ),
+];
+
+/// Writes the board as a module with given name, preceded by the modules of
+/// its subcircuits and of the sequential gates it uses.
+pub fn export(file: &BoardFile, name: &str) -> Result<String, FileError> {
+    let mut out = String::from("// written by logic-sim\n");
+    let boards = || {
+        file.circuits
+            .iter()
+            .map(|circuit| &circuit.board)
+            .chain([file])
+    };
+    for (kind, cell) in CELLS {
+        if boards().any(|board| board.gates.iter().any(|gate| gate.kind == kind)) {
+            write!(out, "\n{cell}").unwrap();
+        }
+    }
+
+    for (index, circuit) in file.circuits.iter().enumerate() {
+        let module = module(
+            &circuit.board,
+            &file.circuits[..index],
+            &circuit.name,
+            false,
+        )
+        .map_err(|FileError(message)| FileError(format!("circuit {}: {message}", circuit.name)))?;
+        write!(out, "\n{module}").unwrap();
+    }
+    write!(out, "\n{}", module(file, &file.circuits, name, true)?).unwrap();
+
+    Ok(out)
+}
+
+/// Gate primitive of a logic gate.
+fn primitive(kind: &str) -> Option<&'static str> {
+    Some(match kind {
+        "AND" => "and",
+        "NAND" => "nand",
+        "OR" => "or",
+        "NOR" => "nor",
+        "XOR" => "xor",
+        "XNOR" => "xnor",
+        "NOT" => "not",
+        "YES" => "buf",
+        _ => return None,
+    })
+}
+
+/// Names of the pins of the behavioural module of a sequential gate.
+fn cell_pins(kind: &str) -> Option<(&'static [&'static str], &'static [&'static str])> {
+    const Q: &[&str] = &["q", "qn"];
+    Some(match kind {
+        "DFF" => (&["d", "clk", "set", "reset"], Q),
+        "JKFF" => (&["j", "k", "clk", "set", "reset"], Q),
+        "TFF" => (&["t", "clk", "set", "reset"], Q),
+        "SR" => (&["s", "r"], Q),
+        _ => return None,
+    })
+}
+
+/// Name of a port standing for a switch, button, clock or indicator.
+fn device_port(kind: &str, id: usize) -> Option<String> {
+    let prefix = match kind {
+        "SW" => "sw",
+        "BTN" => "btn",
+        "CLK" => "clk",
+        "LED" => "led",
+        "7SEG" => "seg",
+        "HEX" => "hex",
+        _ => return None,
+    };
+    Some(format!("{prefix}_{id}"))
+}
+
+/// Reserved words of Verilog, which cannot be used as plain identifiers.
+#[rustfmt::skip]
+const KEYWORDS: &[&str] = &[
+    "always", "and", "assign", "automatic", "begin", "buf", "bufif0", "bufif1", "case", "casex",
+    "casez", "cell", "cmos", "config", "deassign", "default", "defparam", "design", "disable",
+    "edge", "else", "end", "endcase", "endconfig", "endfunction", "endgenerate", "endmodule",
+    "endprimitive", "endspecify", "endtable", "endtask", "event", "for", "force", "forever", "fork",
+    "function", "generate", "genvar", "highz0", "highz1", "if", "ifnone", "incdir", "include",
+    "initial", "inout", "input", "instance", "integer", "join", "large", "liblist", "library",
+    "localparam", "macromodule", "medium", "module", "nand", "negedge", "nmos", "nor",
+    "noshowcancelled", "not", "notif0", "notif1", "or", "output", "parameter", "pmos", "posedge",
+    "primitive", "pull0", "pull1", "pulldown", "pullup", "pulsestyle_ondetect",
+    "pulsestyle_onevent", "rcmos", "real", "realtime", "reg", "release", "repeat", "rnmos", "rpmos",
+    "rtran", "rtranif0", "rtranif1", "scalared", "showcancelled", "signed", "small", "specify",
+    "specparam", "strong0", "strong1", "supply0", "supply1", "table", "task", "time", "tran",
+    "tranif0", "tranif1", "tri", "tri0", "tri1", "triand", "trior", "trireg", "unsigned", "use",
+    "uwire", "vectored", "wait", "wand", "weak0", "weak1", "while", "wire", "wor", "xnor", "xor",
+];
+
+/// Identifier for a name, names that are not plain identifiers or that are
+/// reserved words are escaped.
+pub(crate) fn identifier(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name);
+    if plain {
+        name.to_string()
+    } else {
+        format!("\\{name} ")
+    }
+}
+
+/// Declaration of a net or port of given width.
+fn declare(kind: &str, width: usize, name: &str) -> String {
+    if width == 1 {
+        format!("{kind} {name}")
+    } else {
+        format!("{kind} [{}:0] {name}", width - 1)
+    }
+}
+
+/// Writes a module for a board, subcircuits are looked up in given library.
+/// Switches, buttons, clocks and indicators become ports of the top module.
+fn module(
+    board: &BoardFile,
+    library: &[Circuit],
+    name: &str,
+    top: bool,
+) -> Result<String, FileError> {
+    // the widths of the pins are those of the gates added to a simulation
+    let mut sim = LogicSimulation::new();
+    let (ids, _) = board.build(&mut sim, library, SubcircuitMode::Nested)?;
+    let mut widths: BTreeMap<usize, (Vec<usize>, Vec<usize>)> = BTreeMap::new();
+    for gate in &board.gates {
+        let (inputs, outputs) = sim.get_gate_state(ids[&gate.id])?;
+        let widths_of = |buses: &[Bus]| buses.iter().map(|bus| bus.width()).collect();
+        widths.insert(gate.id, (widths_of(inputs), widths_of(outputs)));
+    }
+
+    let mut ports = Vec::new();
+    // outputs of gates whose nets are ports
+    let mut named: BTreeMap<(usize, usize), String> = BTreeMap::new();
+    let (inputs, outputs) = board.ports();
+    for (index, gate) in inputs.iter().enumerate() {
+        let port = format!("in{index}");
+        ports.push(declare("input", widths[gate].1[0], &port));
+        named.insert((*gate, 0), port);
+    }
+    for (index, gate) in outputs.iter().enumerate() {
+        let port = format!("out{index}");
+        ports.push(declare("output", widths[gate].1[0], &port));
+        named.insert((*gate, 0), port);
+    }
+    if top {
+        for gate in &board.gates {
+            let Some(port) = device_port(&gate.kind, gate.id) else {
+                continue;
+            };
+            let (input_widths, output_widths) = &widths[&gate.id];
+            if output_widths.is_empty() {
+                ports.push(declare("output", input_widths.len(), &port));
+            } else {
+                ports.push(declare("input", 1, &port));
+                named.insert((gate.id, 0), port);
+            }
+        }
+    }
+
+    let mut drivers: BTreeMap<(usize, usize), Vec<(usize, usize)>> = BTreeMap::new();
+    for wire in &board.wires {
+        drivers.entry(wire.input).or_default().push(wire.output);
+    }
+    let resolutions: BTreeMap<(usize, usize), Resolution> = board.nets.iter().copied().collect();
+
+    let net = |output: (usize, usize)| {
+        named
+            .get(&output)
+            .cloned()
+            .unwrap_or_else(|| format!("g{}_o{}", output.0, output.1))
+    };
+    let mut nets = String::new();
+    let mut body = String::new();
+    for gate in &board.gates {
+        let id = gate.id;
+        let kind = gate.kind.as_str();
+        let (input_widths, output_widths) = &widths[&id];
+        if kind == "IN" {
+            continue;
+        }
+
+        for (index, width) in output_widths.iter().enumerate() {
+            if !named.contains_key(&(id, index)) {
+                writeln!(nets, "  {};", declare("wire", *width, &net((id, index)))).unwrap();
+            }
+        }
+
+        // inputs driven by several outputs get a net resolving the drivers
+        let mut input = Vec::with_capacity(input_widths.len());
+        for (index, width) in input_widths.iter().enumerate() {
+            let expression = match drivers.get(&(id, index)).map(Vec::as_slice) {
+                None | Some([]) => {
+                    let inactive =
+                        cell_pins(kind).is_some_and(|(pins, _)| pins[index].contains("set"));
+                    if inactive {
+                        "1'b0".to_string()
+                    } else {
+                        format!("{width}'bz")
+                    }
+                }
+                Some([driver]) => net(*driver),
+                Some(many) => {
+                    let resolved = format!("g{id}_i{index}");
+                    let resolution = resolutions
+                        .get(&(id, index))
+                        .copied()
+                        .unwrap_or(board.default_resolution);
+                    let net_kind = match resolution {
+                        Resolution::WiredOr => "wor",
+                        Resolution::WiredAnd => "wand",
+                        Resolution::Exclusive | Resolution::TriState => "tri",
+                    };
+                    writeln!(nets, "  {};", declare(net_kind, *width, &resolved)).unwrap();
+                    for driver in many {
+                        writeln!(body, "  assign {resolved} = {};", net(*driver)).unwrap();
+                    }
+                    resolved
+                }
+            };
+            input.push(expression);
+        }
+        let output: Vec<String> = (0..output_widths.len())
+            .map(|index| net((id, index)))
+            .collect();
+
+        if let Some(primitive) = primitive(kind) {
+            let array = match output_widths[0] {
+                1 => String::new(),
+                width => format!(" [{}:0]", width - 1),
+            };
+            let pins = output.iter().chain(&input).cloned().collect::<Vec<_>>();
+            writeln!(body, "  {primitive} g{id}{array} ({});", pins.join(", ")).unwrap();
+        } else if let Some((input_pins, output_pins)) = cell_pins(kind) {
+            let pins: Vec<String> = input_pins
+                .iter()
+                .zip(&input)
+                .chain(output_pins.iter().zip(&output))
+                .map(|(pin, net)| format!(".{pin}({net})"))
+                .collect();
+            let cell = format!("logic_sim_{}", kind.to_lowercase());
+            writeln!(body, "  {cell} g{id} ({});", pins.join(", ")).unwrap();
+        } else {
+            match kind {
+                "HIGH" => writeln!(body, "  assign {} = 1'b1;", output[0]).unwrap(),
+                "LOW" => writeln!(body, "  assign {} = 1'b0;", output[0]).unwrap(),
+                "OUT" => writeln!(body, "  assign {} = {};", output[0], input[0]).unwrap(),
+                "SPLIT" => {
+                    // literals cannot be sliced, the input gets its own net
+                    let whole = format!("g{id}_i0");
+                    let width = input_widths[0];
+                    writeln!(nets, "  {};", declare("wire", width, &whole)).unwrap();
+                    writeln!(body, "  assign {whole} = {};", input[0]).unwrap();
+                    let mut low = 0;
+                    for (part, width) in output.iter().zip(output_widths) {
+                        let bits = match width {
+                            1 => format!("{low}"),
+                            _ => format!("{}:{low}", low + width - 1),
+                        };
+                        writeln!(body, "  assign {part} = {whole}[{bits}];").unwrap();
+                        low += width;
+                    }
+                }
+                "LUT" => {
+                    // the table shifted by the inputs has the output as its
+                    // lowest bit
+                    let rows = 1 << input.len();
+                    let table = gate.params.get(1).copied().unwrap_or(0);
+                    let bits: Vec<&str> = input.iter().rev().map(String::as_str).collect();
+                    writeln!(
+                        body,
+                        "  assign {} = {rows}'h{table:x} >> {{{}}};",
+                        output[0],
+                        bits.join(", ")
+                    )
+                    .unwrap();
+                }
+                "MERGE" => {
+                    let parts: Vec<&str> = input.iter().rev().map(String::as_str).collect();
+                    writeln!(body, "  assign {} = {{{}}};", output[0], parts.join(", ")).unwrap();
+                }
+                "SW" | "BTN" | "CLK" if top => {}
+                "SW" | "BTN" => writeln!(body, "  assign {} = 1'b0;", output[0]).unwrap(),
+                "CLK" => return Err(FileError(format!("clock {id} cannot be exported"))),
+                "LED" | "7SEG" | "HEX" if top => {
+                    let port = device_port(kind, id).unwrap();
+                    let bits: Vec<&str> = input.iter().rev().map(String::as_str).collect();
+                    let value = match bits[..] {
+                        [bit] => bit.to_string(),
+                        _ => format!("{{{}}}", bits.join(", ")),
+                    };
+                    writeln!(body, "  assign {port} = {value};").unwrap();
+                }
+                "LED" | "7SEG" | "HEX" => {}
+                circuit => {
+                    let pins: Vec<String> = input
+                        .iter()
+                        .enumerate()
+                        .map(|(index, net)| format!(".in{index}({net})"))
+                        .chain(
+                            output
+                                .iter()
+                                .enumerate()
+                                .map(|(index, net)| format!(".out{index}({net})")),
+                        )
+                        .collect();
+                    writeln!(body, "  {}g{id} ({});", spaced(circuit), pins.join(", ")).unwrap();
+                }
+            }
+        }
+    }
+    let mut out = format!("module {}(", spaced(name));
+    if !ports.is_empty() {
+        write!(out, "\n  {}\n", ports.join(",\n  ")).unwrap();
+    }
+    write!(out, ");\n{nets}{body}endmodule\n").unwrap();
+    Ok(out)
+}
+
+/// Identifier for a name followed by a space, escaped identifiers already
+/// end with one.
+fn spaced(name: &str) -> String {
+    let name = identifier(name);
+    if name.ends_with(' ') {
+        name
+    } else {
+        name + " "
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD: &str = "logic-sim 3
+circuit INV
+gate 0 IN 0 0 1 1
+gate 1 NOT 100 0 1
+gate 2 OUT 200 0 1 1
+wire 0.0 1.0
+wire 1.0 2.0
+end
+gate 0 IN 0 0 1 4
+gate 1 SPLIT 100 0 1 2 2
+gate 2 AND 200 0 1 2
+gate 3 INV 300 100 1
+gate 4 SW 0 100 1
+gate 5 DFF 400 100 1
+gate 6 LED 500 100 1
+gate 7 OUT 500 0 1 2
+gate 8 HIGH 400 200 1
+wire 0.0 1.0
+wire 1.0 2.0
+wire 1.1 2.1
+wire 2.0 7.0
+wire 4.0 3.0
+wire 3.0 5.0
+wire 4.0 5.1
+wire 5.0 6.0
+wire 8.0 6.0
+net 6.0 wired-or
+";
+
+    #[test]
+    fn board_is_written_as_module() {
+        let file = BoardFile::parse(BOARD).unwrap();
+        let verilog = export(&file, "top").unwrap();
+        assert!(verilog.contains("module logic_sim_dff "));
+        assert!(!verilog.contains("module logic_sim_tff "));
+
+        let inv = "module INV (
+  input in0,
+  output out0
+);
+  wire g1_o0;
+  not g1 (g1_o0, in0);
+  assign out0 = g1_o0;
+endmodule
+";
+        let top = "module top (
+  input [3:0] in0,
+  output [1:0] out0,
+  input sw_4,
+  output led_6
+);
+  wire [1:0] g1_o0;
+  wire [1:0] g1_o1;
+  wire [3:0] g1_i0;
+  wire [1:0] g2_o0;
+  wire g3_o0;
+  wire g5_o0;
+  wire g5_o1;
+  wor g6_i0;
+  wire g8_o0;
+  assign g1_i0 = in0;
+  assign g1_o0 = g1_i0[1:0];
+  assign g1_o1 = g1_i0[3:2];
+  and g2 [1:0] (g2_o0, g1_o0, g1_o1);
+  INV g3 (.in0(sw_4), .out0(g3_o0));
+  logic_sim_dff g5 (.d(g3_o0), .clk(sw_4), .set(1'b0), .reset(1'b0), .q(g5_o0), .qn(g5_o1));
+  assign g6_i0 = g5_o0;
+  assign g6_i0 = g8_o0;
+  assign led_6 = g6_i0;
+  assign out0 = g2_o0;
+  assign g8_o0 = 1'b1;
+endmodule
+";
+        // subcircuits are written before the boards using them
+        assert!(verilog.ends_with(&format!("{inv}\n{top}")));
+    }
+
+    #[test]
+    fn clocks_inside_subcircuits_are_rejected() {
+        let file = BoardFile::parse(
+            "logic-sim 3
+circuit TICK
+gate 0 CLK 0 0 1 10 50 0
+gate 1 OUT 100 0 1 1
+wire 0.0 1.0
+end
+gate 0 TICK 0 0 1",
+        )
+        .unwrap();
+        assert_eq!(
+            export(&file, "top"),
+            Err(FileError(
+                "circuit TICK: clock 0 cannot be exported".to_string()
+            ))
+        );
+        assert_eq!(identifier("7SEG"), "\\7SEG ");
+    }
+
+    #[test]
+    fn keywords_are_escaped() {
+        let file = BoardFile::parse(
+            "logic-sim 3
+circuit and
+gate 0 IN 0 0 1 1
+gate 1 OUT 100 0 1 1
+wire 0.0 1.0
+end
+gate 0 and 0 0 1",
+        )
+        .unwrap();
+        let verilog = export(&file, "module").unwrap();
+        assert!(verilog.contains("module \\and (\n  input in0,"));
+        assert!(verilog.contains("module \\module ();\n"));
+        assert!(verilog.contains("  \\and g0 (.in0(1'bz), .out0(g0_o0));\n"));
+        assert_eq!(identifier("wire"), "\\wire ");
+        assert_eq!(identifier("wires"), "wires");
+    }
+}
@@ -0,0 +1,219 @@
+//! Recorded history of selected signals of a
+//! [`LogicSimulation`](crate::logic_simulation::LogicSimulation).
+//!
+//! The simulation samples every recorded pin once per step into a trace,
+//! traces keep a bounded number of the latest samples and drop the oldest
+//! ones as new samples come in.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{
+    bus::Bus,
+    id::{GateId, InputPin, OutputPin},
+};
+
+/// Number of samples kept by default in each trace.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// Pin whose value is recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Probe {
+    Input(InputPin),
+    Output(OutputPin),
+}
+
+impl Probe {
+    pub fn gate(self) -> GateId {
+        match self {
+            Probe::Input(input) => input.gate,
+            Probe::Output(output) => output.gate,
+        }
+    }
+}
+
+/// Values of a pin at consecutive times.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace {
+    /// Time of the oldest kept sample
+    start: u64,
+    samples: VecDeque<Bus>,
+}
+
+impl Trace {
+    fn new() -> Trace {
+        Trace {
+            start: 0,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Time of the oldest kept sample.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Time after the newest sample.
+    pub fn end(&self) -> u64 {
+        self.start + self.samples.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Value of the pin at given time, if it was recorded and is still kept.
+    pub fn value_at(&self, time: u64) -> Option<Bus> {
+        let index = time.checked_sub(self.start)?;
+        self.samples.get(usize::try_from(index).ok()?).copied()
+    }
+
+    /// Kept samples with their times, from the oldest.
+    pub fn samples(&self) -> impl Iterator<Item = (u64, Bus)> + '_ {
+        (self.start..).zip(self.samples.iter().copied())
+    }
+
+    /// Adds the value at given time, a sample that does not directly follow
+    /// the newest one starts the trace over.
+    fn push(&mut self, time: u64, value: Bus, capacity: usize) {
+        if self.is_empty() || time != self.end() {
+            self.samples.clear();
+            self.start = time;
+        }
+        self.samples.push_back(value);
+        self.truncate(capacity);
+    }
+
+    fn truncate(&mut self, capacity: usize) {
+        while self.samples.len() > capacity {
+            self.samples.pop_front();
+            self.start += 1;
+        }
+    }
+}
+
+/// Traces of all recorded pins.
+pub struct Waveforms {
+    capacity: usize,
+    traces: BTreeMap<Probe, Trace>,
+}
+
+impl Default for Waveforms {
+    fn default() -> Self {
+        Waveforms::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Waveforms {
+    /// Creates waveforms keeping at most given number of samples per trace.
+    pub fn new(capacity: usize) -> Waveforms {
+        Waveforms {
+            capacity,
+            traces: BTreeMap::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Changes the number of kept samples, the oldest samples are dropped.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        for trace in self.traces.values_mut() {
+            trace.truncate(capacity);
+        }
+    }
+
+    pub fn is_recorded(&self, probe: Probe) -> bool {
+        self.traces.contains_key(&probe)
+    }
+
+    pub fn trace(&self, probe: Probe) -> Option<&Trace> {
+        self.traces.get(&probe)
+    }
+
+    /// Recorded pins with their traces, ordered by gate and pin.
+    pub fn traces(&self) -> impl Iterator<Item = (Probe, &Trace)> + '_ {
+        self.traces.iter().map(|(probe, trace)| (*probe, trace))
+    }
+
+    /// Latest time recorded in any trace.
+    pub fn last_time(&self) -> Option<u64> {
+        self.traces
+            .values()
+            .filter(|trace| !trace.is_empty())
+            .map(|trace| trace.end() - 1)
+            .max()
+    }
+
+    /// Starts recording a pin, returns whether it was not recorded before.
+    pub(crate) fn add(&mut self, probe: Probe) -> bool {
+        if self.is_recorded(probe) {
+            return false;
+        }
+        self.traces.insert(probe, Trace::new());
+        true
+    }
+
+    /// Stops recording a pin, returns whether it was recorded.
+    pub(crate) fn remove(&mut self, probe: Probe) -> bool {
+        self.traces.remove(&probe).is_some()
+    }
+
+    /// Stops recording the pins of a gate.
+    pub(crate) fn remove_gate(&mut self, gate: GateId) {
+        self.traces.retain(|probe, _| probe.gate() != gate);
+    }
+
+    pub(crate) fn probes(&self) -> impl Iterator<Item = Probe> + '_ {
+        self.traces.keys().copied()
+    }
+
+    pub(crate) fn push(&mut self, probe: Probe, time: u64, value: Bus) {
+        if let Some(trace) = self.traces.get_mut(&probe) {
+            trace.push(time, value, self.capacity);
+        }
+    }
+
+    /// Drops all samples and keeps recording the same pins.
+    pub(crate) fn clear(&mut self) {
+        for trace in self.traces.values_mut() {
+            *trace = Trace::new();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gates::Not, logic_simulation::LogicSimulation};
+
+    #[test]
+    fn traces_keep_the_latest_samples() {
+        let mut sim = LogicSimulation::new();
+        let probe = Probe::Output(sim.add_gate(Not).output(0));
+        let mut waveforms = Waveforms::new(3);
+        assert!(waveforms.add(probe));
+        assert!(!waveforms.add(probe));
+
+        for time in 0..5 {
+            waveforms.push(probe, time, Bus::from_u64(8, time));
+        }
+        let trace = waveforms.trace(probe).unwrap();
+        assert_eq!((trace.start(), trace.end()), (2, 5));
+        assert_eq!(trace.value_at(1), None);
+        assert_eq!(trace.value_at(4), Some(Bus::from_u64(8, 4)));
+        assert_eq!(waveforms.last_time(), Some(4));
+
+        waveforms.set_capacity(1);
+        let trace = waveforms.trace(probe).unwrap();
+        assert_eq!(
+            trace.samples().collect::<Vec<_>>(),
+            [(4, Bus::from_u64(8, 4))]
+        );
+
+        // a gap in time starts the trace over
+        waveforms.push(probe, 9, Bus::from_u64(8, 9));
+        assert_eq!(waveforms.trace(probe).unwrap().start(), 9);
+    }
+}